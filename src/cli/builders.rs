@@ -3,9 +3,19 @@ use std::path::Path;
 use anyhow::{Ok, Result};
 
 use crate::{
-    cli::{Cli, Commands, client::send_watch_request, stats::interface::display_stats_interface},
-    config::parser::load_config,
+    cli::{
+        Cli, Commands,
+        client::send_watch_request,
+        graph::{GraphFormat, render},
+        stats::interface::display_stats_interface,
+    },
+    config::{
+        parser::{load_config, read_config},
+        validate::validate,
+    },
+    core::state::get_id_by_name,
     daemon::server::DaemonRequest,
+    exec::metrics::ExecMetrics,
     git::{remote::branch_wildcard, repo::Repo},
 };
 
@@ -22,7 +32,13 @@ pub async fn build_watch_request(cli: &Cli) -> Result<DaemonRequest> {
     match &cli.command {
         Commands::Watch => build_add_watch_request(),
         Commands::Ps { all } => Ok(DaemonRequest::ListWatches { all: *all }),
-        Commands::Logs { id_or_name, follow } => build_logs_request(id_or_name, *follow),
+        Commands::Logs {
+            id_or_name,
+            follow,
+            run,
+            job,
+        } => build_logs_request(id_or_name, *follow, run, job),
+        Commands::Cancel { id } => Ok(DaemonRequest::CancelRun { id: id.to_string() }),
         Commands::Stop { id } => Ok(DaemonRequest::StopWatch { id: id.to_string() }),
         Commands::Up { id } => Ok(DaemonRequest::UpWatch { id: id.to_string() }),
         Commands::Rm { id } => Ok(DaemonRequest::RmWatch { id: id.to_string() }),
@@ -31,9 +47,71 @@ pub async fn build_watch_request(cli: &Cli) -> Result<DaemonRequest> {
             Ok(DaemonRequest::None)
         }
         Commands::Run { id } => Ok(DaemonRequest::RunPipeline { id: id.clone() }),
+        Commands::History { id_or_name } => build_history_request(id_or_name),
+        Commands::Show { run_id } => Ok(DaemonRequest::GetRun {
+            run_id: run_id.clone(),
+        }),
+        Commands::Artifacts {
+            run_id,
+            job,
+            extract,
+        } => Ok(DaemonRequest::ListArtifacts {
+            run_id: run_id.clone(),
+            job: job.clone(),
+            extract: extract.clone(),
+        }),
+        Commands::Graph { format, status } => {
+            print_graph(*format, *status).await?;
+            Ok(DaemonRequest::None)
+        }
+        Commands::Validate { path } => {
+            validate_config(path.as_deref().unwrap_or("./fleet.yml"));
+            Ok(DaemonRequest::None)
+        }
     }
 }
 
+/// Prints every problem of the config at `path`, then exits with 0 when it is valid,
+/// 1 when it has problems and 2 when it cannot be read.
+fn validate_config(path: &str) {
+    let problems = validate(Path::new(path)).unwrap_or_else(|e| {
+        eprintln!("❌ Error: {e:#}");
+        std::process::exit(2);
+    });
+    if problems.is_empty() {
+        println!("✅ {path} is valid");
+        std::process::exit(0);
+    }
+    for problem in &problems {
+        println!("{problem}");
+    }
+    eprintln!("❌ {} problem(s) found in {path}", problems.len());
+    std::process::exit(1);
+}
+
+/// Prints the job graph of `./fleet.yml`, with the results of the last run of the project when `status` is set.
+async fn print_graph(format: GraphFormat, status: bool) -> Result<()> {
+    let config = read_config(Path::new("./fleet.yml"))?;
+    let last_run = match status {
+        true => last_run().await.unwrap_or_else(|e| {
+            eprintln!("No run to annotate the graph with: {e}");
+            None
+        }),
+        false => None,
+    };
+    print!("{}", render(&config, format, last_run.as_ref())?);
+    Ok(())
+}
+
+/// The last recorded run of the project of the current directory
+async fn last_run() -> Result<Option<ExecMetrics>> {
+    let name = Repo::default_build()?.name;
+    let id = get_id_by_name(&name)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("project '{name}' is not watched"))?;
+    Ok(ExecMetrics::load_history(&id).await?.pop())
+}
+
 /// Builds an [`AddWatch`] request after validating configuration.
 fn build_add_watch_request() -> Result<DaemonRequest> {
     let config_path = Path::new("./fleet.yml");
@@ -74,18 +152,31 @@ fn build_add_watch_request() -> Result<DaemonRequest> {
 }
 
 /// Builds a [`LogsWatches`] request from CLI or repository defaults.
-fn build_logs_request(id_or_name: &Option<String>, follow: bool) -> Result<DaemonRequest> {
+fn build_logs_request(
+    id_or_name: &Option<String>,
+    follow: bool,
+    run: &Option<String>,
+    job: &Option<String>,
+) -> Result<DaemonRequest> {
+    let id = match id_or_name {
+        Some(s) => s.to_string(),
+        None => Repo::default_build()?.name,
+    };
+    Ok(DaemonRequest::LogsWatches {
+        id,
+        f: follow,
+        run: run.clone(),
+        job: job.clone(),
+    })
+}
+
+/// Builds a [`ListRuns`] request from CLI or repository defaults.
+fn build_history_request(id_or_name: &Option<String>) -> Result<DaemonRequest> {
     match id_or_name {
-        Some(s) => Ok(DaemonRequest::LogsWatches {
-            id: s.to_string(),
-            f: follow,
-        }),
+        Some(s) => Ok(DaemonRequest::ListRuns { id: s.to_string() }),
         None => {
             let repo = Repo::default_build()?;
-            Ok(DaemonRequest::LogsWatches {
-                id: repo.name,
-                f: follow,
-            })
+            Ok(DaemonRequest::ListRuns { id: repo.name })
         }
     }
 }
@@ -1,13 +1,19 @@
 use std::{fs::File, io::Read, path::PathBuf, thread, time::Duration};
 
 use anyhow::Result;
+use chrono::Local;
 use std::io::BufRead;
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
     net::UnixStream,
 };
 
-use crate::daemon::server::{DaemonRequest, DaemonResponse, WatchInfo};
+use crate::{
+    core::id::format_commit,
+    daemon::server::{DaemonRequest, DaemonResponse, JobInfo, RunInfo, WatchInfo},
+    exec::{artifacts::ArtifactInfo, metrics::FailureReason},
+    log::logger::Logger,
+};
 
 pub async fn send_watch_request(req: DaemonRequest) -> Result<(), anyhow::Error> {
     if let DaemonRequest::None = req {
@@ -49,8 +55,31 @@ fn handle_daemon_response(response: DaemonResponse) -> Result<()> {
         DaemonResponse::ListWatches(watches) => {
             print_watches_table(&watches);
         }
-        DaemonResponse::LogWatch(p, f) => {
-            display_logs(&p, f)?;
+        DaemonResponse::LogWatch(paths, f) => match paths.as_slice() {
+            [p] => display_logs(p, f)?,
+            _ if f => {
+                return Err(anyhow::anyhow!(
+                    "Can't follow several job logs at once, select one with --job"
+                ));
+            }
+            _ => {
+                for p in &paths {
+                    println!("==> {p} <==");
+                    display_logs(p, false)?;
+                }
+            }
+        },
+        DaemonResponse::ListRuns(runs) => {
+            print_runs_table(&runs);
+        }
+        DaemonResponse::ShowRun(run, jobs) => {
+            print_run_details(&run, &jobs);
+        }
+        DaemonResponse::ListArtifacts(artifacts, None) => {
+            print_artifacts_table(&artifacts);
+        }
+        DaemonResponse::ListArtifacts(artifacts, Some(dir)) => {
+            extract_artifacts(&artifacts, &dir)?;
         }
         DaemonResponse::None | DaemonResponse::Ignore => {}
     }
@@ -64,6 +93,8 @@ fn display_logs(path: &str, follow: bool) -> Result<()> {
     }
     let file = File::open(log_path)?;
     let mut reader = std::io::BufReader::new(file);
+    // keep colours for terminals only, pipes and files get plain text
+    let color = atty::is(atty::Stream::Stdout);
 
     match follow {
         true => loop {
@@ -73,7 +104,7 @@ fn display_logs(path: &str, follow: bool) -> Result<()> {
                     thread::sleep(Duration::from_millis(200));
                 }
                 Ok(_) => {
-                    print!("{buffer}");
+                    println!("{}", Logger::render_line(buffer.trim_end(), color));
                 }
                 Err(e) => return Err(anyhow::anyhow!("Failed to read: {e}")),
             }
@@ -81,7 +112,9 @@ fn display_logs(path: &str, follow: bool) -> Result<()> {
         false => {
             let mut buffer = String::new();
             reader.read_to_string(&mut buffer)?;
-            println!("{buffer}");
+            for line in buffer.lines() {
+                println!("{}", Logger::render_line(line, color));
+            }
             Ok(())
         }
     }
@@ -90,18 +123,133 @@ fn display_logs(path: &str, follow: bool) -> Result<()> {
 /// Prints a formatted table of active watches.
 fn print_watches_table(watches: &[WatchInfo]) {
     println!(
-        "{:<13} {:<10} {:<13} {:<12} {:<20} {:<30}",
-        "PROJECT ID", "NAME", "BRANCH", "COMMIT", "REMOTE URL", "DIR"
+        "{:<13} {:<10} {:<13} {:<12} {:<20} {:<20} {:<30} {:<30}",
+        "PROJECT ID", "NAME", "BRANCH", "COMMIT", "REMOTE URL", "RUN", "CONCURRENCY", "DIR"
     );
     for w in watches {
         println!(
-            "{:<13} {:<10} {:<13} {:<12} {:<20} {:<30}",
+            "{:<13} {:<10} {:<13} {:<12} {:<20} {:<20} {:<30} {:<30}",
             w.id.to_string(),
             w.repo_name,
             w.branch,
             w.short_commit,
             w.short_url,
+            format_run_state(w),
+            format_concurrency(w),
             w.project_dir
         );
     }
 }
+
+/// e.g. `running (2 queued)`
+fn format_run_state(w: &WatchInfo) -> String {
+    let state = match (w.running, w.pending) {
+        (true, _) => "running",
+        (false, true) => "pending",
+        (false, false) => "idle",
+    };
+    match w.waiting {
+        0 => state.to_string(),
+        n => format!("{state} ({n} queued)"),
+    }
+}
+
+/// policy of the project and the last decision it took, e.g. `skip (last: skipped)`
+fn format_concurrency(w: &WatchInfo) -> String {
+    match w.last_decision {
+        Some(decision) => format!("{} (last: {decision})", w.concurrency),
+        None => w.concurrency.to_string(),
+    }
+}
+
+pub fn format_duration(duration_ms: Option<u128>) -> String {
+    match duration_ms {
+        Some(ms) => format!("{:.2}s", ms as f64 / 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+/// Prints a formatted table of the past runs of a project.
+fn print_runs_table(runs: &[RunInfo]) {
+    println!(
+        "{:<13} {:<11} {:<20} {:<8} {:<10} {:<20} {:<10}",
+        "RUN ID", "COMMIT", "BRANCH", "TRIGGER", "STATUS", "STARTED", "DURATION"
+    );
+    for r in runs {
+        println!(
+            "{:<13} {:<11} {:<20} {:<8} {:<10} {:<20} {:<10}",
+            r.run_id,
+            format_commit(&r.commit),
+            r.branch,
+            r.trigger.to_string(),
+            r.status.to_string(),
+            r.started_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+            format_duration(r.duration_ms)
+        );
+    }
+}
+
+/// Prints a formatted table of the artifacts of a run.
+fn print_artifacts_table(artifacts: &[ArtifactInfo]) {
+    if artifacts.is_empty() {
+        println!("No artifacts for this run");
+        return;
+    }
+    println!("{:<20} {:<50} {:<10}", "JOB", "PATH", "SIZE (b)");
+    for a in artifacts {
+        println!("{:<20} {:<50} {:<10}", a.job, a.path, a.size);
+    }
+}
+
+/// Copies the artifacts of a run to `<dir>/<job>/<path>`.
+fn extract_artifacts(artifacts: &[ArtifactInfo], dir: &str) -> Result<()> {
+    for a in artifacts {
+        let dest = PathBuf::from(dir).join(&a.job).join(&a.path);
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&a.stored_at, &dest)?;
+    }
+    println!("✅ {} file(s) extracted to {dir}", artifacts.len());
+    Ok(())
+}
+
+/// Prints a run summary followed by the breakdown of its jobs.
+fn print_run_details(run: &RunInfo, jobs: &[JobInfo]) {
+    println!("Run:      {}", run.run_id);
+    println!("Project:  {}", run.project_name);
+    println!("Commit:   {}", run.commit);
+    println!("Branch:   {}", run.branch);
+    println!("Trigger:  {}", run.trigger);
+    println!("Status:   {}", run.status);
+    println!(
+        "Started:  {}",
+        run.started_at
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("Duration: {}", format_duration(run.duration_ms));
+    println!();
+    println!(
+        "{:<20} {:<24} {:<10} {:<10} {:<10} {:<8}",
+        "JOB", "STATUS", "DURATION", "CPU", "MEM (Kb)", "RETRIES"
+    );
+    for j in jobs {
+        let status = match j.reason {
+            Some(FailureReason::Timeout) => format!("{} (timeout)", j.status),
+            _ => j.status.to_string(),
+        };
+        println!(
+            "{:<20} {:<24} {:<10} {:<10} {:<10} {:<8}",
+            j.name,
+            status,
+            format_duration(j.duration_ms),
+            format!("{:.1}%", j.cpu_usage),
+            j.mem_usage_kb,
+            j.retries
+        );
+    }
+}
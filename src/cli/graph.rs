@@ -0,0 +1,246 @@
+//! `fleet graph`: the jobs of a config and their dependencies, as the scheduler sees them.
+//!
+//! The graph comes from `build_dependency_graph`, so a `pipe` shows as the edge it adds to the
+//! dependencies of its job, drawn apart from the `needs` ones. With the last run of the project,
+//! every job it ran is annotated with its status and duration.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::{
+    cli::client::format_duration,
+    config::ProjectConfig,
+    exec::{
+        metrics::{ExecMetrics, JobStatus},
+        runner::build_dependency_graph,
+    },
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// the jobs stage by stage, each one with the jobs it waits for
+    #[default]
+    Ascii,
+    /// Graphviz, e.g. `fleet graph --format dot | dot -Tsvg > graph.svg`
+    Dot,
+    /// a Mermaid flowchart, rendered by GitHub in markdown
+    Mermaid,
+}
+
+/// An edge of the graph, `from` runs before `to`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    /// added by the `pipe` of `to`, the output of `from` is its input
+    pub pipe: bool,
+}
+
+/// The jobs of `config` in stages: a job is one stage after the last of the jobs it waits for.
+/// Each stage is sorted by name, and so are the edges, by job then dependency.
+pub fn stages(config: &ProjectConfig) -> Result<(Vec<Vec<String>>, Vec<Edge>)> {
+    let graph = build_dependency_graph(config)?;
+    let mut names: Vec<&String> = graph.keys().collect();
+    names.sort();
+
+    let mut edges = Vec::new();
+    for name in &names {
+        let node = &graph[*name];
+        let mut deps = node.depend_on.clone();
+        deps.sort();
+        for dep in deps {
+            let pipe = node.job.pipe == dep;
+            edges.push(Edge {
+                from: dep,
+                to: name.to_string(),
+                pipe,
+            });
+        }
+    }
+
+    let mut stage_of: HashMap<&String, usize> = HashMap::new();
+    while stage_of.len() < names.len() {
+        let ready: Vec<(&String, usize)> = names
+            .iter()
+            .filter(|n| !stage_of.contains_key(*n))
+            .filter_map(|n| {
+                let deps: Option<Vec<usize>> = graph[*n]
+                    .depend_on
+                    .iter()
+                    .map(|d| stage_of.get(d).copied())
+                    .collect();
+                deps.map(|d| (*n, d.iter().map(|s| s + 1).max().unwrap_or(0)))
+            })
+            .collect();
+        if ready.is_empty() {
+            anyhow::bail!("The dependencies of the jobs form a cycle");
+        }
+        stage_of.extend(ready);
+    }
+
+    let mut stages = vec![Vec::new(); stage_of.values().map(|s| s + 1).max().unwrap_or(0)];
+    for name in names {
+        stages[stage_of[name]].push(name.clone());
+    }
+    Ok((stages, edges))
+}
+
+/// The graph of `config` in `format`, its jobs annotated with their result in `last_run`
+pub fn render(
+    config: &ProjectConfig,
+    format: GraphFormat,
+    last_run: Option<&ExecMetrics>,
+) -> Result<String> {
+    let (stages, edges) = stages(config)?;
+    let annotation = |name: &str| {
+        last_run.and_then(|run| run.jobs.get(name)).map(|job| {
+            (
+                job.status.clone(),
+                format!("{}, {}", job.status, format_duration(job.duration_ms)),
+            )
+        })
+    };
+    Ok(match format {
+        GraphFormat::Ascii => ascii(&stages, &edges, annotation),
+        GraphFormat::Dot => dot(&stages, &edges, annotation),
+        GraphFormat::Mermaid => mermaid(&stages, &edges, annotation),
+    })
+}
+
+type Annotation = Option<(JobStatus, String)>;
+
+fn ascii(
+    stages: &[Vec<String>],
+    edges: &[Edge],
+    annotation: impl Fn(&str) -> Annotation,
+) -> String {
+    let mut out = String::new();
+    for (i, stage) in stages.iter().enumerate() {
+        out.push_str(&format!("stage {}\n", i + 1));
+        for name in stage {
+            let mut line = format!("  {name}");
+            if let Some((_, label)) = annotation(name) {
+                line.push_str(&format!(" [{label}]"));
+            }
+            let deps: Vec<String> = edges
+                .iter()
+                .filter(|e| e.to == *name)
+                .map(|e| match e.pipe {
+                    true => format!("{} (pipe)", e.from),
+                    false => e.from.clone(),
+                })
+                .collect();
+            if !deps.is_empty() {
+                line.push_str(&format!(" <- {}", deps.join(", ")));
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn dot(stages: &[Vec<String>], edges: &[Edge], annotation: impl Fn(&str) -> Annotation) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let quote = |s: &str| format!("\"{}\"", escape(s));
+    let mut out = String::from("digraph pipeline {\n  rankdir=LR;\n  node [shape=box];\n");
+    for name in stages.iter().flatten() {
+        match annotation(name) {
+            Some((status, label)) => out.push_str(&format!(
+                "  {} [label=\"{}\\n{}\", color={}];\n",
+                quote(name),
+                escape(name),
+                escape(&label),
+                dot_color(&status)
+            )),
+            None => out.push_str(&format!("  {};\n", quote(name))),
+        }
+    }
+    for edge in edges {
+        let style = if edge.pipe {
+            " [style=dashed, label=\"pipe\"]"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "  {} -> {}{style};\n",
+            quote(&edge.from),
+            quote(&edge.to)
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn dot_color(status: &JobStatus) -> &'static str {
+    match status {
+        JobStatus::Succeeded => "green",
+        JobStatus::SucceededWithWarnings => "orange",
+        JobStatus::Failed => "red",
+        JobStatus::Pending | JobStatus::Running => "blue",
+        JobStatus::Skipped | JobStatus::Cancelled => "gray",
+    }
+}
+
+fn mermaid(
+    stages: &[Vec<String>],
+    edges: &[Edge],
+    annotation: impl Fn(&str) -> Annotation,
+) -> String {
+    // job names may hold spaces and parentheses, the nodes are named by position
+    let ids: HashMap<&String, String> = stages
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, name)| (name, format!("job{i}")))
+        .collect();
+    let escape = |s: &str| s.replace('"', "#quot;");
+    let mut out = String::from("flowchart LR\n");
+    let mut classes = HashSet::new();
+    for name in stages.iter().flatten() {
+        match annotation(name) {
+            Some((status, label)) => {
+                let class = mermaid_class(&status);
+                classes.insert(class);
+                out.push_str(&format!(
+                    "  {}[\"{}<br/>{}\"]:::{class}\n",
+                    ids[name],
+                    escape(name),
+                    escape(&label)
+                ));
+            }
+            None => out.push_str(&format!("  {}[\"{}\"]\n", ids[name], escape(name))),
+        }
+    }
+    for edge in edges {
+        let arrow = if edge.pipe { "-. pipe .->" } else { "-->" };
+        out.push_str(&format!(
+            "  {} {arrow} {}\n",
+            ids[&edge.from], ids[&edge.to]
+        ));
+    }
+    for (class, style) in [
+        ("succeeded", "fill:#d4f4dd,stroke:#2ecc71"),
+        ("warning", "fill:#fdebd0,stroke:#e67e22"),
+        ("failed", "fill:#fadbd8,stroke:#e74c3c"),
+        ("running", "fill:#d6eaf8,stroke:#3498db"),
+        ("skipped", "fill:#eaeded,stroke:#95a5a6"),
+    ] {
+        if classes.contains(class) {
+            out.push_str(&format!("  classDef {class} {style}\n"));
+        }
+    }
+    out
+}
+
+fn mermaid_class(status: &JobStatus) -> &'static str {
+    match status {
+        JobStatus::Succeeded => "succeeded",
+        JobStatus::SucceededWithWarnings => "warning",
+        JobStatus::Failed => "failed",
+        JobStatus::Pending | JobStatus::Running => "running",
+        JobStatus::Skipped | JobStatus::Cancelled => "skipped",
+    }
+}
@@ -2,10 +2,13 @@
 
 pub mod builders;
 pub mod client;
+pub mod graph;
 pub mod stats;
 
 use clap::{Parser, Subcommand};
 
+use crate::cli::graph::GraphFormat;
+
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
@@ -26,6 +29,11 @@ pub enum Commands {
 
     Stats,
 
+    /// Cancel the running pipeline of a project
+    Cancel {
+        id: String,
+    },
+
     Stop {
         id: String,
     },
@@ -41,6 +49,46 @@ pub enum Commands {
     Logs {
         #[arg(short = 'f', long)]
         follow: bool,
+        /// only show the logs of this run
+        #[arg(long)]
+        run: Option<String>,
+        /// only show the logs of this job (in the latest run if `--run` is not given)
+        #[arg(long)]
+        job: Option<String>,
         id_or_name: Option<String>,
     },
+
+    History {
+        id_or_name: Option<String>,
+    },
+
+    Show {
+        run_id: String,
+    },
+
+    /// List the artifacts kept during a run
+    Artifacts {
+        run_id: String,
+        /// only the artifacts of this job
+        #[arg(long)]
+        job: Option<String>,
+        /// copy the artifacts to this directory, under one directory per job
+        #[arg(long)]
+        extract: Option<String>,
+    },
+
+    /// Check a config without the daemon, exits with 0 when valid, 1 on problems, 2 when unreadable
+    Validate {
+        /// `./fleet.yml` by default
+        path: Option<String>,
+    },
+
+    /// Print the jobs of `./fleet.yml` and their dependencies
+    Graph {
+        #[arg(long, value_enum, default_value_t)]
+        format: GraphFormat,
+        /// annotate the jobs with their status and duration in the last run of the project
+        #[arg(long)]
+        status: bool,
+    },
 }
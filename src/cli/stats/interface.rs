@@ -194,6 +194,12 @@ pub async fn load_all_stats() -> Result<Vec<ProjectStats>> {
         let last_duration = format!("{} ms", last.duration_ms);
         let last_logs = Logger::fetchn(&id, 5)
             .await
+            .map(|lines| {
+                lines
+                    .iter()
+                    .map(|l| Logger::render_line(l, false))
+                    .collect()
+            })
             .unwrap_or_else(|e| vec![format!("Error: {e}")]);
         let avg_mem_kb = runs.iter().map(|r| r.mem_usage_kb).sum::<u64>() / runs_count as u64;
 
@@ -253,21 +259,17 @@ pub async fn display_stats_interface() -> anyhow::Result<()> {
         {
             match key.code {
                 KeyCode::Char('q') | KeyCode::Char('Q') => break,
-                KeyCode::Down => {
-                    if !app.project.is_empty() {
-                        app.selected = (app.selected + 1).min(app.project.len() - 1);
-                        if app.selected >= app.scroll + app.table_height {
-                            app.scroll = app.selected - app.table_height + 1;
-                        }
+                KeyCode::Down if !app.project.is_empty() => {
+                    app.selected = (app.selected + 1).min(app.project.len() - 1);
+                    if app.selected >= app.scroll + app.table_height {
+                        app.scroll = app.selected - app.table_height + 1;
                     }
                 }
-                KeyCode::Up => {
-                    if !app.project.is_empty() && app.selected > 0 {
-                        app.selected -= 1;
+                KeyCode::Up if !app.project.is_empty() && app.selected > 0 => {
+                    app.selected -= 1;
 
-                        if app.selected < app.scroll {
-                            app.scroll = app.selected;
-                        }
+                    if app.selected < app.scroll {
+                        app.scroll = app.selected;
                     }
                 }
                 _ => {}
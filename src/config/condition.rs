@@ -0,0 +1,426 @@
+//! `if:` expressions of the jobs.
+//!
+//! ```text
+//! expr     := and ( "||" and )*
+//! and      := unary ( "&&" unary )*
+//! unary    := "!" unary | compare
+//! compare  := primary ( ( "==" | "!=" ) primary )?
+//! primary  := "(" expr ")" | 'string' | "string" | true | false
+//!           | name "(" [ expr ( "," expr )* ] ")" | name ( "." name )*
+//! ```
+//!
+//! Variables: `branch`, `commit`, `env.NAME`, `jobs.NAME.outputs.KEY` (an output of an upstream job).
+//! The whole expression may be wrapped in `${{ }}`.
+//! Functions: `always()`, `success()`, `failure()`, `changed('glob')`,
+//! `contains(a, b)`, `startsWith(a, b)`, `endsWith(a, b)`.
+//!
+//! An expression without any status function (`always`, `success`, `failure`)
+//! only runs when the upstream jobs succeeded, as if it was `success() && (expr)`.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use globset::Glob;
+
+use crate::config::template::{JobOutputs, job_output};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Bool(bool),
+    Str(String),
+    Var(String),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Eq(Box<Expr>, Box<Expr>),
+    Ne(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Str(s) => !s.is_empty(),
+        }
+    }
+
+    fn as_str(&self) -> String {
+        match self {
+            Value::Bool(b) => b.to_string(),
+            Value::Str(s) => s.clone(),
+        }
+    }
+}
+
+/// Everything an expression can look at when a job is about to be dispatched.
+#[derive(Debug, Default)]
+pub struct EvalContext<'a> {
+    pub branch: &'a str,
+    pub commit: &'a str,
+    pub changed_paths: &'a [String],
+    /// env of the job, looked up before the env of the daemon
+    pub env: Option<&'a HashMap<String, String>>,
+    /// upstream jobs all succeeded
+    pub success: bool,
+    /// an upstream job failed
+    pub failure: bool,
+    /// outputs of the upstream jobs
+    pub outputs: Option<&'a JobOutputs>,
+}
+
+const STATUS_FUNCTIONS: [&str; 3] = ["always", "success", "failure"];
+
+impl Expr {
+    pub fn parse(src: &str) -> Result<Expr> {
+        let src = src.trim();
+        let src = src
+            .strip_prefix("${{")
+            .and_then(|s| s.strip_suffix("}}"))
+            .unwrap_or(src);
+        let tokens = tokenize(src)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if let Some(tok) = parser.peek() {
+            bail!("unexpected `{}` in expression `{src}`", tok.text());
+        }
+        expr.validate()?;
+        Ok(expr)
+    }
+
+    /// Whether the job should run.
+    pub fn eval(&self, ctx: &EvalContext) -> Result<bool> {
+        let value = self.value(ctx)?.truthy();
+        if self.uses_status_function() {
+            Ok(value)
+        } else {
+            Ok(ctx.success && value)
+        }
+    }
+
+    /// Jobs whose outputs the expression reads
+    pub fn referenced_jobs(&self) -> Vec<&str> {
+        match self {
+            Expr::Var(name) => job_output(name).map(|(job, _)| job).into_iter().collect(),
+            Expr::Call(_, args) => args.iter().flat_map(|a| a.referenced_jobs()).collect(),
+            Expr::Not(e) => e.referenced_jobs(),
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Eq(a, b) | Expr::Ne(a, b) => {
+                let mut jobs = a.referenced_jobs();
+                jobs.extend(b.referenced_jobs());
+                jobs
+            }
+            Expr::Bool(_) | Expr::Str(_) => Vec::new(),
+        }
+    }
+
+    fn uses_status_function(&self) -> bool {
+        match self {
+            Expr::Call(name, args) => {
+                STATUS_FUNCTIONS.contains(&name.as_str())
+                    || args.iter().any(|a| a.uses_status_function())
+            }
+            Expr::Not(e) => e.uses_status_function(),
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Eq(a, b) | Expr::Ne(a, b) => {
+                a.uses_status_function() || b.uses_status_function()
+            }
+            Expr::Bool(_) | Expr::Str(_) | Expr::Var(_) => false,
+        }
+    }
+
+    /// unknown variables and functions are rejected when the config is loaded
+    fn validate(&self) -> Result<()> {
+        match self {
+            Expr::Var(name) => {
+                if !(name == "branch"
+                    || name == "commit"
+                    || name.starts_with("env.")
+                    || job_output(name).is_some())
+                {
+                    bail!("unknown variable `{name}`");
+                }
+            }
+            Expr::Call(name, args) => {
+                let arity = match name.as_str() {
+                    "always" | "success" | "failure" => 0,
+                    "changed" => 1,
+                    "contains" | "startsWith" | "endsWith" => 2,
+                    _ => bail!("unknown function `{name}()`"),
+                };
+                if args.len() != arity {
+                    bail!("`{name}()` takes {arity} argument(s), got {}", args.len());
+                }
+                if name == "changed"
+                    && let Expr::Str(pattern) = &args[0]
+                {
+                    Glob::new(pattern)?;
+                }
+                for a in args {
+                    a.validate()?;
+                }
+            }
+            Expr::Not(e) => e.validate()?,
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Eq(a, b) | Expr::Ne(a, b) => {
+                a.validate()?;
+                b.validate()?;
+            }
+            Expr::Bool(_) | Expr::Str(_) => {}
+        }
+        Ok(())
+    }
+
+    fn value(&self, ctx: &EvalContext) -> Result<Value> {
+        Ok(match self {
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Str(s) => Value::Str(s.clone()),
+            Expr::Var(name) => Value::Str(variable(name, ctx)),
+            Expr::Not(e) => Value::Bool(!e.value(ctx)?.truthy()),
+            Expr::And(a, b) => Value::Bool(a.value(ctx)?.truthy() && b.value(ctx)?.truthy()),
+            Expr::Or(a, b) => Value::Bool(a.value(ctx)?.truthy() || b.value(ctx)?.truthy()),
+            Expr::Eq(a, b) => Value::Bool(a.value(ctx)?.as_str() == b.value(ctx)?.as_str()),
+            Expr::Ne(a, b) => Value::Bool(a.value(ctx)?.as_str() != b.value(ctx)?.as_str()),
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.value(ctx).map(|v| v.as_str()))
+                    .collect::<Result<Vec<_>>>()?;
+                Value::Bool(match name.as_str() {
+                    "always" => true,
+                    "success" => ctx.success,
+                    "failure" => ctx.failure,
+                    "changed" => {
+                        let glob = Glob::new(&args[0])?.compile_matcher();
+                        ctx.changed_paths.iter().any(|p| glob.is_match(p))
+                    }
+                    "contains" => args[0].contains(&args[1]),
+                    "startsWith" => args[0].starts_with(&args[1]),
+                    "endsWith" => args[0].ends_with(&args[1]),
+                    _ => bail!("unknown function `{name}()`"),
+                })
+            }
+        })
+    }
+}
+
+fn variable(name: &str, ctx: &EvalContext) -> String {
+    match name {
+        "branch" => ctx.branch.to_string(),
+        "commit" => ctx.commit.to_string(),
+        _ if let Some((job, key)) = job_output(name) => ctx
+            .outputs
+            .and_then(|outputs| outputs.get(job))
+            .and_then(|o| o.get(key).cloned())
+            .unwrap_or_default(),
+        _ => {
+            let key = name.trim_start_matches("env.");
+            ctx.env
+                .and_then(|env| env.get(key).cloned())
+                .or_else(|| std::env::var(key).ok())
+                .unwrap_or_default()
+        }
+    }
+}
+
+// -----------------------
+// Parsing
+// -----------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Not,
+    And,
+    Or,
+    Eq,
+    Ne,
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Ident(s) => s.clone(),
+            Token::Str(s) => format!("'{s}'"),
+            Token::LParen => "(".into(),
+            Token::RParen => ")".into(),
+            Token::Comma => ",".into(),
+            Token::Not => "!".into(),
+            Token::And => "&&".into(),
+            Token::Or => "||".into(),
+            Token::Eq => "==".into(),
+            Token::Ne => "!=".into(),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            ' ' | '\t' | '\n' | '\r' => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '&' if next == Some('&') => {
+                tokens.push(Token::And);
+                i += 2;
+            }
+            '|' if next == Some('|') => {
+                tokens.push(Token::Or);
+                i += 2;
+            }
+            '=' if next == Some('=') => {
+                tokens.push(Token::Eq);
+                i += 2;
+            }
+            '!' if next == Some('=') => {
+                tokens.push(Token::Ne);
+                i += 2;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&q| q == c)
+                    .ok_or_else(|| anyhow::anyhow!("unterminated string in expression `{src}`"))?;
+                tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 2;
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '-' | '.'))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            _ => bail!("unexpected character `{c}` in expression `{src}`"),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(tok) if tok == expected => Ok(()),
+            Some(tok) => bail!("expected `{}`, found `{}`", expected.text(), tok.text()),
+            None => bail!(
+                "expected `{}` at the end of the expression",
+                expected.text()
+            ),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr> {
+        let left = self.primary()?;
+        match self.peek() {
+            Some(Token::Eq) => {
+                self.next();
+                Ok(Expr::Eq(Box::new(left), Box::new(self.primary()?)))
+            }
+            Some(Token::Ne) => {
+                self.next();
+                Ok(Expr::Ne(Box::new(left), Box::new(self.primary()?)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.or()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Str(s)) => Ok(Expr::Str(s)),
+            Some(Token::Ident(name)) if name == "true" => Ok(Expr::Bool(true)),
+            Some(Token::Ident(name)) if name == "false" => Ok(Expr::Bool(false)),
+            Some(Token::Ident(name)) => {
+                if self.peek() != Some(&Token::LParen) {
+                    return Ok(Expr::Var(name));
+                }
+                self.next();
+                let mut args = Vec::new();
+                if self.peek() != Some(&Token::RParen) {
+                    args.push(self.or()?);
+                    while self.peek() == Some(&Token::Comma) {
+                        self.next();
+                        args.push(self.or()?);
+                    }
+                }
+                self.expect(Token::RParen)?;
+                Ok(Expr::Call(name, args))
+            }
+            Some(tok) => bail!("unexpected `{}` in expression", tok.text()),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+}
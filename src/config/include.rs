@@ -0,0 +1,269 @@
+//! `include:` of shared YAML files and `extends:` of jobs, resolved before the config is deserialized.
+//!
+//! `include: [ci/rust.yml, notify.yml]` lists files with the same layout as `fleet.yml`.
+//! A relative path is looked up next to the including file, then in `~/.fleet/templates/`.
+//! Included files may include others, a file including itself is an error.
+//!
+//! A job with `extends: .rust` (or a list of jobs) inherits everything the listed jobs define.
+//! Jobs whose name starts with `.` are templates: they can be extended but never run.
+//!
+//! Merge rules, applied the same way to includes and `extends`:
+//! - the includes are merged in the order they are listed, then the including file on top of them;
+//!   the parents of a job are merged in the order they are listed, then the job itself on top of them;
+//! - mappings (`pipeline`, `jobs`, `env`, ...) are merged key by key, recursively;
+//! - any other value, lists (`steps`, `needs`, `branches`, ...) included, replaces the one below it.
+//!
+//! YAML anchors still only work inside one file. The top-level keys holding them, written in capitals
+//! (`ENV: &default_env`) or starting with `x-`, are dropped once the files are merged.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use dirs::home_dir;
+use serde_yaml::{Mapping, Value};
+
+/// `~/.fleet/templates/`
+pub fn templates_dir() -> PathBuf {
+    let home = home_dir().unwrap();
+    home.join(".fleet").join("templates")
+}
+
+/// A file that is not valid YAML
+#[derive(Debug)]
+pub struct YamlError {
+    pub path: PathBuf,
+    pub error: serde_yaml::Error,
+}
+
+impl std::fmt::Display for YamlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Error parsing YAML configuration file {:?}: {}",
+            self.path, self.error
+        )
+    }
+}
+
+impl std::error::Error for YamlError {}
+
+/// An `include` or an `extends` that cannot be resolved, `key` is the path of the offending key
+/// in `file`, or in one of the files of the config when `file` is unknown
+#[derive(Debug)]
+pub struct IncludeError {
+    pub file: Option<PathBuf>,
+    pub key: Vec<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+/// The config of `path` with its includes and the `extends` of its jobs resolved
+pub fn load_value(path: &Path) -> Result<Value> {
+    Ok(load_with_files(path)?.0)
+}
+
+/// `load_value`, with every file read: `path` first, then its includes in the order they are merged
+pub fn load_with_files(path: &Path) -> Result<(Value, Vec<PathBuf>)> {
+    let mut files = Vec::new();
+    let mut config = read_with_includes(path, &mut Vec::new(), &mut files)?;
+    resolve_extends(&mut config)?;
+    if let Some(root) = config.as_mapping_mut() {
+        root.retain(|key, _| !key.as_str().is_some_and(is_extension_key));
+    }
+    Ok((config, files))
+}
+
+/// A top-level key left to the user, e.g. to hold YAML anchors: written in capitals (`ENV`) or starting with `x-`
+pub fn is_extension_key(key: &str) -> bool {
+    key.starts_with("x-")
+        || (key.chars().any(|c| c.is_ascii_alphabetic())
+            && !key.chars().any(|c| c.is_ascii_lowercase()))
+}
+
+/// `over` merged into `base`: mappings key by key, any other value replaced
+pub fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Mapping(base), Value::Mapping(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(below) => merge(below, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+fn read_with_includes(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<Value> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Error reading config file {path:?}"))?;
+    files.push(path.to_path_buf());
+    let mut value: Value = serde_yaml::from_str(&content).map_err(|error| YamlError {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    let includes = value
+        .as_mapping_mut()
+        .and_then(|m| m.remove("include"))
+        .map(|v| names(v, "include"))
+        .transpose()
+        .map_err(|e| include_error(path, e))?
+        .unwrap_or_default();
+    if includes.is_empty() {
+        return Ok(value);
+    }
+
+    let canonical = path.canonicalize()?;
+    if stack.contains(&canonical) {
+        return Err(include_error(
+            path,
+            anyhow::anyhow!("{path:?} includes itself"),
+        ));
+    }
+    stack.push(canonical);
+    let mut merged = Value::Mapping(Mapping::new());
+    for include in includes {
+        let resolved = resolve_include(path, &include).map_err(|e| include_error(path, e))?;
+        let included = read_with_includes(&resolved, stack, files)?;
+        merge(&mut merged, included);
+    }
+    stack.pop();
+    merge(&mut merged, value);
+    Ok(merged)
+}
+
+fn resolve_include(from: &Path, include: &str) -> Result<PathBuf> {
+    let local = from.parent().unwrap_or(Path::new(".")).join(include);
+    if local.is_file() {
+        return Ok(local);
+    }
+    let shared = templates_dir().join(include);
+    if Path::new(include).is_relative() && shared.is_file() {
+        return Ok(shared);
+    }
+    bail!(
+        "Included file '{include}' of {from:?} not found, neither next to it nor in {:?}",
+        templates_dir()
+    )
+}
+
+fn include_error(file: &Path, error: anyhow::Error) -> anyhow::Error {
+    IncludeError {
+        file: Some(file.to_path_buf()),
+        key: vec!["include".to_string()],
+        message: error.to_string(),
+    }
+    .into()
+}
+
+fn extends_error(name: &str, error: anyhow::Error) -> anyhow::Error {
+    IncludeError {
+        file: None,
+        key: ["pipeline", "jobs", name, "extends"]
+            .map(String::from)
+            .to_vec(),
+        message: error.to_string(),
+    }
+    .into()
+}
+
+/// A name or a list of names
+fn names(value: Value, key: &str) -> Result<Vec<String>> {
+    let values = match value {
+        Value::Sequence(values) => values,
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .map(|v| match v {
+            Value::String(s) => Ok(s),
+            v => bail!("`{key}` takes names, found {v:?}"),
+        })
+        .collect()
+}
+
+/// Replace every job by its merge with the jobs it extends, then drop the templates
+fn resolve_extends(config: &mut Value) -> Result<()> {
+    let Some(jobs) = config
+        .get_mut("pipeline")
+        .and_then(|p| p.get_mut("jobs"))
+        .and_then(Value::as_mapping_mut)
+    else {
+        return Ok(());
+    };
+
+    let defined = jobs.clone();
+    let mut resolved = HashMap::new();
+    for name in defined.keys().filter_map(Value::as_str) {
+        let job = resolve_job(name, &defined, &mut resolved, &mut Vec::new())?;
+        if name.starts_with('.') {
+            jobs.remove(name);
+        } else {
+            jobs.insert(Value::from(name), job);
+        }
+    }
+    Ok(())
+}
+
+fn resolve_job(
+    name: &str,
+    jobs: &Mapping,
+    resolved: &mut HashMap<String, Value>,
+    stack: &mut Vec<String>,
+) -> Result<Value> {
+    if let Some(job) = resolved.get(name) {
+        return Ok(job.clone());
+    }
+    if stack.iter().any(|n| n == name) {
+        return Err(extends_error(
+            name,
+            anyhow::anyhow!("Job '{name}' extends itself"),
+        ));
+    }
+
+    let mut job = jobs[name].clone();
+    let parents = job
+        .as_mapping_mut()
+        .and_then(|m| m.remove("extends"))
+        .map(|v| names(v, "extends"))
+        .transpose()
+        .map_err(|e| extends_error(name, e))?
+        .unwrap_or_default();
+
+    stack.push(name.to_string());
+    let mut merged = Value::Mapping(Mapping::new());
+    for parent in parents {
+        if !jobs.contains_key(parent.as_str()) {
+            return Err(extends_error(
+                name,
+                anyhow::anyhow!("Job '{name}' extends unknown job '{parent}'"),
+            ));
+        }
+        let parent = resolve_job(&parent, jobs, resolved, stack)?;
+        merge(&mut merged, parent);
+    }
+    stack.pop();
+    merge(&mut merged, job);
+
+    resolved.insert(name.to_string(), merged.clone());
+    Ok(merged)
+}
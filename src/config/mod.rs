@@ -1,21 +1,263 @@
+pub mod condition;
+pub mod include;
 pub mod parser;
-use std::{collections::HashMap, fs::OpenOptions};
+pub mod template;
+pub mod validate;
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::{core::watcher::WatchContext, exec::OutpuStrategy};
+use crate::{core::watcher::WatchContext, exec::OutpuStrategy, log::job_log::JobLog};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Cmd {
+    /// a single program invocation, split like a shell would but run without one
+    #[serde(default)]
     pub cmd: String,
+    /// a script run by `shell`, instead of `cmd`
+    #[serde(default)]
+    pub run: Option<String>,
+    /// program running the `run` script, overrides the `shell` of the job
+    #[serde(default)]
+    pub shell: Option<String>,
+    /// directory the step runs in, relative to the repository root, overrides the one of the job
+    #[serde(default)]
+    pub working_directory: Option<String>,
+    /// environment of the step, merged over the `env` of the job
+    #[serde(default)]
+    pub env: Option<HashMap<String, String>>,
     #[serde(default)]
     pub blocking: bool,
     #[serde(default)]
     pub container: Option<String>,
+    /// overrides the `retry` of the job
+    #[serde(default)]
+    pub retry: Option<Retry>,
+    /// seconds the step may run, overrides the `timeout` of the project
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// a failure of the step is logged as a warning and the next steps still run
+    #[serde(default, alias = "continue_on_error")]
+    pub allow_failure: bool,
+    /// exit codes counted as a success, only 0 when empty
+    #[serde(default)]
+    pub success_codes: Vec<i32>,
+}
+
+/// Run a failing step again, `attempts` counts the first run.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Retry {
+    pub attempts: u32,
+    /// seconds to wait before the second attempt
+    #[serde(default = "default_retry_delay")]
+    pub delay: u64,
+    #[serde(default)]
+    pub backoff: Backoff,
+}
+
+/// How the delay between two attempts grows.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    /// always `delay`
+    #[default]
+    Fixed,
+    /// `delay`, `2 * delay`, `3 * delay`...
+    Linear,
+    /// `delay`, `2 * delay`, `4 * delay`...
+    Exponential,
+}
+
+fn default_retry_delay() -> u64 {
+    1
+}
+
+/// Shell of the `run` scripts when neither the step, the job nor the pipeline sets one
+pub const DEFAULT_SHELL: &str = "sh -e";
+
+impl Cmd {
+    /// Program and arguments of the step, a `run` script is given to its shell with `-c`.
+    /// `shell` is the one inherited from the job or the pipeline.
+    pub fn argv(&self, shell: Option<&str>) -> Result<Vec<String>> {
+        let Some(script) = &self.run else {
+            return Ok(shell_words::split(&self.cmd)?);
+        };
+        let shell = self.shell.as_deref().or(shell).unwrap_or(DEFAULT_SHELL);
+        let mut argv = shell_words::split(shell)?;
+        argv.extend(["-c".to_string(), script.clone()]);
+        Ok(argv)
+    }
+
+    /// Environment of the step: the one of its job with its own `env` over it
+    pub fn merged_env(&self, job: &Job) -> Option<HashMap<String, String>> {
+        match (&job.env, &self.env) {
+            (None, None) => None,
+            (job_env, step_env) => {
+                let mut env = job_env.clone().unwrap_or_default();
+                env.extend(
+                    step_env
+                        .iter()
+                        .flatten()
+                        .map(|(k, v)| (k.clone(), v.clone())),
+                );
+                Some(env)
+            }
+        }
+    }
+}
+
+/// Normalize a `working_directory`, it must stay inside the repository
+pub fn relative_dir(dir: &str) -> Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(dir).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if normalized.pop() => {}
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "'{dir}' is outside of the repository, use a path relative to its root"
+                ));
+            }
+        }
+    }
+    Ok(normalized)
+}
+
+/// Directory of a container named by the absolute path `dir`, relative to `/`.
+/// `..` and the repository mounted on `/app` are rejected.
+pub fn container_dir(dir: &str) -> Result<PathBuf> {
+    let mut components = Path::new(dir).components();
+    if components.next() != Some(Component::RootDir) {
+        return Err(anyhow::anyhow!("'{dir}' is not an absolute path"));
+    }
+    let mut normalized = PathBuf::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            _ => return Err(anyhow::anyhow!("'{dir}' must not contain '..'")),
+        }
+    }
+    if normalized.as_os_str().is_empty() || normalized.starts_with("app") {
+        return Err(anyhow::anyhow!(
+            "'{dir}' overlaps the repository mounted on /app, use a path relative to its root"
+        ));
+    }
+    Ok(normalized)
+}
+
+impl Matrix {
+    /// Every combination of values: the product of the lists, without the `exclude` ones, then the `include` ones.
+    /// An `include` entry adds its other values to the combinations of the product it matches,
+    /// or is a combination of its own when it matches none.
+    pub fn combinations(&self) -> Result<Vec<MatrixValues>> {
+        let mut combinations: Vec<MatrixValues> = Vec::new();
+        let mut names = Vec::new();
+        if !self.axes.is_empty() {
+            combinations.push(Vec::new());
+        }
+        for (name, values) in &self.axes {
+            let name = matrix_scalar(name)?;
+            let Some(values) = values.as_sequence().filter(|v| !v.is_empty()) else {
+                return Err(anyhow::anyhow!("'{name}' is not a list of values"));
+            };
+            let values = values
+                .iter()
+                .map(matrix_scalar)
+                .collect::<Result<Vec<_>>>()?;
+            combinations = combinations
+                .into_iter()
+                .flat_map(|c| {
+                    let name = &name;
+                    values.iter().map(move |v| {
+                        let mut c = c.clone();
+                        c.push((name.clone(), v.clone()));
+                        c
+                    })
+                })
+                .collect();
+            names.push(name);
+        }
+
+        let exclude = self
+            .exclude
+            .iter()
+            .map(matrix_pairs)
+            .collect::<Result<Vec<_>>>()?;
+        combinations.retain(|c| !exclude.iter().any(|e| matrix_matches(c, e)));
+
+        let product = combinations.len();
+        for entry in &self.include {
+            let entry = matrix_pairs(entry)?;
+            let (on_axes, extra): (MatrixValues, MatrixValues) =
+                entry.iter().cloned().partition(|(k, _)| names.contains(k));
+            let mut matched = false;
+            for c in combinations[..product]
+                .iter_mut()
+                .filter(|c| matrix_matches(c, &on_axes))
+            {
+                matched = true;
+                for (key, value) in &extra {
+                    match c.iter_mut().find(|(k, _)| k == key) {
+                        Some((_, v)) => *v = value.clone(),
+                        None => c.push((key.clone(), value.clone())),
+                    }
+                }
+            }
+            if !matched {
+                combinations.push(entry);
+            }
+        }
+        Ok(combinations)
+    }
+}
+
+fn matrix_scalar(value: &serde_yaml::Value) -> Result<String> {
+    match value {
+        serde_yaml::Value::String(s) => Ok(s.clone()),
+        serde_yaml::Value::Number(n) => Ok(n.to_string()),
+        serde_yaml::Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(anyhow::anyhow!(
+            "matrix values are strings, numbers or booleans, found {value:?}"
+        )),
+    }
+}
+
+fn matrix_pairs(mapping: &serde_yaml::Mapping) -> Result<MatrixValues> {
+    mapping
+        .iter()
+        .map(|(k, v)| Ok((matrix_scalar(k)?, matrix_scalar(v)?)))
+        .collect()
+}
+
+/// every value of `entry` is the one of `combination`
+fn matrix_matches(combination: &MatrixValues, entry: &MatrixValues) -> bool {
+    entry.iter().all(|e| combination.contains(e))
+}
+
+impl Retry {
+    /// Wait before the attempt following the failed `attempt` (1-based).
+    pub fn delay_after(&self, attempt: u32) -> std::time::Duration {
+        let secs = match self.backoff {
+            Backoff::Fixed => self.delay,
+            Backoff::Linear => self.delay.saturating_mul(attempt as u64),
+            Backoff::Exponential => self
+                .delay
+                .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1))),
+        };
+        std::time::Duration::from_secs(secs)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Job {
     #[serde(default)]
     pub needs: Vec<String>,
@@ -23,16 +265,144 @@ pub struct Job {
     pub pipe: String,
     #[serde(default)]
     pub env: Option<HashMap<String, String>>,
+    /// run the job only when this expression holds, see `config::condition`
+    #[serde(default, rename = "if")]
+    pub condition: Option<String>,
+    /// branches the job runs on (glob patterns), every branch when empty
+    #[serde(default)]
+    pub branches: Vec<String>,
+    /// branches the job never runs on (glob patterns)
+    #[serde(default)]
+    pub ignore_branches: Vec<String>,
+    /// run the job only when a file matching one of these globs changed
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// changes to files matching these globs do not trigger the job
+    #[serde(default)]
+    pub paths_ignore: Vec<String>,
+    /// retry policy of the steps of the job
+    #[serde(default)]
+    pub retry: Option<Retry>,
+    /// seconds the whole job may run, every step included
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// a failure of the job does not fail the run, its dependents still run
+    #[serde(default, alias = "continue_on_error")]
+    pub allow_failure: bool,
+    /// program running the `run` scripts of the job, overrides the `shell` of the pipeline
+    #[serde(default)]
+    pub shell: Option<String>,
+    /// directory the steps run in, relative to the repository root
+    #[serde(default)]
+    pub working_directory: Option<String>,
+    /// files kept once the job succeeded, see `exec::artifacts`
+    #[serde(default)]
+    pub artifacts: Option<Artifacts>,
+    /// jobs whose artifacts are copied into the working tree before the first step
+    #[serde(default)]
+    pub download_artifacts: Vec<String>,
+    /// files kept between runs, see `exec::cache`
+    #[serde(default)]
+    pub cache: Option<Cache>,
+    /// run the job once per combination of values, expanded when the config is loaded,
+    /// see `parser::expand_matrix`
+    #[serde(default)]
+    pub matrix: Option<Matrix>,
     pub steps: Vec<Cmd>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+impl Job {
+    /// Jobs that must finish before this one starts: its `needs`, and the job of its `pipe`
+    pub fn dependencies(&self) -> impl Iterator<Item = &String> {
+        self.needs
+            .iter()
+            .chain(Some(&self.pipe).filter(|p| !p.is_empty() && !self.needs.contains(p)))
+    }
+}
+
+/// Values a job is run with, one job per combination, e.g. `toolchain: [stable, nightly]`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Matrix {
+    /// combinations added, or extra values given to the combinations they match
+    #[serde(default)]
+    pub include: Vec<serde_yaml::Mapping>,
+    /// combinations left out, matched on the values they give
+    #[serde(default)]
+    pub exclude: Vec<serde_yaml::Mapping>,
+    /// lists of values by name, in the order of the file
+    #[serde(flatten)]
+    pub axes: serde_yaml::Mapping,
+}
+
+/// `(name, value)` pairs of one job of a matrix
+pub type MatrixValues = Vec<(String, String)>;
+
+/// Files of the working tree kept from a job.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Artifacts {
+    /// paths or glob patterns relative to the repository root, a directory is kept whole
+    pub paths: Vec<String>,
+}
+
+/// Files kept between the runs of a project under a key, e.g. the `target/` of a cargo build.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Cache {
+    /// name of the entry, `${{ hashFiles('Cargo.lock') }}` and the outputs of upstream jobs are substituted
+    pub key: String,
+    /// paths or glob patterns relative to the repository root,
+    /// an absolute path is a directory of the containers of the job, mounted from the cache
+    pub paths: Vec<String>,
+    /// prefixes of older keys, the most recent entry matching one is restored when `key` has none
+    #[serde(default)]
+    pub restore_keys: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct Pipeline {
     pub notifications: Option<Notification>,
     pub jobs: HashMap<String, Job>,
+    /// stop scheduling new jobs after the first failure,
+    /// with `false` the jobs not depending on a failed one still run
+    #[serde(default = "default_fail_fast")]
+    pub fail_fast: bool,
+    /// run the pipeline only when a file matching one of these globs changed
+    /// since the last successful build of the branch
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// changes to files matching these globs do not trigger the pipeline
+    #[serde(default)]
+    pub paths_ignore: Vec<String>,
+    /// seconds the whole run may take, the steps still running are killed when it expires
+    #[serde(default)]
+    pub deadline: Option<u64>,
+    /// program running the `run` scripts, `DEFAULT_SHELL` when unset
+    #[serde(default)]
+    pub shell: Option<String>,
+}
+
+fn default_fail_fast() -> bool {
+    true
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self {
+            notifications: None,
+            jobs: HashMap::new(),
+            fail_fast: true,
+            paths: Vec::new(),
+            paths_ignore: Vec::new(),
+            deadline: None,
+            shell: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct ProjectConfig {
     pub pipeline: Pipeline,
 
@@ -40,17 +410,93 @@ pub struct ProjectConfig {
 
     #[serde(default)]
     pub timeout: Option<u64>,
+
+    #[serde(default)]
+    pub history: HistoryRetention,
+
+    #[serde(default)]
+    pub concurrency: ConcurrencyPolicy,
+}
+
+/// What to do when a run is requested while another run of the same project is in progress.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConcurrencyPolicy {
+    /// wait for the current run to finish
+    #[default]
+    Queue,
+    /// abort the current run, the newest commit wins
+    CancelInProgress,
+    /// drop the new run
+    Skip,
+}
+
+impl std::fmt::Display for ConcurrencyPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConcurrencyPolicy::Queue => write!(f, "queue"),
+            ConcurrencyPolicy::CancelInProgress => write!(f, "cancel-in-progress"),
+            ConcurrencyPolicy::Skip => write!(f, "skip"),
+        }
+    }
+}
+
+pub const DEFAULT_KEEP_RUNS: usize = 50;
+
+/// How many past runs are kept in the metrics history of a project.
+/// A run is dropped as soon as it is outside of one of the limits.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryRetention {
+    #[serde(default = "default_keep_runs")]
+    pub keep_runs: usize,
+    #[serde(default)]
+    pub keep_days: Option<u64>,
+}
+
+fn default_keep_runs() -> usize {
+    DEFAULT_KEEP_RUNS
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        Self {
+            keep_runs: DEFAULT_KEEP_RUNS,
+            keep_days: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct ConfChannel {
-    pub service: String,
+    pub service: ChannelService,
     pub url: String,
 }
 
+/// Where a channel sends its notifications.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelService {
+    /// a Discord webhook
+    #[default]
+    Discord,
+}
+
+/// End of a run that sends the notifications.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationEvent {
+    Success,
+    Failure,
+    /// cancelled runs also notify on `failure`
+    Cancelled,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct Notification {
-    pub on: Vec<String>,
+    pub on: Vec<NotificationEvent>,
     pub channels: Vec<ConfChannel>,
     #[serde(default)]
     pub thumbnail: Option<String>,
@@ -67,34 +513,32 @@ fn find_pipe_dependance(ctx: &WatchContext, job_name: &str) -> Option<Cmd> {
 }
 
 impl ProjectConfig {
-    pub fn drop_strategy(&self, job_name: &str, ctx: &WatchContext) -> Result<OutpuStrategy> {
-        let log_path = ctx.log_path();
-        let stdout_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&log_path)?;
-        let stderr_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&log_path)?;
-
-        for j in self.pipeline.jobs.values() {
+    pub fn drop_strategy(
+        &self,
+        job_name: &str,
+        run_id: &str,
+        ctx: &WatchContext,
+    ) -> Result<OutpuStrategy> {
+        let log = JobLog::open(&ctx.id, run_id, job_name, ctx.logger.clone())?;
+
+        for (name, j) in &self.pipeline.jobs {
             if !j.pipe.is_empty() && j.pipe == job_name {
                 let cmd = ctx
                     .config
                     .pipeline
                     .jobs
                     .get(job_name)
-                    .unwrap()
+                    .and_then(|job| job.steps.last())
+                    .ok_or_else(|| anyhow::anyhow!("Job '{job_name}' has no step to pipe"))?;
+                let target = j
                     .steps
                     .last()
-                    .unwrap();
-                let target = String::from(&j.steps.last().unwrap().cmd);
+                    .map(|s| s.cmd.clone())
+                    .ok_or_else(|| anyhow::anyhow!("Job '{name}' has no step to pipe into"))?;
                 println!("[1]'{target}' has design as target");
                 return Ok(OutpuStrategy::ToPipeOut {
                     cmd: cmd.cmd.clone(),
-                    stdout: stdout_file,
-                    stderr: stderr_file,
+                    log,
                     target,
                 });
             }
@@ -102,15 +546,11 @@ impl ProjectConfig {
         if let Some(depend) = find_pipe_dependance(ctx, job_name) {
             // job who depend another
             return Ok(OutpuStrategy::ToPipeIn {
-                stdout: stdout_file,
-                stderr: stderr_file,
+                log,
                 target: depend.cmd,
             });
         }
-        Ok(OutpuStrategy::ToFiles {
-            stdout: stdout_file,
-            stderr: stderr_file,
-        })
+        Ok(OutpuStrategy::ToFiles { log })
     }
 }
 
@@ -1,35 +1,279 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs,
     io::Write,
     path::Path,
 };
 
 use anyhow::{Context, Result};
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
 
 use crate::{
-    config::{Job, ProjectConfig, stdin_is_tty},
+    config::{
+        Cmd, Job, MatrixValues, Pipeline, ProjectConfig,
+        condition::Expr,
+        container_dir,
+        include::load_value,
+        relative_dir, stdin_is_tty,
+        template::{Placeholder, placeholders, referenced_jobs, render_matrix},
+    },
     log::logger::{LogLevel, Logger},
 };
 
-pub fn check_dependency_graph(config: &ProjectConfig) -> Result<()> {
-    let pipeline = &config.pipeline;
+/// Jobs whose outputs a job reads, in its `env`, the `env` of its steps, its `if` and its cache keys
+fn output_references(name: &str, job: &Job) -> Result<Vec<String>> {
+    let mut jobs = Vec::new();
+    let envs = job
+        .env
+        .iter()
+        .chain(job.steps.iter().filter_map(|s| s.env.as_ref()));
+    for value in envs.flat_map(|env| env.values()) {
+        for placeholder in placeholders(value)
+            .map_err(|e| anyhow::anyhow!("Job '{}' has an invalid env: {}", name, e))?
+        {
+            match placeholder {
+                Placeholder::Output { job, .. } => jobs.push(job.to_string()),
+                Placeholder::HashFiles(_) => {
+                    return Err(anyhow::anyhow!(
+                        "Job '{}' has an invalid env: hashFiles() is only available in cache keys",
+                        name
+                    ));
+                }
+            }
+        }
+    }
+    let keys = job
+        .cache
+        .iter()
+        .flat_map(|c| std::iter::once(&c.key).chain(&c.restore_keys));
+    for key in keys {
+        let referenced = referenced_jobs(key)
+            .map_err(|e| anyhow::anyhow!("Job '{}' has an invalid cache key: {}", name, e))?;
+        jobs.extend(referenced.into_iter().map(String::from));
+    }
+    if let Some(condition) = &job.condition {
+        let expr = Expr::parse(condition)
+            .map_err(|e| anyhow::anyhow!("Job '{}' has an invalid if: {}", name, e))?;
+        jobs.extend(expr.referenced_jobs().into_iter().map(String::from));
+    }
+    Ok(jobs)
+}
 
-    for (name, job) in pipeline.jobs.iter() {
-        if job.needs.contains(name) {
-            return Err(anyhow::anyhow!("Job '{}' cannot depend on itself", name));
+/// Artifact paths stay in the repository, artifacts come from upstream jobs keeping some
+fn check_artifacts(
+    name: &str,
+    job: &Job,
+    pipeline: &Pipeline,
+    upstream: &HashSet<String>,
+) -> Result<()> {
+    for path in job.artifacts.iter().flat_map(|a| &a.paths) {
+        relative_dir(path)
+            .and_then(|_| Ok(Glob::new(path)?))
+            .map_err(|e| anyhow::anyhow!("Job '{}' has an invalid artifact path: {}", name, e))?;
+    }
+    for dep in &job.download_artifacts {
+        if !upstream.contains(dep) {
+            return Err(anyhow::anyhow!(
+                "Job '{}' downloads the artifacts of '{}' which it does not need",
+                name,
+                dep
+            ));
         }
-        for dep in &job.needs {
-            if !pipeline.jobs.contains_key(dep) {
-                return Err(anyhow::anyhow!(
-                    "Job '{}' depends on unknown job '{}'",
-                    name,
-                    dep
-                ));
+        if pipeline
+            .jobs
+            .get(dep)
+            .is_some_and(|d| d.artifacts.is_none())
+        {
+            return Err(anyhow::anyhow!(
+                "Job '{}' downloads the artifacts of '{}' which keeps none",
+                name,
+                dep
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A cache has a key and paths, in the repository or, for a job running containers, absolute
+fn check_cache(name: &str, job: &Job) -> Result<()> {
+    let Some(cache) = &job.cache else {
+        return Ok(());
+    };
+    if cache.key.trim().is_empty() {
+        return Err(anyhow::anyhow!("Job '{}' has a cache without key", name));
+    }
+    if cache.paths.is_empty() {
+        return Err(anyhow::anyhow!("Job '{}' has a cache without paths", name));
+    }
+    for key in std::iter::once(&cache.key).chain(&cache.restore_keys) {
+        for placeholder in placeholders(key)? {
+            if let Placeholder::HashFiles(patterns) = placeholder {
+                glob_set(&patterns).map_err(|e| {
+                    anyhow::anyhow!("Job '{}' has an invalid cache key: {}", name, e)
+                })?;
             }
         }
     }
+    let in_container = job.steps.iter().any(|s| s.container.is_some());
+    for path in &cache.paths {
+        if path.starts_with('/') && !in_container {
+            return Err(anyhow::anyhow!(
+                "Job '{}' caches the absolute path '{}' but none of its steps runs in a container",
+                name,
+                path
+            ));
+        }
+        if path.starts_with('/') {
+            container_dir(path).map(|_| ())
+        } else {
+            relative_dir(path).and_then(|_| Ok(Glob::new(path).map(|_| ())?))
+        }
+        .map_err(|e| anyhow::anyhow!("Job '{}' has an invalid cache path: {}", name, e))?;
+    }
+    Ok(())
+}
+
+/// Every job `name` depends on, even indirectly, through `needs` or `pipe`
+fn upstream_jobs(pipeline: &Pipeline, name: &str) -> HashSet<String> {
+    let mut upstream = HashSet::new();
+    let mut stack: Vec<&String> = pipeline
+        .jobs
+        .get(name)
+        .into_iter()
+        .flat_map(Job::dependencies)
+        .collect();
+    while let Some(dep) = stack.pop() {
+        if upstream.insert(dep.clone())
+            && let Some(job) = pipeline.jobs.get(dep)
+        {
+            stack.extend(job.dependencies());
+        }
+    }
+    upstream
+}
 
+/// A step runs either a `cmd` or a `run` script, with a shell that parses
+fn check_step(name: &str, index: usize, step: &Cmd, job: &Job, pipeline: &Pipeline) -> Result<()> {
+    match (step.cmd.trim().is_empty(), &step.run) {
+        (true, None) => {
+            return Err(anyhow::anyhow!(
+                "Job '{}' step {} has neither cmd nor run",
+                name,
+                index
+            ));
+        }
+        (false, Some(_)) => {
+            return Err(anyhow::anyhow!(
+                "Job '{}' step {} has both cmd and run",
+                name,
+                index
+            ));
+        }
+        _ => {}
+    }
+    for dir in job.working_directory.iter().chain(&step.working_directory) {
+        relative_dir(dir).map_err(|e| {
+            anyhow::anyhow!("Job '{}' has an invalid working_directory: {}", name, e)
+        })?;
+    }
+    let inherited = job.shell.as_ref().or(pipeline.shell.as_ref());
+    let argv = step
+        .argv(inherited.map(String::as_str))
+        .map_err(|e| anyhow::anyhow!("Job '{}' step {} cannot be parsed: {}", name, index, e))?;
+    if argv.first().is_none_or(|p| p == "-c") {
+        return Err(anyhow::anyhow!(
+            "Job '{}' step {} has an empty shell",
+            name,
+            index
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_dependency_graph(config: &ProjectConfig) -> Result<()> {
+    let pipeline = &config.pipeline;
+    check_paths(pipeline)?;
+    for (name, job) in pipeline.jobs.iter() {
+        check_job(name, job, pipeline)?;
+    }
+    check_cycles(pipeline)
+}
+
+/// The `paths` and `paths_ignore` patterns of the pipeline
+pub fn check_paths(pipeline: &Pipeline) -> Result<()> {
+    glob_set(pipeline.paths.iter().chain(&pipeline.paths_ignore))
+        .map_err(|e| anyhow::anyhow!("Pipeline has an invalid path pattern: {}", e))?;
+    Ok(())
+}
+
+/// Everything about one job of the pipeline: its dependencies, the jobs it reads from and its steps
+pub fn check_job(name: &str, job: &Job, pipeline: &Pipeline) -> Result<()> {
+    if job.needs.iter().any(|n| n == name) {
+        return Err(anyhow::anyhow!("Job '{}' cannot depend on itself", name));
+    }
+    for dep in &job.needs {
+        if !pipeline.jobs.contains_key(dep) {
+            return Err(anyhow::anyhow!(
+                "Job '{}' depends on unknown job '{}'",
+                name,
+                dep
+            ));
+        }
+    }
+    if job.pipe == name {
+        return Err(anyhow::anyhow!("Job '{}' cannot pipe into itself", name));
+    }
+    if !job.pipe.is_empty() && !pipeline.jobs.contains_key(&job.pipe) {
+        return Err(anyhow::anyhow!(
+            "Job '{}' pipes into unknown job '{}'",
+            name,
+            job.pipe
+        ));
+    }
+    let upstream = upstream_jobs(pipeline, name);
+    check_artifacts(name, job, pipeline, &upstream)?;
+    check_cache(name, job)?;
+    let mut references = output_references(name, job)?.into_iter();
+    if let Some(dep) = references.find(|dep| !upstream.contains(dep)) {
+        return Err(anyhow::anyhow!(
+            "Job '{}' uses the outputs of '{}' which it does not need",
+            name,
+            dep
+        ));
+    }
+    for pattern in job.branches.iter().chain(&job.ignore_branches) {
+        branch_glob(pattern)
+            .map_err(|e| anyhow::anyhow!("Job '{}' has an invalid branch pattern: {}", name, e))?;
+    }
+    let retries = job.steps.iter().filter_map(|s| s.retry.as_ref());
+    if job.retry.iter().chain(retries).any(|r| r.attempts == 0) {
+        return Err(anyhow::anyhow!(
+            "Job '{}' has a retry with 0 attempts, at least 1 is needed",
+            name
+        ));
+    }
+    glob_set(job.paths.iter().chain(&job.paths_ignore))
+        .map_err(|e| anyhow::anyhow!("Job '{}' has an invalid path pattern: {}", name, e))?;
+    for (i, step) in job.steps.iter().enumerate() {
+        check_step(name, i + 1, step, job, pipeline)?;
+    }
+    // the piped output is matched on the program of the last steps
+    let piped = std::iter::once(job).chain(pipeline.jobs.get(&job.pipe));
+    if !job.pipe.is_empty()
+        && piped
+            .filter_map(|j| j.steps.last())
+            .any(|s| s.run.is_some())
+    {
+        return Err(anyhow::anyhow!(
+            "Job '{}' pipes into '{}', their last steps must use cmd, not run",
+            name,
+            job.pipe
+        ));
+    }
+    Ok(())
+}
+
+/// Jobs never need themselves, even indirectly
+pub fn check_cycles(pipeline: &Pipeline) -> Result<()> {
     fn visit(
         name: &str,
         pipeline: &HashMap<String, Job>,
@@ -55,7 +299,7 @@ pub fn check_dependency_graph(config: &ProjectConfig) -> Result<()> {
 
         path.push(name.to_string());
         if let Some(job) = pipeline.get(name) {
-            for dep in &job.needs {
+            for dep in job.dependencies() {
                 visit(dep, pipeline, temp, perm, path)?;
             }
         }
@@ -73,63 +317,349 @@ pub fn check_dependency_graph(config: &ProjectConfig) -> Result<()> {
     Ok(())
 }
 
-pub fn load_config(path: &Path) -> Result<ProjectConfig> {
-    let content: String =
-        fs::read_to_string(path).with_context(|| format!("Error reading config file {path:?}"))?;
+fn branch_glob(pattern: &str) -> Result<GlobMatcher> {
+    let pattern = pattern.strip_prefix("origin/").unwrap_or(pattern);
+    Ok(Glob::new(pattern)?.compile_matcher())
+}
 
-    let mut config: ProjectConfig =
-        serde_yaml::from_str(&content).with_context(|| "Error parsing YAML configuration file")?;
+/// Whether `job` targets `branch`, according to its `branches` and `ignore_branches` patterns
+pub fn job_runs_on_branch(job: &Job, branch: &str) -> Result<bool> {
+    let branch = branch.strip_prefix("origin/").unwrap_or(branch);
+    for pattern in &job.ignore_branches {
+        if branch_glob(pattern)?.is_match(branch) {
+            return Ok(false);
+        }
+    }
+    if job.branches.is_empty() {
+        return Ok(true);
+    }
+    for pattern in &job.branches {
+        if branch_glob(pattern)?.is_match(branch) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
 
-    let mut skipped_missing_variables = HashSet::new();
+/// Keep only the jobs targeting `branch`, returns the pruned config and the names of the removed jobs.
+/// A kept job must not need (or pipe into) a removed one.
+pub fn prune_for_branch(
+    config: &ProjectConfig,
+    branch: &str,
+) -> Result<(ProjectConfig, Vec<String>)> {
+    let mut pruned = config.clone();
+    let mut removed = Vec::new();
+    for (name, job) in &config.pipeline.jobs {
+        if !job_runs_on_branch(job, branch)? {
+            pruned.pipeline.jobs.remove(name);
+            removed.push(name.clone());
+        }
+    }
+    removed.sort();
 
-    // resolve secret env variable for each job
-    for (job_name, job) in config.pipeline.jobs.iter_mut() {
-        let env_map = job.env.as_mut();
-        if env_map.is_none() {
-            continue;
+    for (name, job) in &pruned.pipeline.jobs {
+        let mut links = job.needs.iter().chain(std::iter::once(&job.pipe));
+        if let Some(dep) = links.find(|dep| removed.contains(dep)) {
+            return Err(anyhow::anyhow!(
+                "Job '{}' depends on '{}' which does not run on branch '{}'",
+                name,
+                dep,
+                branch
+            ));
+        }
+    }
+    Ok((pruned, removed))
+}
+
+fn glob_set<'a>(patterns: impl IntoIterator<Item = &'a String>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Whether the changes are relevant for the `paths` / `paths_ignore` filters:
+/// a changed file not ignored must match `paths` (any one when `paths` is empty).
+/// Without a list of changes (first build of a branch) everything is relevant.
+pub fn paths_match(
+    paths: &[String],
+    paths_ignore: &[String],
+    changed: Option<&[String]>,
+) -> Result<bool> {
+    let Some(changed) = changed else {
+        return Ok(true);
+    };
+    if paths.is_empty() && paths_ignore.is_empty() {
+        return Ok(true);
+    }
+    let wanted = glob_set(paths)?;
+    let ignored = glob_set(paths_ignore)?;
+    Ok(changed
+        .iter()
+        .filter(|path| !ignored.is_match(path))
+        .any(|path| paths.is_empty() || wanted.is_match(path)))
+}
+
+/// Remove the jobs without a relevant change, returns the pruned config and the names of the removed jobs.
+/// They are dropped from the `needs` of the other jobs, which run without them.
+pub fn prune_for_paths(
+    config: &ProjectConfig,
+    changed: Option<&[String]>,
+) -> Result<(ProjectConfig, Vec<String>)> {
+    let mut pruned = config.clone();
+    let mut removed = Vec::new();
+    for (name, job) in &config.pipeline.jobs {
+        if !paths_match(&job.paths, &job.paths_ignore, changed)? {
+            pruned.pipeline.jobs.remove(name);
+            removed.push(name.clone());
         }
+    }
+    removed.sort();
 
-        for (name, value) in env_map.unwrap().iter_mut() {
-            if !value.starts_with("$") {
-                continue;
-            }
+    for (name, job) in pruned.pipeline.jobs.iter_mut() {
+        if removed.contains(&job.pipe) {
+            return Err(anyhow::anyhow!(
+                "Job '{}' reads the output of '{}' which has no matching change",
+                name,
+                job.pipe
+            ));
+        }
+        if let Some(dep) = output_references(name, job)?
+            .into_iter()
+            .find(|dep| removed.contains(dep))
+        {
+            return Err(anyhow::anyhow!(
+                "Job '{}' uses the outputs of '{}' which has no matching change",
+                name,
+                dep
+            ));
+        }
+        if let Some(dep) = job.download_artifacts.iter().find(|d| removed.contains(d)) {
+            return Err(anyhow::anyhow!(
+                "Job '{}' downloads the artifacts of '{}' which has no matching change",
+                name,
+                dep
+            ));
+        }
+        job.needs.retain(|dep| !removed.contains(dep));
+    }
+    Ok((pruned, removed))
+}
 
-            let env_key = if !&value[1..].is_empty() {
-                &value[1..]
-            } else {
+/// Replace every job with a `matrix` by one job per combination of its values, named like `test (stable, no-tty)`.
+/// A `needs` or `download_artifacts` naming a matrix job names all its jobs.
+pub fn expand_matrix(config: &mut ProjectConfig) -> Result<()> {
+    let jobs = &mut config.pipeline.jobs;
+    let mut expansions: HashMap<String, Vec<String>> = HashMap::new();
+    let mut expanded = HashMap::new();
+    for (name, job) in jobs.iter() {
+        let Some(matrix) = &job.matrix else {
+            continue;
+        };
+        let invalid =
+            |e: anyhow::Error| anyhow::anyhow!("Job '{}' has an invalid matrix: {}", name, e);
+        let combinations = matrix.combinations().map_err(invalid)?;
+        if combinations.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Job '{}' has a matrix without any combination",
                 name
-            };
-
-            let extraction_result = std::env::var(env_key);
-            if let Ok(env_value) = extraction_result {
-                *value = env_value;
-                continue;
+            ));
+        }
+        // a value some combinations do not give renders as an empty string in them
+        let mut known: Vec<&String> = Vec::new();
+        for (name, _) in combinations.iter().flatten() {
+            if !known.contains(&name) {
+                known.push(name);
             }
+        }
+        let mut names = Vec::new();
+        for values in &combinations {
+            let shown: Vec<&str> = values.iter().map(|(_, v)| v.as_str()).collect();
+            let job_name = format!("{} ({})", name, shown.join(", "));
+            if expanded
+                .insert(
+                    job_name.clone(),
+                    matrix_job(job, values, &known).map_err(invalid)?,
+                )
+                .is_some()
+                || jobs.contains_key(&job_name)
+            {
+                return Err(anyhow::anyhow!(
+                    "Job '{}' of the matrix of '{}' is defined twice",
+                    job_name,
+                    name
+                ));
+            }
+            names.push(job_name);
+        }
+        expansions.insert(name.clone(), names);
+    }
+    if expansions.is_empty() {
+        return Ok(());
+    }
 
-            Logger::write(
-                &format!(r#""${}" not found for job "{job_name}""#, env_key),
-                LogLevel::Warning,
-            );
+    jobs.retain(|name, _| !expansions.contains_key(name));
+    jobs.extend(expanded);
+    let expand = |names: &mut Vec<String>| {
+        *names = names
+            .iter()
+            .flat_map(|n| {
+                expansions
+                    .get(n)
+                    .cloned()
+                    .unwrap_or_else(|| vec![n.clone()])
+            })
+            .collect();
+    };
+    for (name, job) in jobs.iter_mut() {
+        expand(&mut job.needs);
+        expand(&mut job.download_artifacts);
+        if expansions.contains_key(&job.pipe) {
+            return Err(anyhow::anyhow!(
+                "Job '{}' pipes into '{}' which has a matrix",
+                name,
+                job.pipe
+            ));
+        }
+    }
+    Ok(())
+}
 
-            if skipped_missing_variables.contains(env_key) {
-                *value = "".to_string();
-                continue;
-            }
+/// A job of a matrix: its `${{ matrix.NAME }}` placeholders replaced, `MATRIX_NAME` added to its env.
+/// `known` are the names given by any combination of the matrix.
+fn matrix_job(job: &Job, values: &MatrixValues, known: &[&String]) -> Result<Job> {
+    let mut job = job.clone();
+    job.matrix = None;
+    let mut rendered = values.clone();
+    for name in known {
+        if !values.iter().any(|(n, _)| n == *name) {
+            rendered.push((name.to_string(), String::new()));
+        }
+    }
+
+    let mut texts: Vec<&mut String> = Vec::new();
+    texts.extend(job.env.iter_mut().flat_map(|e| e.values_mut()));
+    texts.extend(job.condition.as_mut());
+    texts.extend(job.shell.as_mut());
+    texts.extend(job.working_directory.as_mut());
+    texts.extend(job.artifacts.iter_mut().flat_map(|a| a.paths.iter_mut()));
+    if let Some(cache) = job.cache.as_mut() {
+        texts.push(&mut cache.key);
+        texts.extend(cache.restore_keys.iter_mut().chain(cache.paths.iter_mut()));
+    }
+    for step in job.steps.iter_mut() {
+        texts.push(&mut step.cmd);
+        texts.extend(step.run.as_mut());
+        texts.extend(step.shell.as_mut());
+        texts.extend(step.container.as_mut());
+        texts.extend(step.working_directory.as_mut());
+        texts.extend(step.env.iter_mut().flat_map(|e| e.values_mut()));
+    }
+    for text in texts {
+        *text = render_matrix(text, &rendered)?;
+    }
 
-            if stdin_is_tty() {
-                if ask_continue_anyway()? {
-                    skipped_missing_variables.insert(env_key.to_string());
-                    *value = "".to_string();
-                    continue;
+    let env = job.env.get_or_insert_default();
+    for (name, value) in values {
+        let var: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
                 } else {
-                    return Err(extraction_result.unwrap_err().into());
+                    '_'
                 }
+            })
+            .collect();
+        env.entry(format!("MATRIX_{var}"))
+            .or_insert_with(|| value.clone());
+    }
+    Ok(job)
+}
+
+/// Replace the `$VAR` values of `env` with the variables of the environment, asking before
+/// leaving a missing one empty
+fn resolve_secrets(
+    job_name: &str,
+    env: &mut HashMap<String, String>,
+    skipped_missing_variables: &mut HashSet<String>,
+) -> Result<()> {
+    for (name, value) in env.iter_mut() {
+        // `${{ }}` placeholders are rendered when the job starts
+        if !value.starts_with("$") || value.starts_with("${{") {
+            continue;
+        }
+
+        let env_key = if !&value[1..].is_empty() {
+            &value[1..]
+        } else {
+            name
+        };
+
+        let extraction_result = std::env::var(env_key);
+        if let Ok(env_value) = extraction_result {
+            *value = env_value;
+            continue;
+        }
+
+        Logger::write(
+            &format!(r#""${}" not found for job "{job_name}""#, env_key),
+            LogLevel::Warning,
+        );
+
+        if skipped_missing_variables.contains(env_key) {
+            *value = "".to_string();
+            continue;
+        }
+
+        if stdin_is_tty() {
+            if ask_continue_anyway()? {
+                skipped_missing_variables.insert(env_key.to_string());
+                *value = "".to_string();
+                continue;
             } else {
-                return Err(anyhow::anyhow!(
-                    "Missing env variable '{}' and no TTY to ask user",
-                    env_key
-                ));
+                return Err(extraction_result.unwrap_err().into());
             }
+        } else {
+            return Err(anyhow::anyhow!(
+                "Missing env variable '{}' and no TTY to ask user",
+                env_key
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `load_config` with the secrets of the env left as written, for the commands only reading the config
+pub fn read_config(path: &Path) -> Result<ProjectConfig> {
+    let config = parse_config(path)?;
+    check_dependency_graph(&config)?;
+    Ok(config)
+}
+
+fn parse_config(path: &Path) -> Result<ProjectConfig> {
+    let value = load_value(path)?;
+    let mut config: ProjectConfig =
+        serde_yaml::from_value(value).with_context(|| "Error parsing YAML configuration file")?;
+    expand_matrix(&mut config)?;
+    Ok(config)
+}
+
+pub fn load_config(path: &Path) -> Result<ProjectConfig> {
+    let mut config = parse_config(path)?;
+
+    let mut skipped_missing_variables = HashSet::new();
+
+    // resolve secret env variable for each job and each of its steps
+    for (job_name, job) in config.pipeline.jobs.iter_mut() {
+        let envs = job
+            .env
+            .as_mut()
+            .into_iter()
+            .chain(job.steps.iter_mut().filter_map(|s| s.env.as_mut()));
+        for env in envs {
+            resolve_secrets(job_name, env, &mut skipped_missing_variables)?;
         }
     }
     // dbg!(&config);
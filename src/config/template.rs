@@ -0,0 +1,170 @@
+//! `${{ ... }}` placeholders in the `env` values of jobs and steps and in cache keys.
+//!
+//! `${{ jobs.NAME.outputs.KEY }}` is replaced by the output `KEY` of the job `NAME`,
+//! written by one of its steps as a `KEY=value` line in the file named by `FLEET_OUTPUT`.
+//! An output never written is replaced by an empty string.
+//! `${{ hashFiles('Cargo.lock', '**/package-lock.json') }}` is only available in cache keys,
+//! see `exec::cache`.
+//! `${{ matrix.NAME }}` is replaced when the jobs of a matrix are expanded, see `parser::expand_matrix`.
+
+use std::{collections::HashMap, ops::Range};
+
+use anyhow::{Result, bail};
+
+/// Outputs of the jobs of a run, by job name
+pub type JobOutputs = HashMap<String, HashMap<String, String>>;
+
+/// Job and key of a `jobs.NAME.outputs.KEY` reference
+pub fn job_output(reference: &str) -> Option<(&str, &str)> {
+    let mut parts = reference.split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("jobs"), Some(job), Some("outputs"), Some(key))
+            if !job.is_empty() && !key.is_empty() && parts.next().is_none() =>
+        {
+            Some((job, key))
+        }
+        _ => None,
+    }
+}
+
+/// A `${{ ... }}` placeholder
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placeholder<'a> {
+    /// `jobs.NAME.outputs.KEY`
+    Output { job: &'a str, key: &'a str },
+    /// `hashFiles('pattern', ...)`
+    HashFiles(Vec<String>),
+}
+
+/// Every placeholder of `text`: its byte range and its trimmed expression
+fn scan(text: &str) -> Result<Vec<(Range<usize>, &str)>> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find("${{").map(|i| offset + i) {
+        let Some(len) = text[start + 3..].find("}}") else {
+            bail!("unterminated `${{{{` in `{text}`");
+        };
+        let end = start + 3 + len + 2;
+        found.push((start..end, text[start + 3..end - 2].trim()));
+        offset = end;
+    }
+    Ok(found)
+}
+
+fn parse_placeholder(expr: &str) -> Result<Placeholder<'_>> {
+    if let Some((job, key)) = job_output(expr) {
+        return Ok(Placeholder::Output { job, key });
+    }
+    if let Some(args) = expr
+        .strip_prefix("hashFiles(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let mut patterns = Vec::new();
+        let mut rest = args.trim();
+        while let Some(quoted) = rest.strip_prefix('\'') {
+            let Some((pattern, after)) = quoted.split_once('\'') else {
+                break;
+            };
+            patterns.push(pattern.to_string());
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(next) => rest = next.trim_start(),
+                None => break,
+            }
+        }
+        if !rest.is_empty() || patterns.is_empty() || patterns.iter().any(String::is_empty) {
+            bail!("hashFiles() takes quoted patterns separated by commas, found `{expr}`");
+        }
+        return Ok(Placeholder::HashFiles(patterns));
+    }
+    bail!("unknown placeholder `${{{{ {expr} }}}}`")
+}
+
+/// Every placeholder of `text`, parsed
+pub fn placeholders(text: &str) -> Result<Vec<Placeholder<'_>>> {
+    scan(text)?
+        .into_iter()
+        .map(|(_, expr)| parse_placeholder(expr))
+        .collect()
+}
+
+/// Jobs whose outputs are used by `text`
+pub fn referenced_jobs(text: &str) -> Result<Vec<&str>> {
+    Ok(placeholders(text)?
+        .into_iter()
+        .filter_map(|p| match p {
+            Placeholder::Output { job, .. } => Some(job),
+            Placeholder::HashFiles(_) => None,
+        })
+        .collect())
+}
+
+/// Replace every placeholder of `text` with the value `value` gives it
+pub fn render_with(
+    text: &str,
+    mut value: impl FnMut(&Placeholder) -> Result<String>,
+) -> Result<String> {
+    let mut rendered = String::with_capacity(text.len());
+    let mut last = 0;
+    for (range, expr) in scan(text)? {
+        let placeholder = parse_placeholder(expr)?;
+        rendered.push_str(&text[last..range.start]);
+        rendered.push_str(&value(&placeholder)?);
+        last = range.end;
+    }
+    rendered.push_str(&text[last..]);
+    Ok(rendered)
+}
+
+/// Replace the `${{ matrix.NAME }}` placeholders of `text` with the values of a matrix job,
+/// the other placeholders are left as they are
+pub fn render_matrix(text: &str, values: &[(String, String)]) -> Result<String> {
+    let mut rendered = String::with_capacity(text.len());
+    let mut last = 0;
+    for (range, expr) in scan(text)? {
+        let Some(name) = expr.strip_prefix("matrix.") else {
+            continue;
+        };
+        let Some((_, value)) = values.iter().find(|(n, _)| n == name) else {
+            bail!("`{expr}` is not a value of the matrix");
+        };
+        rendered.push_str(&text[last..range.start]);
+        rendered.push_str(value);
+        last = range.end;
+    }
+    rendered.push_str(&text[last..]);
+    Ok(rendered)
+}
+
+/// The output of a job, empty when it was never written
+pub fn output_value(outputs: &JobOutputs, job: &str, key: &str) -> String {
+    outputs
+        .get(job)
+        .and_then(|o| o.get(key))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Replace every placeholder of an `env` value with the output it references
+pub fn render(text: &str, outputs: &JobOutputs) -> Result<String> {
+    render_with(text, |placeholder| match placeholder {
+        Placeholder::Output { job, key } => Ok(output_value(outputs, job, key)),
+        Placeholder::HashFiles(_) => bail!("hashFiles() is only available in cache keys"),
+    })
+}
+
+/// `KEY=value` lines written to `FLEET_OUTPUT`, the last value of a key wins.
+/// Blank lines are ignored, any other line without `=` is returned as invalid.
+pub fn parse_outputs(content: &str) -> (HashMap<String, String>, Vec<String>) {
+    let mut outputs = HashMap::new();
+    let mut invalid = Vec::new();
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                outputs.insert(key.trim().to_string(), value.to_string());
+            }
+            _ => invalid.push(line.to_string()),
+        }
+    }
+    (outputs, invalid)
+}
@@ -0,0 +1,626 @@
+//! `fleet validate`: every problem of a config at once, each one with the file, line and column it comes from.
+//!
+//! The config is checked in passes, a later pass only looks at what the earlier ones accepted:
+//! YAML syntax and includes, unknown keys, values of the wrong type, references to jobs, notification
+//! events and services, then the checks `load_config` runs on every job.
+//! Positions are found by reading the files as block-style YAML; a key written in flow style (`{ a: 1 }`)
+//! or inherited with `extends` is reported at the closest parent key found.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+
+use crate::config::{
+    Artifacts, Cache, Cmd, ConfChannel, HistoryRetention, Job, Notification, NotificationEvent,
+    Pipeline, ProjectConfig, Retry,
+    include::{IncludeError, YamlError, load_with_files},
+    parser::{check_cycles, check_job, check_paths, expand_matrix},
+};
+
+/// One problem of a config
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+/// Every problem of the config at `path`, sorted by file and position.
+/// `Err` when a file of the config cannot be read.
+pub fn validate(path: &Path) -> Result<Vec<Problem>> {
+    let (config, files) = match load_with_files(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            if let Some(yaml) = e.downcast_ref::<YamlError>() {
+                let (line, column) = yaml
+                    .error
+                    .location()
+                    .map_or((1, 1), |l| (l.line(), l.column()));
+                let message = yaml.error.to_string();
+                let message = match message.find(" at line ") {
+                    Some(i) => message[..i].to_string(),
+                    None => message,
+                };
+                return Ok(vec![Problem {
+                    file: yaml.path.clone(),
+                    line,
+                    column,
+                    message,
+                }]);
+            }
+            if let Some(include) = e.downcast_ref::<IncludeError>() {
+                let file = include.file.clone().unwrap_or_else(|| path.to_path_buf());
+                return Ok(vec![
+                    Locator::new(&[file]).problem(&include.key, include.message.clone()),
+                ]);
+            }
+            return Err(e);
+        }
+    };
+
+    let mut check = Check {
+        locator: Locator::new(&files),
+        problems: Vec::new(),
+        faulty_jobs: HashSet::new(),
+        dropped: Vec::new(),
+    };
+    check.config(&config);
+
+    let mut problems = check.problems;
+    problems.sort_by_key(|p| {
+        let file = files.iter().position(|f| *f == p.file);
+        (file, p.line, p.column)
+    });
+    problems.dedup();
+    Ok(problems)
+}
+
+struct Check {
+    locator: Locator,
+    problems: Vec<Problem>,
+    /// jobs with a problem, left out of the later passes
+    faulty_jobs: HashSet<String>,
+    /// keys unknown or holding a wrong value, removed before the checks of `load_config`
+    dropped: Vec<Vec<String>>,
+}
+
+impl Check {
+    fn report(&mut self, key: &[String], message: String) {
+        if let [pipeline, jobs, job, ..] = key
+            && pipeline == "pipeline"
+            && jobs == "jobs"
+        {
+            self.faulty_jobs.insert(job.clone());
+        }
+        self.problems.push(self.locator.problem(key, message));
+    }
+
+    fn config(&mut self, config: &Value) {
+        let Some(root) = config.as_mapping() else {
+            self.report(&[], "The config is not a mapping".to_string());
+            return;
+        };
+        self.unknown_keys::<ProjectConfig>(root, &[], "the config");
+        let base = Value::from(
+            serde_yaml::from_str::<Mapping>("{pipeline: {jobs: {}}, branches: []}").unwrap(),
+        );
+        self.wrong_types::<ProjectConfig>(root, &base, &[], &["pipeline"]);
+        if let Some(history) = root.get("history").and_then(Value::as_mapping) {
+            self.unknown_keys::<HistoryRetention>(history, &key(&["history"]), "history");
+        }
+
+        let Some(pipeline) = root.get("pipeline").and_then(Value::as_mapping) else {
+            if root.get("pipeline").is_none() {
+                self.report(&[], "The config has no pipeline".to_string());
+            }
+            return;
+        };
+        self.pipeline(pipeline);
+
+        // the checks of `load_config`, on the jobs without problem so far
+        let mut config = config.clone();
+        for at in &self.dropped {
+            remove(&mut config, at);
+        }
+        let config = match serde_yaml::from_value::<ProjectConfig>(config) {
+            Ok(config) => config,
+            Err(e) => {
+                if self.problems.is_empty() {
+                    self.report(&[], e.to_string());
+                }
+                return;
+            }
+        };
+        self.jobs(&config);
+    }
+
+    fn pipeline(&mut self, pipeline: &Mapping) {
+        let at = key(&["pipeline"]);
+        self.unknown_keys::<Pipeline>(pipeline, &at, "the pipeline");
+        let base = Value::from(serde_yaml::from_str::<Mapping>("{jobs: {}}").unwrap());
+        self.wrong_types::<Pipeline>(pipeline, &base, &at, &["jobs", "notifications"]);
+        if let Some(notifications) = pipeline.get("notifications").and_then(Value::as_mapping) {
+            self.notifications(notifications);
+        }
+
+        let Some(jobs) = pipeline.get("jobs").and_then(Value::as_mapping) else {
+            if pipeline.get("jobs").is_none() {
+                self.report(&at, "The pipeline has no jobs".to_string());
+            }
+            return;
+        };
+        let names: HashSet<&str> = jobs.keys().filter_map(Value::as_str).collect();
+        for (name, job) in jobs {
+            let Some(name) = name.as_str() else {
+                continue;
+            };
+            let at = key(&["pipeline", "jobs", name]);
+            match job.as_mapping() {
+                Some(job) => self.job(name, job, &names),
+                None => self.report(&at, format!("Job '{name}' is not a mapping")),
+            }
+        }
+    }
+
+    fn notifications(&mut self, notifications: &Mapping) {
+        let at = key(&["pipeline", "notifications"]);
+        self.unknown_keys::<Notification>(notifications, &at, "the notifications");
+        let base = Value::from(serde_yaml::from_str::<Mapping>("{on: [], channels: []}").unwrap());
+        self.wrong_types::<Notification>(notifications, &base, &at, &["on", "channels"]);
+        if let Some(on) = notifications.get("on").and_then(Value::as_sequence) {
+            for (i, event) in on.iter().enumerate() {
+                if let Err(e) = serde_yaml::from_value::<NotificationEvent>(event.clone()) {
+                    self.report(
+                        &key(&["pipeline", "notifications", "on", &i.to_string()]),
+                        format!("Invalid notification event: {e}"),
+                    );
+                }
+            }
+        }
+        let channels = notifications.get("channels").and_then(Value::as_sequence);
+        for (i, channel) in channels.into_iter().flatten().enumerate() {
+            let at = key(&["pipeline", "notifications", "channels", &i.to_string()]);
+            let Some(channel) = channel.as_mapping() else {
+                self.report(
+                    &at,
+                    format!("Notification channel {} is not a mapping", i + 1),
+                );
+                continue;
+            };
+            self.unknown_keys::<ConfChannel>(channel, &at, "a channel");
+            let base = Value::from(
+                serde_yaml::from_str::<Mapping>("{service: discord, url: ''}").unwrap(),
+            );
+            self.wrong_types::<ConfChannel>(channel, &base, &at, &[]);
+        }
+    }
+
+    fn job(&mut self, name: &str, job: &Mapping, names: &HashSet<&str>) {
+        let at = key(&["pipeline", "jobs", name]);
+        let job_key = |k: &str| [at.clone(), key(&[k])].concat();
+        let what = format!("job '{name}'");
+        self.unknown_keys::<Job>(job, &at, &what);
+        let base = Value::from(serde_yaml::from_str::<Mapping>("{steps: []}").unwrap());
+        self.wrong_types::<Job>(job, &base, &at, &["steps", "retry", "artifacts", "cache"]);
+        self.nested::<Retry>(job, &at, "retry", "{attempts: 1}", &what);
+        self.nested::<Artifacts>(job, &at, "artifacts", "{paths: []}", &what);
+        self.nested::<Cache>(job, &at, "cache", "{key: '', paths: []}", &what);
+
+        if let Some(needs) = job.get("needs").and_then(Value::as_sequence) {
+            for (i, dep) in needs.iter().enumerate() {
+                if let Some(dep) = dep.as_str()
+                    && !names.contains(dep)
+                {
+                    self.report(
+                        &[job_key("needs"), key(&[&i.to_string()])].concat(),
+                        format!("Job '{name}' depends on unknown job '{dep}'"),
+                    );
+                }
+            }
+        }
+        if let Some(pipe) = job.get("pipe").and_then(Value::as_str)
+            && !pipe.is_empty()
+            && (pipe == name || !names.contains(pipe))
+        {
+            self.report(
+                &job_key("pipe"),
+                match pipe == name {
+                    true => format!("Job '{name}' cannot pipe into itself"),
+                    false => format!("Job '{name}' pipes into unknown job '{pipe}'"),
+                },
+            );
+        }
+
+        match job.get("steps") {
+            None => self.report(&at, format!("Job '{name}' has no steps")),
+            Some(Value::Sequence(steps)) if steps.is_empty() => {
+                self.report(&job_key("steps"), format!("Job '{name}' has no steps"))
+            }
+            Some(Value::Sequence(steps)) => {
+                for (i, step) in steps.iter().enumerate() {
+                    let at = [job_key("steps"), key(&[&i.to_string()])].concat();
+                    let Some(step) = step.as_mapping() else {
+                        self.report(
+                            &at,
+                            format!(
+                                "Job '{name}' step {} is not a mapping, e.g. `- cmd: ...`",
+                                i + 1
+                            ),
+                        );
+                        continue;
+                    };
+                    let what = format!("step {} of {what}", i + 1);
+                    self.unknown_keys::<Cmd>(step, &at, &what);
+                    let base = Value::Mapping(Mapping::new());
+                    self.wrong_types::<Cmd>(step, &base, &at, &["retry"]);
+                    self.nested::<Retry>(step, &at, "retry", "{attempts: 1}", &what);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// The checks of `load_config` on the expanded jobs whose own keys are valid
+    fn jobs(&mut self, config: &ProjectConfig) {
+        let jobs_key = key(&["pipeline", "jobs"]);
+        let mut expanded = config.clone();
+        let reported = self.problems.len();
+        // a matrix is expanded alone first, so that its problem is reported on its job
+        for (name, job) in &config.pipeline.jobs {
+            if job.matrix.is_none() || self.faulty_jobs.contains(name) {
+                continue;
+            }
+            let mut alone = config.clone();
+            alone.pipeline.jobs.retain(|n, _| n == name);
+            if let Some(job) = alone.pipeline.jobs.get_mut(name) {
+                job.needs.clear();
+                job.pipe.clear();
+            }
+            if let Err(e) = expand_matrix(&mut alone) {
+                self.report(
+                    &[jobs_key.clone(), key(&[name, "matrix"])].concat(),
+                    e.to_string(),
+                );
+            }
+        }
+        if self.problems.len() > reported {
+            return;
+        }
+        if let Err(e) = expand_matrix(&mut expanded) {
+            self.report(&jobs_key, e.to_string());
+            return;
+        }
+
+        let pipeline = &expanded.pipeline;
+        if let Err(e) = check_paths(pipeline) {
+            self.report(&key(&["pipeline", "paths"]), e.to_string());
+        }
+        let mut names: Vec<&String> = pipeline.jobs.keys().collect();
+        names.sort();
+        for name in names {
+            // the jobs of a matrix are reported on the job they come from
+            let defined = match name.split_once(" (") {
+                Some((base, _)) if config.pipeline.jobs.contains_key(base) => base,
+                _ => name.as_str(),
+            };
+            if self.faulty_jobs.contains(defined) {
+                continue;
+            }
+            if let Err(e) = check_job(name, &pipeline.jobs[name], pipeline) {
+                self.report(&[jobs_key.clone(), key(&[defined])].concat(), e.to_string());
+            }
+        }
+        if self.problems.is_empty()
+            && let Err(e) = check_cycles(pipeline)
+        {
+            self.report(&jobs_key, e.to_string());
+        }
+    }
+
+    /// The keys of `mapping` that are not fields of `T`
+    fn unknown_keys<T: DeserializeOwned>(&mut self, mapping: &Mapping, at: &[String], what: &str) {
+        let known = fields::<T>();
+        for k in mapping.keys() {
+            let Some(k) = k.as_str() else {
+                self.report(at, format!("Non-string key {k:?} in {what}"));
+                continue;
+            };
+            if known.iter().any(|f| f == k) {
+                continue;
+            }
+            let mut message = format!("Unknown key `{k}` in {what}");
+            if let Some(close) = known.iter().find(|known| is_typo(k, known)) {
+                message.push_str(&format!(", did you mean `{close}`?"));
+            }
+            let at = [at.to_vec(), key(&[k])].concat();
+            self.report(&at, message);
+            self.dropped.push(at);
+        }
+    }
+
+    /// The `field` of `parent` when it is a mapping: its unknown keys, and the ones holding a value `T` rejects,
+    /// tried on top of `base`
+    fn nested<T: DeserializeOwned>(
+        &mut self,
+        parent: &Mapping,
+        at: &[String],
+        field: &str,
+        base: &str,
+        what: &str,
+    ) {
+        let Some(mapping) = parent.get(field).and_then(Value::as_mapping) else {
+            return;
+        };
+        let at = [at.to_vec(), key(&[field])].concat();
+        self.unknown_keys::<T>(mapping, &at, &format!("the {field} of {what}"));
+        let base = Value::from(serde_yaml::from_str::<Mapping>(base).unwrap());
+        self.wrong_types::<T>(mapping, &base, &at, &[]);
+    }
+
+    /// Every key of `mapping` that is a field of `T` holding a value `T` rejects, each one tried alone on top of `base`.
+    /// The keys in `nested` are checked on their own when they hold a mapping or a list.
+    fn wrong_types<T: DeserializeOwned>(
+        &mut self,
+        mapping: &Mapping,
+        base: &Value,
+        at: &[String],
+        nested: &[&str],
+    ) {
+        let known = fields::<T>();
+        for (k, v) in mapping {
+            let Some(name) = k.as_str().filter(|k| known.iter().any(|f| f == k)) else {
+                continue;
+            };
+            if nested.contains(&name) && (v.is_mapping() || v.is_sequence()) {
+                continue;
+            }
+            let mut probe = base.clone();
+            if let Some(probe) = probe.as_mapping_mut() {
+                probe.insert(k.clone(), v.clone());
+            }
+            if let Err(e) = serde_yaml::from_value::<T>(probe) {
+                let at = [at.to_vec(), key(&[name])].concat();
+                self.report(&at, format!("Invalid `{name}`: {e}"));
+                self.dropped.push(at);
+            }
+        }
+    }
+}
+
+/// The fields of `T` with their aliases, as listed by the error serde gives for an unknown field,
+/// so that they cannot fall behind the structs of the config
+fn fields<T: DeserializeOwned>() -> Vec<String> {
+    let mut probe = Mapping::new();
+    probe.insert(Value::from(""), Value::Null);
+    let Err(e) = serde_yaml::from_value::<T>(Value::Mapping(probe)) else {
+        return Vec::new();
+    };
+    let message = e.to_string();
+    let Some((_, expected)) = message.split_once(", expected ") else {
+        return Vec::new();
+    };
+    expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(str::to_string)
+        .collect()
+}
+
+fn key(parts: &[&str]) -> Vec<String> {
+    parts.iter().map(|p| p.to_string()).collect()
+}
+
+/// Removes the key or list item at `at` from `value`, if present
+fn remove(value: &mut Value, at: &[String]) {
+    let Some((last, parents)) = at.split_last() else {
+        return;
+    };
+    let mut node = value;
+    for part in parents {
+        let next = match node {
+            Value::Mapping(mapping) => mapping.get_mut(part.as_str()),
+            Value::Sequence(items) => part.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+            _ => None,
+        };
+        let Some(next) = next else {
+            return;
+        };
+        node = next;
+    }
+    if let Value::Mapping(mapping) = node {
+        mapping.remove(last.as_str());
+    }
+}
+
+/// One letter added, removed, replaced or two letters swapped
+fn is_typo(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (da, db) = (a.len() - prefix - suffix, b.len() - prefix - suffix);
+    match (da, db) {
+        (0, 1) | (1, 0) | (1, 1) => true,
+        (2, 2) => a[prefix] == b[prefix + 1] && a[prefix + 1] == b[prefix],
+        _ => false,
+    }
+}
+
+/// Line and column of the keys and list items of a file, by path
+type Positions = HashMap<Vec<String>, (usize, usize)>;
+
+/// Positions of the keys and list items of the files of a config
+struct Locator {
+    files: Vec<(PathBuf, Positions)>,
+}
+
+impl Locator {
+    fn new(files: &[PathBuf]) -> Self {
+        Locator {
+            files: files
+                .iter()
+                .map(|f| {
+                    let text = fs::read_to_string(f).unwrap_or_default();
+                    (f.clone(), positions(&text))
+                })
+                .collect(),
+        }
+    }
+
+    /// `message` at `key`, else at its closest parent found, else at the start of the main file
+    fn problem(&self, key: &[String], message: String) -> Problem {
+        for len in (1..=key.len()).rev() {
+            for (file, positions) in &self.files {
+                if let Some(&(line, column)) = positions.get(&key[..len]) {
+                    return Problem {
+                        file: file.clone(),
+                        line,
+                        column,
+                        message,
+                    };
+                }
+            }
+        }
+        Problem {
+            file: self
+                .files
+                .first()
+                .map(|(f, _)| f.clone())
+                .unwrap_or_default(),
+            line: 1,
+            column: 1,
+            message,
+        }
+    }
+}
+
+/// Line and column (1-based) of every key and list item of a block-style YAML document
+fn positions(text: &str) -> Positions {
+    struct Node {
+        column: usize,
+        path: Vec<String>,
+        item: bool,
+        items: usize,
+    }
+    let mut found = HashMap::new();
+    let mut stack: Vec<Node> = Vec::new();
+    let mut root_items = 0;
+    // column of the key whose value is a `|` or `>` block, its lines are skipped
+    let mut block: Option<usize> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let mut rest = line.trim_start();
+        let mut column = line.len() - rest.len();
+        if let Some(owner) = block {
+            if rest.is_empty() || column > owner {
+                continue;
+            }
+            block = None;
+        }
+        if rest.is_empty() || rest.starts_with('#') || line.starts_with("---") {
+            continue;
+        }
+
+        loop {
+            let is_item = rest == "-" || rest.starts_with("- ");
+            let entry = if is_item { None } else { split_key(rest) };
+            if !is_item && entry.is_none() {
+                break;
+            }
+            while let Some(top) = stack.last() {
+                if top.column > column || (top.column == column && (top.item || !is_item)) {
+                    stack.pop();
+                } else {
+                    break;
+                }
+            }
+            let mut path = stack.last().map(|n| n.path.clone()).unwrap_or_default();
+
+            let Some((key, value)) = entry else {
+                let counter = match stack.last_mut() {
+                    Some(parent) => &mut parent.items,
+                    None => &mut root_items,
+                };
+                path.push(counter.to_string());
+                *counter += 1;
+                found.entry(path.clone()).or_insert((index + 1, column + 1));
+                stack.push(Node {
+                    column,
+                    path,
+                    item: true,
+                    items: 0,
+                });
+                let after = rest[1..].trim_start();
+                column += rest.len() - after.len();
+                rest = after;
+                if rest.is_empty() {
+                    break;
+                }
+                continue;
+            };
+
+            path.push(key);
+            found.entry(path.clone()).or_insert((index + 1, column + 1));
+            stack.push(Node {
+                column,
+                path,
+                item: false,
+                items: 0,
+            });
+            let value = value.split(" #").next().unwrap_or_default().trim();
+            if value.starts_with('|') || value.starts_with('>') {
+                block = Some(column);
+            }
+            break;
+        }
+    }
+    found
+}
+
+/// The key of a `key: value` line and what follows its colon
+fn split_key(line: &str) -> Option<(String, &str)> {
+    if let Some(quote) = line.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        let end = line[1..].find(quote)? + 1;
+        let after = &line[end + 1..];
+        let value = after.strip_prefix(':')?;
+        return (value.is_empty() || value.starts_with(' '))
+            .then(|| (line[1..end].to_string(), value));
+    }
+    if line.starts_with(['[', '{', '&', '*', '!', '|', '>', '%', '@', '`', '#']) {
+        return None;
+    }
+    let colon = line
+        .char_indices()
+        .find(|&(i, c)| c == ':' && line[i + 1..].chars().next().is_none_or(|n| n == ' '))
+        .map(|(i, _)| i)?;
+    let key = line[..colon].trim_end();
+    if key.contains(" #") {
+        return None;
+    }
+    Some((key.to_string(), &line[colon + 1..]))
+}
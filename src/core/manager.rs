@@ -4,6 +4,7 @@ use std::{os::unix::fs::PermissionsExt, path::Path, sync::Arc, time::Duration};
 use tokio::{
     io::{AsyncBufReadExt, BufReader, split},
     net::UnixListener,
+    task::JoinHandle,
     time::interval,
 };
 
@@ -14,7 +15,10 @@ use crate::{
         watcher::{WatchContext, watch_once},
     },
     daemon::server::{DaemonRequest, handle_request},
-    exec::pipeline::run_pipeline,
+    exec::{
+        metrics::{RunStatus, RunTrigger},
+        pipeline::run_pipeline,
+    },
     git::repo::Repo,
 };
 
@@ -26,27 +30,39 @@ pub async fn supervisor_loop(state: Arc<AppState>, interval_secs: u64) {
         ticker.tick().await;
 
         let to_update = collect_updates(&state).await;
-        let mut dirty = false;
 
         for (id, _new_commit) in to_update {
             // update_commit(&state, &id, new_commit.clone()).await;
             if let Some(ctx) = get_watch_ctx(&state, &id).await {
-                match run_pipeline(Arc::new(ctx)).await {
-                    Ok(_) => {
-                        println!("[{id}] ✅ Update succeeded");
-                        dirty = true;
-                    }
-                    Err(e) => {
-                        eprintln!("[{id}] ❌ Update failed => {e}");
-                    }
-                }
+                // runs on the worker pool, the next tick is not delayed by a slow pipeline
+                dispatch_run(Arc::clone(&state), ctx, RunTrigger::Poll);
             }
         }
+    }
+}
 
-        if dirty && let Err(e) = state.save_to_disk().await {
-            eprintln!("❌ Failed to save state: {e}");
+/// Runs the pipeline of a watch in its own task, the state is saved once it succeeded.
+pub fn dispatch_run(
+    state: Arc<AppState>,
+    ctx: WatchContext,
+    trigger: RunTrigger,
+) -> JoinHandle<anyhow::Result<()>> {
+    tokio::spawn(async move {
+        let id = ctx.id.clone();
+        match run_tracked(&state, ctx, trigger).await {
+            Ok(_) => {
+                println!("[{id}] ✅ Update succeeded");
+                if let Err(e) = state.save_to_disk().await {
+                    eprintln!("❌ Failed to save state: {e}");
+                }
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("[{id}] ❌ Update failed => {e}");
+                Err(e)
+            }
         }
-    }
+    })
 }
 
 /// Loop through the watches, call `watch_once` on each one,
@@ -72,8 +88,7 @@ async fn collect_updates(state: &Arc<AppState>) -> Vec<(String, String)> {
                     ))
                     .await
                     .ok(); // ignore log fail
-                //for the moment i ignore the error but in the futur i have to handle it correctly
-                Repo::switch_branch(ctx, &ctx.repo.branches.last_name).ok();
+
                 to_update.push((id.clone(), new_commit));
             }
             Ok(None) => {}
@@ -84,6 +99,34 @@ async fn collect_updates(state: &Arc<AppState>) -> Vec<(String, String)> {
     to_update
 }
 
+/// Sync the project dir to the commit detected by the watcher, return false if it failed.
+async fn sync_working_tree(ctx: &WatchContext) -> bool {
+    let commit = &ctx.repo.branches.last_commit;
+    match Repo::sync_to_commit(&ctx.project_dir, &ctx.repo.branches.last_name, commit) {
+        Ok(outcome) => {
+            ctx.logger
+                .info(&format!(
+                    "Working tree synced to [{}] ({outcome})",
+                    format_commit(commit)
+                ))
+                .await
+                .ok();
+            true
+        }
+        Err(e) => {
+            eprintln!("[{}] ❌ Sync failed: {e}", ctx.id);
+            ctx.logger
+                .error(&format!(
+                    "Pipeline not started, failed to sync [{}]: {e}",
+                    format_commit(commit)
+                ))
+                .await
+                .ok();
+            false
+        }
+    }
+}
+
 /// Updates the commit stored in the state for a given watch.
 async fn update_commit(state: &Arc<AppState>, id: &str, new_commit: String) -> anyhow::Result<()> {
     let mut watches_write = state.watches.write().await;
@@ -93,6 +136,60 @@ async fn update_commit(state: &Arc<AppState>, id: &str, new_commit: String) -> a
     Ok(())
 }
 
+/// Runs the pipeline of a watch under the run lock of the project,
+/// overlapping runs are handled by the `concurrency` policy of its config.
+/// The pipeline then waits for a free worker of the daemon pool,
+/// and is registered in the state as active while it executes.
+pub async fn run_tracked(
+    state: &Arc<AppState>,
+    mut ctx: WatchContext,
+    trigger: RunTrigger,
+) -> anyhow::Result<()> {
+    let slot = state.run_slot(&ctx.id).await;
+    let (decision, permit) = slot.acquire(ctx.config.concurrency, &ctx.logger).await;
+    let Some(permit) = permit else {
+        println!("[{}] ⏭ Run {decision}", ctx.id);
+        return Ok(());
+    };
+
+    // the working tree is only touched once the previous run of the project is over
+    if trigger == RunTrigger::Poll && !sync_working_tree(&ctx).await {
+        return Err(anyhow::anyhow!("Failed to sync the working tree"));
+    }
+
+    let worker = match state.pool.try_acquire() {
+        Some(worker) => worker,
+        None => {
+            ctx.logger
+                .info(&format!(
+                    "All {} workers are busy, waiting for a free one",
+                    state.pool.limit()
+                ))
+                .await
+                .ok();
+            state.pool.acquire().await
+        }
+    };
+
+    let id = ctx.id.clone();
+    let branch = ctx.repo.branches.current().to_string();
+    let project_dir = ctx.project_dir.clone();
+    ctx.cancel = permit.cancel.clone();
+    state.start_active(&id, trigger).await;
+    let result = run_pipeline(Arc::new(ctx), trigger).await;
+    state.finish_active(&id).await;
+
+    // read while the working tree is still locked by the permit
+    if let Ok(RunStatus::Succeeded) = result
+        && let Ok(commit) = Repo::head_commit(&project_dir)
+    {
+        state.record_build(&id, &branch, &commit).await;
+    }
+    drop(worker);
+    drop(permit);
+    result.map(|_| ())
+}
+
 pub async fn get_watch_ctx(state: &Arc<AppState>, id: &str) -> Option<WatchContext> {
     let watches_read: tokio::sync::RwLockReadGuard<
         '_,
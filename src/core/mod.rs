@@ -1,4 +1,6 @@
 pub mod id;
 pub mod manager;
+pub mod pool;
+pub mod run_lock;
 pub mod state;
 pub mod watcher;
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub const DEFAULT_MAX_PARALLEL_RUNS: usize = 4;
+
+/// Daemon-wide limit of pipelines running at the same time, shared by every project.
+/// Set with `FLEET_MAX_PARALLEL_RUNS` (4 by default).
+#[derive(Debug)]
+pub struct WorkerPool {
+    limit: usize,
+    workers: Arc<Semaphore>,
+}
+
+impl WorkerPool {
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            limit,
+            workers: Arc::new(Semaphore::new(limit)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let limit = std::env::var("FLEET_MAX_PARALLEL_RUNS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_PARALLEL_RUNS);
+        WorkerPool::new(limit)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Wait for a free worker, it is given back when the permit is dropped.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        Arc::clone(&self.workers)
+            .acquire_owned()
+            .await
+            .expect("worker pool is never closed")
+    }
+
+    pub fn try_acquire(&self) -> Option<OwnedSemaphorePermit> {
+        Arc::clone(&self.workers).try_acquire_owned().ok()
+    }
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        WorkerPool::from_env()
+    }
+}
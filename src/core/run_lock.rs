@@ -0,0 +1,162 @@
+use std::{
+    fmt,
+    sync::{
+        Arc, Mutex as StdMutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::ConcurrencyPolicy, log::logger::Logger};
+
+/// What happened to a run request, according to the concurrency policy of the project.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunDecision {
+    /// nothing was running, the run started right away
+    Started,
+    /// the run waited for the previous one to finish
+    Queued,
+    /// the previous run was cancelled in favour of this one
+    Superseded,
+    /// the run was dropped
+    Skipped,
+}
+
+impl fmt::Display for RunDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunDecision::Started => write!(f, "started"),
+            RunDecision::Queued => write!(f, "queued"),
+            RunDecision::Superseded => write!(f, "superseded"),
+            RunDecision::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/// Run lock of a project: only one pipeline of a project runs at a time.
+#[derive(Debug, Default)]
+pub struct RunSlot {
+    lock: Arc<Mutex<()>>,
+    current: StdMutex<Option<CancellationToken>>,
+    waiting: AtomicUsize,
+    /// incremented on every request, lets a queued run see it has been superseded
+    requests: AtomicU64,
+    last_decision: StdMutex<Option<RunDecision>>,
+}
+
+/// Held for the whole run, frees the slot when dropped.
+pub struct RunPermit {
+    _guard: OwnedMutexGuard<()>,
+    slot: Arc<RunSlot>,
+    pub cancel: CancellationToken,
+}
+
+impl Drop for RunPermit {
+    fn drop(&mut self) {
+        *self.slot.current.lock().unwrap() = None;
+    }
+}
+
+impl RunSlot {
+    /// Wait for the right to run, as the policy says.
+    /// Returns no permit when the run must not happen.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        policy: ConcurrencyPolicy,
+        logger: &Logger,
+    ) -> (RunDecision, Option<RunPermit>) {
+        let ticket = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+
+        let (decision, guard) = match self.lock.clone().try_lock_owned() {
+            Ok(guard) => (RunDecision::Started, guard),
+            Err(_) => match policy {
+                ConcurrencyPolicy::Skip => {
+                    logger
+                        .warning("A run is already in progress, run skipped (concurrency: skip)")
+                        .await
+                        .ok();
+                    self.record(RunDecision::Skipped);
+                    return (RunDecision::Skipped, None);
+                }
+                ConcurrencyPolicy::Queue => {
+                    logger
+                        .info("A run is already in progress, run queued (concurrency: queue)")
+                        .await
+                        .ok();
+                    self.record(RunDecision::Queued);
+                    (RunDecision::Queued, self.wait().await)
+                }
+                ConcurrencyPolicy::CancelInProgress => {
+                    logger
+                        .warning("A run is already in progress, cancelling it for the newest one (concurrency: cancel-in-progress)")
+                        .await
+                        .ok();
+                    self.record(RunDecision::Superseded);
+                    self.cancel_current();
+                    let guard = self.wait().await;
+                    if self.requests.load(Ordering::SeqCst) != ticket {
+                        logger
+                            .warning("Queued run superseded by a newer one, run skipped")
+                            .await
+                            .ok();
+                        return (RunDecision::Skipped, None);
+                    }
+                    (RunDecision::Superseded, guard)
+                }
+            },
+        };
+
+        if decision == RunDecision::Started {
+            self.record(decision);
+        }
+        let cancel = CancellationToken::new();
+        *self.current.lock().unwrap() = Some(cancel.clone());
+        (
+            decision,
+            Some(RunPermit {
+                _guard: guard,
+                slot: Arc::clone(self),
+                cancel,
+            }),
+        )
+    }
+
+    async fn wait(&self) -> OwnedMutexGuard<()> {
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        let guard = self.lock.clone().lock_owned().await;
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        guard
+    }
+
+    fn record(&self, decision: RunDecision) {
+        *self.last_decision.lock().unwrap() = Some(decision);
+    }
+
+    /// Cancel the running pipeline, return false if nothing is running.
+    pub fn cancel_current(&self) -> bool {
+        match self.current.lock().unwrap().as_ref() {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.current.lock().unwrap().is_some()
+    }
+
+    /// runs waiting for the slot
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    pub fn last_decision(&self) -> Option<RunDecision> {
+        *self.last_decision.lock().unwrap()
+    }
+}
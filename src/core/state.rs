@@ -1,8 +1,13 @@
 #![allow(dead_code)]
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use crate::{core::watcher::WatchContext, log::logger::Logger};
+use crate::{
+    core::{pool::WorkerPool, run_lock::RunSlot, watcher::WatchContext},
+    exec::metrics::RunTrigger,
+    log::logger::Logger,
+};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::{fs, sync::RwLock};
 
@@ -10,6 +15,20 @@ use tokio::{fs, sync::RwLock};
 #[derive(Default)]
 pub struct AppState {
     pub watches: RwLock<HashMap<String, WatchContext>>,
+    /// run lock of each project, by project id
+    pub runs: RwLock<HashMap<String, Arc<RunSlot>>>,
+    /// workers shared by the pipelines of every project
+    pub pool: WorkerPool,
+    /// pipelines currently executing on a worker, by project id
+    pub active: RwLock<HashMap<String, ActiveRun>>,
+}
+
+/// A pipeline executing on a worker of the pool.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ActiveRun {
+    pub project_id: String,
+    pub trigger: RunTrigger,
+    pub started_at: DateTime<Utc>,
 }
 
 impl AppState {
@@ -18,13 +37,16 @@ impl AppState {
         let mut watches: HashMap<String, WatchContext> = HashMap::new();
 
         for mut ctx in registry.projects {
-            let logger = Logger::new(&ctx.log_path()).await?;
+            let logger = Logger::new(&ctx.log_path()).await?.for_project(&ctx.id);
             ctx.logger = logger;
             watches.insert(ctx.id.clone(), ctx);
         }
 
         Ok(Self {
             watches: RwLock::new(watches),
+            runs: RwLock::new(HashMap::new()),
+            pool: WorkerPool::from_env(),
+            active: RwLock::new(HashMap::new()),
         })
     }
 
@@ -36,6 +58,52 @@ impl AppState {
         save_watches(&registry).await
     }
 
+    /// Run lock of a project, created on first use.
+    pub async fn run_slot(&self, id: &str) -> Arc<RunSlot> {
+        if let Some(slot) = self.runs.read().await.get(id) {
+            return Arc::clone(slot);
+        }
+        Arc::clone(self.runs.write().await.entry(id.to_string()).or_default())
+    }
+
+    /// Cancel the running pipeline of the project, return false if nothing is running.
+    pub async fn cancel_run(&self, id: &str) -> bool {
+        match self.runs.read().await.get(id) {
+            Some(slot) => slot.cancel_current(),
+            None => false,
+        }
+    }
+
+    pub async fn start_active(&self, project_id: &str, trigger: RunTrigger) {
+        self.active.write().await.insert(
+            project_id.to_string(),
+            ActiveRun {
+                project_id: project_id.to_string(),
+                trigger,
+                started_at: Utc::now(),
+            },
+        );
+    }
+
+    /// Remember the last successfully built commit of a branch, base of the next `paths` diff.
+    pub async fn record_build(&self, project_id: &str, branch: &str, commit: &str) {
+        if let Some(ctx) = self.watches.write().await.get_mut(project_id) {
+            ctx.last_built
+                .insert(branch.to_string(), commit.to_string());
+        }
+    }
+
+    pub async fn finish_active(&self, project_id: &str) {
+        self.active.write().await.remove(project_id);
+    }
+
+    /// Pipelines currently executing, oldest first.
+    pub async fn active_runs(&self) -> Vec<ActiveRun> {
+        let mut runs: Vec<ActiveRun> = self.active.read().await.values().cloned().collect();
+        runs.sort_by_key(|r| r.started_at);
+        runs
+    }
+
     // -----------------------
     // Static methods
     // -----------------------
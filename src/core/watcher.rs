@@ -1,10 +1,11 @@
 #![allow(dead_code)]
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::{Ok, Result};
 use dirs::home_dir;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
+use tokio_util::sync::CancellationToken;
 
 #[allow(unused_imports)]
 use crate::git::{remote::get_remote_branch_hash, repo::Repo};
@@ -20,6 +21,12 @@ pub struct WatchContext {
     pub paused: bool,
     #[serde(skip, default = "Logger::placeholder")]
     pub logger: Logger,
+    /// cancelled by `fleet cancel`, shared by every task of the running pipeline
+    #[serde(skip)]
+    pub cancel: CancellationToken,
+    /// last successfully built commit of each branch, base of the `paths` filters
+    #[serde(default)]
+    pub last_built: HashMap<String, String>,
 }
 
 pub struct WatchContextBuilder {
@@ -44,7 +51,7 @@ impl WatchContextBuilder {
 
     pub async fn build(self) -> Result<WatchContext, anyhow::Error> {
         // Création du logger avec les infos du contexte partiel
-        let logger = Logger::new(&self.log_path()).await?;
+        let logger = Logger::new(&self.log_path()).await?.for_project(&self.id);
 
         // Construction du WatchContext complet
         Ok(WatchContext {
@@ -54,6 +61,8 @@ impl WatchContextBuilder {
             id: self.id,
             paused: self.paused,
             logger,
+            cancel: CancellationToken::new(),
+            last_built: HashMap::new(),
         })
     }
 
@@ -117,7 +126,7 @@ pub fn watch_once(repo: &mut Repo) -> Result<Option<String>, anyhow::Error> {
                 println!("new commit detected: {} -> {}", b.last_commit, remote_hash);
                 return Ok(Some(remote_hash));
             }
-            return Ok(None);
+            Ok(None)
         })?;
 
         let first_new = res.into_iter().flatten().next();
@@ -1,20 +1,26 @@
 #![allow(dead_code)]
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use crate::{
-    config::ProjectConfig,
+    config::{ConcurrencyPolicy, ProjectConfig},
     core::{
         id::short_id,
-        manager::get_watch_ctx,
+        manager::{get_watch_ctx, run_tracked},
+        run_lock::RunDecision,
         state::{AppState, get_id_by_name, get_name_by_id},
         watcher::{WatchContext, WatchContextBuilder},
     },
     daemon::utiles::extract_repo_path,
-    exec::{metrics::ExecMetrics, pipeline::run_pipeline},
+    exec::{
+        artifacts::{self, ArtifactInfo},
+        cache,
+        metrics::{ExecMetrics, FailureReason, JobMetrics, JobStatus, RunStatus, RunTrigger},
+    },
     git::repo::Repo,
     log::logger::Logger,
 };
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::{
     fs::{File, OpenOptions},
@@ -38,6 +44,11 @@ pub enum DaemonRequest {
         id: String,
     },
 
+    #[serde(rename = "cancel_run")]
+    CancelRun {
+        id: String,
+    },
+
     #[serde(rename = "stop_watch")]
     StopWatch {
         id: String,
@@ -62,6 +73,30 @@ pub enum DaemonRequest {
     LogsWatches {
         id: String,
         f: bool,
+        #[serde(default)]
+        run: Option<String>,
+        #[serde(default)]
+        job: Option<String>,
+    },
+
+    #[serde(rename = "list_runs")]
+    ListRuns {
+        id: String,
+    },
+
+    #[serde(rename = "get_run")]
+    GetRun {
+        run_id: String,
+    },
+
+    #[serde(rename = "list_artifacts")]
+    ListArtifacts {
+        run_id: String,
+        #[serde(default)]
+        job: Option<String>,
+        /// directory the client copies the artifacts to
+        #[serde(default)]
+        extract: Option<String>,
     },
 
     None,
@@ -76,6 +111,75 @@ pub struct WatchInfo {
     pub repo_name: String,
     pub id: String,
     pub paused: bool,
+    #[serde(default)]
+    pub concurrency: ConcurrencyPolicy,
+    /// executing on a worker
+    #[serde(default)]
+    pub running: bool,
+    /// holds the run lock of the project but waits for a free worker
+    #[serde(default)]
+    pub pending: bool,
+    /// runs queued behind the current one
+    #[serde(default)]
+    pub waiting: usize,
+    #[serde(default)]
+    pub last_decision: Option<RunDecision>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RunInfo {
+    pub run_id: String,
+    pub project_name: String,
+    pub commit: String,
+    pub branch: String,
+    pub trigger: RunTrigger,
+    pub status: RunStatus,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: Option<u128>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct JobInfo {
+    pub name: String,
+    pub status: JobStatus,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: Option<u128>,
+    pub cpu_usage: f32,
+    pub mem_usage_kb: u64,
+    #[serde(default)]
+    pub retries: usize,
+    #[serde(default)]
+    pub reason: Option<FailureReason>,
+}
+
+impl From<&ExecMetrics> for RunInfo {
+    fn from(m: &ExecMetrics) -> Self {
+        Self {
+            run_id: m.run_id.clone(),
+            project_name: m.project_name.clone(),
+            commit: m.commit.clone(),
+            branch: m.branch.clone(),
+            trigger: m.trigger,
+            status: m.status,
+            started_at: m.started_at,
+            duration_ms: m.duration_ms,
+        }
+    }
+}
+
+impl From<&JobMetrics> for JobInfo {
+    fn from(j: &JobMetrics) -> Self {
+        Self {
+            name: j.name.clone(),
+            status: j.status.clone(),
+            started_at: j.started_at,
+            duration_ms: j.duration_ms,
+            cpu_usage: j.cpu_usage,
+            mem_usage_kb: j.mem_usage_kb,
+            retries: j.retries(),
+            reason: j.reason,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -83,7 +187,11 @@ pub enum DaemonResponse {
     Success(String),
     Error(String),
     ListWatches(Vec<WatchInfo>),
-    LogWatch(String, bool),
+    LogWatch(Vec<String>, bool),
+    ListRuns(Vec<RunInfo>),
+    ShowRun(RunInfo, Vec<JobInfo>),
+    /// artifacts of a run and the directory to extract them to, if any
+    ListArtifacts(Vec<ArtifactInfo>, Option<String>),
     Ignore,
     None,
 }
@@ -99,17 +207,44 @@ pub async fn get_log_file(ctx: &WatchContext) -> Result<File> {
     Ok(log_file)
 }
 
-async fn get_logs_by_id(id: &str) -> Result<String> {
-    let log_path = WatchContext::log_path_by_id(id);
+/// Resolves the log files to display for a project:
+/// - no filter: the project log (daemon events and every job output)
+/// - `run`: every job log of this run, `run` + `job`: only this job
+/// - `job` alone: this job in the latest run
+async fn get_logs_by_id(id: &str, run: Option<&str>, job: Option<&str>) -> Result<Vec<String>> {
+    let paths: Vec<PathBuf> = match (run, job) {
+        (None, None) => vec![WatchContext::log_path_by_id(id)],
+        (Some(run), Some(job)) => vec![Logger::job_path(id, run, job)],
+        (None, Some(job)) => {
+            let run_dir = Logger::latest_run_dir(id)?
+                .ok_or_else(|| anyhow::anyhow!("No run logs found for this project"))?;
+            let run = run_dir
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| anyhow::anyhow!("Failed to find log path"))?;
+            vec![Logger::job_path(id, run, job)]
+        }
+        (Some(run), None) => {
+            let run_dir = Logger::run_dir(id, run);
+            if !run_dir.exists() {
+                anyhow::bail!("No logs found for run {run}");
+            }
+            let mut files: Vec<PathBuf> = std::fs::read_dir(run_dir)?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("log"))
+                .collect();
+            files.sort();
+            files
+        }
+    };
 
-    // let file = File::open(&log_path).await?;
-    // let mut reader = BufReader::new(file);
-    // let mut contents = String::new();
-    // reader.read_to_string(&mut contents).await?;
-    match log_path.to_str() {
-        Some(p) => Ok(String::from(p)),
-        None => Err(anyhow::anyhow!("Failed to find log path")),
-    }
+    paths
+        .iter()
+        .map(|p| match p.to_str() {
+            Some(p) => Ok(String::from(p)),
+            None => Err(anyhow::anyhow!("Failed to find log path")),
+        })
+        .collect()
 }
 
 /// Handles a daemon request and sends the resulting [`DaemonResponse`] back to the client.
@@ -126,6 +261,8 @@ pub async fn handle_request(
             config,
         } => handle_add_watch(state, project_dir, *repo, *config).await?,
 
+        DaemonRequest::CancelRun { id } => handle_cancel_run(state, id).await,
+
         DaemonRequest::StopWatch { id } => handle_stop_watch(state, id).await,
 
         DaemonRequest::UpWatch { id } => handle_up_watch(state, id).await,
@@ -134,7 +271,18 @@ pub async fn handle_request(
 
         DaemonRequest::ListWatches { all } => handle_list_watches(state, all).await,
 
-        DaemonRequest::LogsWatches { id, f } => handle_logs_watches(id, f).await,
+        DaemonRequest::LogsWatches { id, f, run, job } => {
+            handle_logs_watches(id, f, run, job).await
+        }
+
+        DaemonRequest::ListRuns { id } => handle_list_runs(id).await,
+
+        DaemonRequest::GetRun { run_id } => handle_get_run(run_id).await,
+        DaemonRequest::ListArtifacts {
+            run_id,
+            job,
+            extract,
+        } => handle_list_artifacts(run_id, job, extract).await,
 
         DaemonRequest::RunPipeline { id } => {
             handle_run_pipeline(&id, state, stream).await?;
@@ -160,7 +308,7 @@ async fn handle_run_pipeline(
             DaemonResponse::Success(format!("Pipeline {id} has been runed")),
         )
         .await?;
-        match run_pipeline(Arc::new(ctx)).await {
+        match run_tracked(&state, ctx, RunTrigger::Manual).await {
             Ok(_) => {
                 println!("[{id}] ✅ Update succeeded");
             }
@@ -193,7 +341,7 @@ async fn handle_add_watch(
         .await?;
 
     let result = async {
-        let logger = Logger::new(&ctx.log_path()).await?;
+        let logger = Logger::new(&ctx.log_path()).await?.for_project(&ctx.id);
         {
             // delete the projects with the same project_dir, before saving the new one
             guard.retain(|_, existing_ctx| existing_ctx.project_dir != ctx.project_dir);
@@ -215,6 +363,23 @@ async fn handle_add_watch(
     }
 }
 
+/// Cancels the running pipeline of a project (by ID or name).
+pub async fn handle_cancel_run(state: Arc<AppState>, id: String) -> DaemonResponse {
+    match async {
+        let id = resolve_id(id).await?;
+        if state.cancel_run(&id).await {
+            Ok::<_, anyhow::Error>(format!("🛑 Pipeline cancelled for ID: {id}"))
+        } else {
+            Err(anyhow::anyhow!("No pipeline running for ID: {id}"))
+        }
+    }
+    .await
+    {
+        Ok(msg) => DaemonResponse::Success(msg),
+        Err(e) => DaemonResponse::Error(format!("Failed to cancel pipeline: {e}")),
+    }
+}
+
 /// Stops a watch by ID if it exists in the application state.
 pub async fn handle_stop_watch(state: Arc<AppState>, id: String) -> DaemonResponse {
     match async {
@@ -260,6 +425,8 @@ pub async fn handle_rm_watch(state: Arc<AppState>, id: String) -> DaemonResponse
         if let Some(w) = guard.remove(&id) {
             ExecMetrics::rm_metrics_by_id(&id)?; // remove metrics file
             Logger::rm_logs_by_id(&id)?; // remove log file 
+            artifacts::rm_artifacts_by_id(&id)?; // remove the artifacts of every run
+            cache::rm_cache_by_id(&id)?; // remove the cache entries
             AppState::remove_watch_by_id(&id).await?; // remove this watch in watches.json
             Ok::<_, anyhow::Error>(format!("Project: {} was deleted", w.repo.name))
         } else {
@@ -277,6 +444,8 @@ pub async fn handle_rm_watch(state: Arc<AppState>, id: String) -> DaemonResponse
 pub async fn handle_list_watches(state: Arc<AppState>, all: bool) -> DaemonResponse {
     match async {
         let guard = state.watches.read().await;
+        let runs = state.runs.read().await;
+        let active = state.active.read().await;
         let result: Result<Vec<WatchInfo>, anyhow::Error> = guard
             .iter()
             .filter(|(_, ctx)| all || !ctx.paused) // if all = true everything pass, else only if paused is false they can pass
@@ -302,6 +471,12 @@ pub async fn handle_list_watches(state: Arc<AppState>, all: bool) -> DaemonRespo
                     repo_name: ctx.repo.name.clone(),
                     id: id.clone(),
                     paused: ctx.paused,
+                    concurrency: ctx.config.concurrency,
+                    running: active.contains_key(id),
+                    pending: !active.contains_key(id)
+                        && runs.get(id).is_some_and(|slot| slot.is_running()),
+                    waiting: runs.get(id).map_or(0, |slot| slot.waiting()),
+                    last_decision: runs.get(id).and_then(|slot| slot.last_decision()),
                 })
             })
             .collect();
@@ -314,19 +489,90 @@ pub async fn handle_list_watches(state: Arc<AppState>, all: bool) -> DaemonRespo
     }
 }
 
+/// Resolves a project ID from an ID or a repository name.
+async fn resolve_id(id_or_name: String) -> Result<String> {
+    match get_name_by_id(&id_or_name).await {
+        Ok(Some(_)) => Ok(id_or_name),
+        Err(_) | Ok(None) => match get_id_by_name(&id_or_name).await? {
+            Some(uuid) => Ok(uuid),
+            None => anyhow::bail!("No repo with this name exists"),
+        },
+    }
+}
+
+/// Returns the recorded runs of a project (by ID or name), most recent first.
+pub async fn handle_list_runs(id: String) -> DaemonResponse {
+    match async {
+        let id = resolve_id(id).await?;
+        let runs = ExecMetrics::load_history(&id)
+            .await?
+            .iter()
+            .rev()
+            .map(RunInfo::from)
+            .collect();
+        Ok::<_, anyhow::Error>(DaemonResponse::ListRuns(runs))
+    }
+    .await
+    {
+        Ok(resp) => resp,
+        Err(e) => DaemonResponse::Error(format!("Failed to list runs: {e}")),
+    }
+}
+
+/// Returns a single run with the details of each of its jobs.
+pub async fn handle_get_run(run_id: String) -> DaemonResponse {
+    match async {
+        let run = ExecMetrics::find_run(&run_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("⚠ Run not found: {}", run_id))?;
+
+        let mut jobs: Vec<JobInfo> = run.jobs.values().map(JobInfo::from).collect();
+        jobs.sort_by_key(|j| j.started_at);
+        Ok::<_, anyhow::Error>(DaemonResponse::ShowRun(RunInfo::from(&run), jobs))
+    }
+    .await
+    {
+        Ok(resp) => resp,
+        Err(e) => DaemonResponse::Error(format!("Failed to get run: {e}")),
+    }
+}
+
+/// Returns the artifacts kept during a run, optionally the ones of a single job.
+pub async fn handle_list_artifacts(
+    run_id: String,
+    job: Option<String>,
+    extract: Option<String>,
+) -> DaemonResponse {
+    match async {
+        let run = ExecMetrics::find_run(&run_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("⚠ Run not found: {}", run_id))?;
+
+        let mut artifacts = artifacts::list(&run.project_id, &run.run_id)?;
+        if let Some(job) = &job {
+            artifacts.retain(|a| &a.job == job);
+        }
+        Ok::<_, anyhow::Error>(DaemonResponse::ListArtifacts(artifacts, extract))
+    }
+    .await
+    {
+        Ok(resp) => resp,
+        Err(e) => DaemonResponse::Error(format!("Failed to list artifacts: {e}")),
+    }
+}
+
 /// Fetches logs for a given watch by ID or name.
 /// If the watch is not found, sends an error directly to the client.
 /// Returns `None` if an error was already sent to the stream.
-async fn handle_logs_watches(id: String, follow: bool) -> DaemonResponse {
+async fn handle_logs_watches(
+    id: String,
+    follow: bool,
+    run: Option<String>,
+    job: Option<String>,
+) -> DaemonResponse {
     match async {
-        let id = match get_name_by_id(&id).await {
-            Ok(Some(_)) => id,
-            Err(_) | Ok(None) => match get_id_by_name(&id).await? {
-                Some(uuid) => uuid,
-                None => anyhow::bail!("No repo with this name exists"),
-            },
-        };
-        let logs = get_logs_by_id(&id).await?;
+        let id = resolve_id(id).await?;
+        let logs = get_logs_by_id(&id, run.as_deref(), job.as_deref()).await?;
         Ok::<_, anyhow::Error>(DaemonResponse::LogWatch(logs, follow))
     }
     .await
@@ -0,0 +1,180 @@
+//! Files kept from a job once it succeeded, for the jobs depending on it.
+//!
+//! The `artifacts.paths` of a job are copied to `~/.fleet/artifacts/<project_id>/<run_id>/<job>/`,
+//! keeping their path relative to the repository root. A job listing it in `download_artifacts`
+//! gets them copied back into the working tree before its first step.
+//! The artifacts of a run are removed with its history record.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use dirs::home_dir;
+use globset::Glob;
+use serde::{Deserialize, Serialize};
+
+use crate::config::relative_dir;
+
+/// One file kept by a job
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArtifactInfo {
+    pub job: String,
+    /// relative to the repository root
+    pub path: String,
+    pub size: u64,
+    /// where the file is stored
+    pub stored_at: String,
+}
+
+/// `~/.fleet/artifacts/<id>/`
+pub fn artifacts_dir_by_id(id: &str) -> PathBuf {
+    let home = home_dir().unwrap();
+    home.join(".fleet").join("artifacts").join(id)
+}
+
+/// `~/.fleet/artifacts/<id>/<run_id>/`
+pub fn run_dir(id: &str, run_id: &str) -> PathBuf {
+    artifacts_dir_by_id(id).join(run_id)
+}
+
+/// `~/.fleet/artifacts/<id>/<run_id>/<job>/`
+pub fn job_dir(id: &str, run_id: &str, job: &str) -> PathBuf {
+    let dir_name: String = job
+        .chars()
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect();
+    run_dir(id, run_id).join(dir_name)
+}
+
+/// Copy the files of `project_dir` matching `paths` into `dest`, returns the number of files copied.
+pub fn save(project_dir: &Path, paths: &[String], dest: &Path) -> Result<usize> {
+    let files = matching_files(project_dir, paths)?;
+    for file in &files {
+        copy_file(file, &dest.join(file.strip_prefix(project_dir)?))?;
+    }
+    Ok(files.len())
+}
+
+/// Files of `project_dir` matching `patterns`, sorted and without duplicates.
+/// A path without glob characters may name a directory, standing for its whole content.
+pub(crate) fn matching_files(project_dir: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        if is_glob(pattern) {
+            let glob = Glob::new(pattern)?.compile_matcher();
+            for file in walk(project_dir)? {
+                if glob.is_match(file.strip_prefix(project_dir)?) {
+                    files.push(file);
+                }
+            }
+            continue;
+        }
+
+        let source = project_dir.join(relative_dir(pattern)?);
+        if source.is_dir() {
+            files.extend(walk(&source)?);
+        } else if source.is_file() || source.is_symlink() {
+            files.push(source);
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Copy every file stored in `source` into `project_dir`, returns the number of files restored.
+pub fn restore(source: &Path, project_dir: &Path) -> Result<usize> {
+    let files = walk(source)?;
+    for file in &files {
+        copy_file(file, &project_dir.join(file.strip_prefix(source)?))?;
+    }
+    Ok(files.len())
+}
+
+/// Every file kept during a run, sorted by job then path
+pub fn list(id: &str, run_id: &str) -> Result<Vec<ArtifactInfo>> {
+    let dir = run_dir(id, run_id);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut artifacts = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let job_dir = entry?.path();
+        let Some(job) = job_dir.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        for file in walk(&job_dir)? {
+            artifacts.push(ArtifactInfo {
+                job: job.to_string(),
+                path: file.strip_prefix(&job_dir)?.to_string_lossy().into_owned(),
+                size: file.symlink_metadata()?.len(),
+                stored_at: file.to_string_lossy().into_owned(),
+            });
+        }
+    }
+    artifacts.sort_by(|a, b| (&a.job, &a.path).cmp(&(&b.job, &b.path)));
+    Ok(artifacts)
+}
+
+pub fn rm_run_artifacts(id: &str, run_id: &str) -> Result<()> {
+    let dir = run_dir(id, run_id);
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+pub fn rm_artifacts_by_id(id: &str) -> Result<()> {
+    let dir = artifacts_dir_by_id(id);
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// Every file and symlink under `dir`, the `.git` directory left out.
+/// A symlink to a directory is listed, not followed.
+pub(crate) fn walk(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() && entry.file_name() != ".git" {
+                stack.push(entry.path());
+            } else if file_type.is_file() || file_type.is_symlink() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copy a file, or a symlink as a symlink (e.g. the `node_modules/.bin/` links), replacing `to`
+pub(crate) fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // never write through a symlink left at `to`
+    if to.is_symlink() {
+        fs::remove_file(to)?;
+    }
+    if from.is_symlink() {
+        if to.exists() {
+            fs::remove_file(to)?;
+        }
+        std::os::unix::fs::symlink(fs::read_link(from)?, to)?;
+    } else {
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,303 @@
+//! Files kept between the runs of a project, e.g. `target/` or `node_modules`.
+//!
+//! An entry is stored in `~/.fleet/cache/<project_id>/<key>/`: the files of the working tree in `files/`,
+//! the directories mounted in the containers of the job in `mounts/`, and its description in `entry.json`.
+//! A job restores the entry of its `cache.key` before its first step, else the most recently used one
+//! whose key starts with one of its `restore_keys`, and saves a new entry once it succeeded when its key had none.
+//! Entries are never updated. Once the cache of all the projects exceeds `FLEET_CACHE_MAX_SIZE` megabytes,
+//! the least recently used entries are removed.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dirs::home_dir;
+use git2::{ObjectType, Oid};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{
+        container_dir,
+        template::{JobOutputs, Placeholder, output_value, render_with},
+    },
+    core::id::short_id,
+    exec::artifacts::{copy_file, matching_files, walk},
+};
+
+pub const DEFAULT_MAX_SIZE_MB: u64 = 2048;
+
+const ENTRY_FILE: &str = "entry.json";
+const FILES_DIR: &str = "files";
+const MOUNTS_DIR: &str = "mounts";
+
+/// Description of a stored entry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CacheEntry {
+    pub key: String,
+    /// bytes
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+}
+
+/// `~/.fleet/cache/`
+pub fn cache_dir() -> PathBuf {
+    let home = home_dir().unwrap();
+    home.join(".fleet").join("cache")
+}
+
+/// `~/.fleet/cache/<id>/`
+pub fn cache_dir_by_id(id: &str) -> PathBuf {
+    cache_dir().join(id)
+}
+
+/// Size limit of the cache in bytes, set with `FLEET_CACHE_MAX_SIZE` in megabytes (2048 by default)
+pub fn max_size() -> u64 {
+    std::env::var("FLEET_CACHE_MAX_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_SIZE_MB)
+        .saturating_mul(1024 * 1024)
+}
+
+/// A key usable as a directory name: any character other than letters, digits, `-`, `_`, `.` and `+`
+/// is replaced with `_`, and so is a leading `.`
+pub fn sanitize_key(key: &str) -> String {
+    key.chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            '.' if i == 0 => '_',
+            c if c.is_ascii_alphanumeric() || "-_.+".contains(c) => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Render a `key` or a restore key: outputs of upstream jobs and `hashFiles()` of the working tree
+pub fn render_key(text: &str, outputs: &JobOutputs, project_dir: &Path) -> Result<String> {
+    let key = render_with(text, |placeholder| match placeholder {
+        Placeholder::Output { job, key } => Ok(output_value(outputs, job, key)),
+        Placeholder::HashFiles(patterns) => hash_files(project_dir, patterns),
+    })?;
+    let key = sanitize_key(key.trim());
+    if key.is_empty() {
+        anyhow::bail!("cache key `{text}` is empty once rendered");
+    }
+    Ok(key)
+}
+
+/// Hash of the content and path of every file matching `patterns`, empty when none matches.
+/// A symlink is hashed by its target like git does, it may lead to a directory or nowhere.
+pub fn hash_files(project_dir: &Path, patterns: &[String]) -> Result<String> {
+    let files = matching_files(project_dir, patterns)?;
+    if files.is_empty() {
+        return Ok(String::new());
+    }
+    let mut manifest = String::new();
+    for file in files {
+        let content = match file.is_symlink() {
+            true => fs::read_link(&file)?.into_os_string().into_encoded_bytes(),
+            false => fs::read(&file)?,
+        };
+        let oid = Oid::hash_object(ObjectType::Blob, &content)?;
+        let rel = file.strip_prefix(project_dir)?.to_string_lossy();
+        manifest.push_str(&format!("{oid} {rel}\n"));
+    }
+    Ok(Oid::hash_object(ObjectType::Blob, manifest.as_bytes())?.to_string())
+}
+
+/// The entry stored for `key`, else the most recently used one matching a restore key, tried in order
+pub fn lookup(
+    id: &str,
+    key: &str,
+    restore_keys: &[String],
+) -> Result<Option<(PathBuf, CacheEntry)>> {
+    let exact = cache_dir_by_id(id).join(key);
+    if let Some(entry) = read_entry(&exact) {
+        return Ok(Some((exact, entry)));
+    }
+    let entries = entries(&cache_dir_by_id(id))?;
+    for prefix in restore_keys {
+        let found = entries
+            .iter()
+            .filter(|(_, e)| e.key.starts_with(prefix.as_str()))
+            .max_by_key(|(_, e)| e.last_used);
+        if let Some(found) = found {
+            return Ok(Some(found.clone()));
+        }
+    }
+    Ok(None)
+}
+
+/// Copy an entry into the working tree and the mounted directories, returns the number of files restored.
+/// The entry becomes the most recently used one.
+pub fn restore(entry_dir: &Path, project_dir: &Path, mount_dir: Option<&Path>) -> Result<usize> {
+    let (mut restored, _) = copy_tree(&entry_dir.join(FILES_DIR), project_dir)?;
+    if let Some(mount_dir) = mount_dir {
+        restored += copy_tree(&entry_dir.join(MOUNTS_DIR), mount_dir)?.0;
+    }
+    if let Some(mut entry) = read_entry(entry_dir) {
+        entry.last_used = Utc::now();
+        write_entry(entry_dir, &entry)?;
+    }
+    Ok(restored)
+}
+
+/// Store the files of `project_dir` matching the relative `paths` and the mounted directories of
+/// the absolute ones under `key`. `None` when an entry of `key` was stored in the meantime.
+pub fn save(
+    id: &str,
+    key: &str,
+    project_dir: &Path,
+    paths: &[String],
+    mount_dir: Option<&Path>,
+) -> Result<Option<CacheEntry>> {
+    let project_cache = cache_dir_by_id(id);
+    let dest = project_cache.join(key);
+    if dest.exists() {
+        return Ok(None);
+    }
+
+    // filled next to its final place then renamed, so a lookup never sees half an entry
+    let tmp = project_cache.join(format!(".tmp-{}", short_id()));
+    let result = (|| {
+        let (mounted, relative): (Vec<String>, Vec<String>) =
+            paths.iter().cloned().partition(|p| p.starts_with('/'));
+        let mut size = 0;
+        for file in matching_files(project_dir, &relative)? {
+            let to = tmp.join(FILES_DIR).join(file.strip_prefix(project_dir)?);
+            copy_file(&file, &to)?;
+            size += to.symlink_metadata()?.len();
+        }
+        if let Some(mount_dir) = mount_dir {
+            for path in &mounted {
+                let dir = container_dir(path)?;
+                size += copy_tree(&mount_dir.join(&dir), &tmp.join(MOUNTS_DIR).join(&dir))?.1;
+            }
+        }
+        let now = Utc::now();
+        let entry = CacheEntry {
+            key: key.to_string(),
+            size,
+            created_at: now,
+            last_used: now,
+        };
+        fs::create_dir_all(&tmp)?;
+        write_entry(&tmp, &entry)?;
+        Ok::<_, anyhow::Error>(entry)
+    })();
+
+    let entry = match result {
+        Ok(entry) => entry,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&tmp);
+            return Err(e);
+        }
+    };
+    if fs::rename(&tmp, &dest).is_err() {
+        // another run stored the key first
+        fs::remove_dir_all(&tmp)?;
+        return Ok(None);
+    }
+    Ok(Some(entry))
+}
+
+/// `host:container` bind mounts of the absolute `paths`, their host directories created in `mount_dir`
+pub fn mounts(paths: &[String], mount_dir: &Path) -> Result<Vec<String>> {
+    let mut binds = Vec::new();
+    for path in paths.iter().filter(|p| p.starts_with('/')) {
+        let host = mount_dir.join(container_dir(path)?);
+        fs::create_dir_all(&host)?;
+        binds.push(format!("{}:{}", host.display(), path));
+    }
+    Ok(binds)
+}
+
+/// Remove the least recently used entries of every project until the cache fits in `max_size` bytes.
+/// Returns the removed entries as `<project_id>/<key>`.
+pub fn evict(max_size: u64) -> Result<Vec<String>> {
+    evict_from(&cache_dir(), max_size)
+}
+
+/// `evict` on the cache stored in `root`
+pub fn evict_from(root: &Path, max_size: u64) -> Result<Vec<String>> {
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+    let mut all = Vec::new();
+    for project in fs::read_dir(root)? {
+        let project = project?.path();
+        if project.is_dir() {
+            all.extend(entries(&project)?);
+        }
+    }
+    all.sort_by_key(|(_, e)| e.last_used);
+
+    let mut total: u64 = all.iter().map(|(_, e)| e.size).sum();
+    let mut removed = Vec::new();
+    for (dir, entry) in all {
+        if total <= max_size {
+            break;
+        }
+        fs::remove_dir_all(&dir)?;
+        total = total.saturating_sub(entry.size);
+        removed.push(dir.strip_prefix(root)?.to_string_lossy().into_owned());
+    }
+    Ok(removed)
+}
+
+pub fn rm_cache_by_id(id: &str) -> Result<()> {
+    let dir = cache_dir_by_id(id);
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Stored entries of a project, the ones being saved left out
+fn entries(project_cache: &Path) -> Result<Vec<(PathBuf, CacheEntry)>> {
+    if !project_cache.exists() {
+        return Ok(Vec::new());
+    }
+    let mut found = Vec::new();
+    for dir in fs::read_dir(project_cache)? {
+        let dir = dir?.path();
+        if dir
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if let Some(entry) = read_entry(&dir) {
+            found.push((dir, entry));
+        }
+    }
+    Ok(found)
+}
+
+fn read_entry(entry_dir: &Path) -> Option<CacheEntry> {
+    let content = fs::read_to_string(entry_dir.join(ENTRY_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_entry(entry_dir: &Path, entry: &CacheEntry) -> Result<()> {
+    fs::write(entry_dir.join(ENTRY_FILE), serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Copy every file under `from` to the same place under `to`, returns the number of files and bytes copied
+fn copy_tree(from: &Path, to: &Path) -> Result<(usize, u64)> {
+    if !from.is_dir() {
+        return Ok((0, 0));
+    }
+    let files = walk(from)?;
+    let mut size = 0;
+    for file in &files {
+        copy_file(file, &to.join(file.strip_prefix(from)?))?;
+        size += file.symlink_metadata()?.len();
+    }
+    Ok((files.len(), size))
+}
@@ -1,5 +1,5 @@
 #![allow(dead_code)]
-use std::{collections::HashMap, fs::OpenOptions, sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use tokio::{
@@ -7,19 +7,63 @@ use tokio::{
     sync::Mutex,
     time::timeout,
 };
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     core::watcher::WatchContext,
     exec::{OutpuStrategy, PipeRegistry, metrics::monitor_process},
-    log::logger::Logger,
+    log::{
+        job_log::{JobLog, OutputStream},
+        logger::Logger,
+    },
 };
 
+/// A command that ran but exited with a non-zero code
+#[derive(Debug)]
+pub struct CommandFailed {
+    pub parts: Vec<String>,
+    pub code: Option<i32>,
+}
+
+impl std::fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed command: {:?}", self.parts)
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// A step killed because its time limit expired
+#[derive(Debug)]
+pub struct TimedOut {
+    pub secs: u64,
+}
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timed out after {} seconds", self.secs)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
 pub struct CommandOutput {
     pub status_code: Option<i32>,
     pub cpu_usage: f32,
     pub mem_usage_kb: u64,
 }
 
+/// Kill every process of the group led by `pid` (the command and everything it spawned).
+pub fn kill_process_group(pid: Option<u32>) {
+    if let Some(pid) = pid {
+        // SAFETY: killpg only sends a signal, an outdated group id is reported as ESRCH
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run_command_with_timeout(
     program: &str,
     args: &[String],
@@ -28,6 +72,7 @@ pub async fn run_command_with_timeout(
     output: &OutpuStrategy,
     env: Option<HashMap<String, String>>,
     pipe_registry: Arc<Mutex<PipeRegistry>>,
+    cancel: &CancellationToken,
 ) -> Result<CommandOutput> {
     // Lance le process avec pipes pour stdout et stderr
     let mut cmd = Command::new(program);
@@ -36,7 +81,8 @@ pub async fn run_command_with_timeout(
         .chain(args.iter().cloned())
         .collect();
 
-    cmd.args(args).current_dir(current_dir);
+    // own process group, so a timeout or a cancel also reaches the grandchildren
+    cmd.args(args).current_dir(current_dir).process_group(0);
     output.configure(&mut cmd, full, pipe_registry).await?;
 
     if let Some(vars) = env {
@@ -46,6 +92,7 @@ pub async fn run_command_with_timeout(
     }
 
     let mut child = cmd.spawn()?;
+    let captures = output.capture(&mut child);
 
     let (tx, mut rx) = tokio::sync::mpsc::channel(1);
     let child_pid = child.id();
@@ -58,25 +105,36 @@ pub async fn run_command_with_timeout(
 
     let run_future = async {
         let status = child.wait().await?;
+        for c in captures {
+            c.await.ok();
+        }
 
         let (cpu_usage, mem_usage_kb) = rx.recv().await.unwrap_or((0.0, 0));
         println!("METRICS EXTRACTED => {cpu_usage} | {mem_usage_kb}");
         anyhow::Ok((status, cpu_usage, mem_usage_kb))
     };
 
-    match timeout(duration, run_future).await {
-        Ok(Ok((status, cpu_usage, mem_usage_kb))) => Ok(CommandOutput {
+    let outcome = tokio::select! {
+        res = timeout(duration, run_future) => Some(res),
+        _ = cancel.cancelled() => None,
+    };
+
+    match outcome {
+        Some(Ok(Ok((status, cpu_usage, mem_usage_kb)))) => Ok(CommandOutput {
             status_code: status.code(),
             cpu_usage,
             mem_usage_kb,
         }),
-        Ok(Err(e)) => Err(anyhow::anyhow!("Error during execution : {}", e)),
-        Err(_) => {
+        Some(Ok(Err(e))) => Err(anyhow::anyhow!("Error during execution : {}", e)),
+        Some(Err(_)) => {
+            kill_process_group(child_pid);
+            child.kill().await.ok();
+            Err(TimedOut { secs: timeout_secs }.into())
+        }
+        None => {
+            kill_process_group(child_pid);
             child.kill().await.ok();
-            Err(anyhow::anyhow!(
-                "Command timeout after {} seconds, process killed",
-                timeout_secs
-            ))
+            Err(anyhow::anyhow!("Command cancelled, process killed"))
         }
     }
 }
@@ -85,19 +143,16 @@ pub async fn run_command_background(
     program: &str,
     args: &[String],
     current_dir: &str,
-    stdout_file: std::fs::File,
-    stderr_file: std::fs::File,
+    log: &JobLog,
     env: Option<HashMap<String, String>>,
 ) -> Result<Child> {
     use std::process::Stdio;
-    let stdout_stdio = Stdio::from(stdout_file);
-    let stderr_stdio = Stdio::from(stderr_file);
 
     let mut cmd = Command::new(program);
     cmd.args(args)
         .current_dir(current_dir)
-        .stdout(stdout_stdio)
-        .stderr(stderr_stdio);
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
 
     if let Some(vars) = env {
         for (k, v) in vars {
@@ -105,19 +160,38 @@ pub async fn run_command_background(
         }
     }
 
-    let child = cmd.spawn()?;
+    let mut child = cmd.spawn()?;
+    // capture tasks keep running after the job ends, until the process closes its outputs
+    if let Some(stdout) = child.stdout.take() {
+        log.capture(stdout, OutputStream::Stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        log.capture(stderr, OutputStream::Stderr);
+    }
 
     Ok(child)
 }
 
+/// Whether a command exited with one of `success_codes`, only 0 when it is empty
+pub fn is_success(code: Option<i32>, success_codes: &[i32]) -> bool {
+    match code {
+        Some(code) if success_codes.is_empty() => code == 0,
+        Some(code) => success_codes.contains(&code),
+        None => false,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn exec_timeout(
     parts: Vec<String>,
     ctx: &WatchContext,
+    dir: &str,
     logger: &Logger,
     timeout: u64,
     env: Option<HashMap<String, String>>,
     output_strategy: &OutpuStrategy,
     pipe_registry: Arc<Mutex<PipeRegistry>>,
+    success_codes: &[i32],
 ) -> Result<CommandOutput, anyhow::Error> {
     let program = &parts[0];
     let args = &parts[1..];
@@ -125,23 +199,28 @@ pub async fn exec_timeout(
     match run_command_with_timeout(
         program,
         args,
-        &ctx.project_dir,
+        dir,
         timeout,
         output_strategy,
         env,
         pipe_registry,
+        &ctx.cancel,
     )
     .await
     {
         Ok(output) => {
-            if output.status_code != Some(0) {
+            if !is_success(output.status_code, success_codes) {
                 logger
                     .error(&format!(
                         "Command failed with exit code {:?}",
                         output.status_code
                     ))
                     .await?;
-                return Err(anyhow::anyhow!("Failed command: {:?}", parts));
+                return Err(CommandFailed {
+                    parts,
+                    code: output.status_code,
+                }
+                .into());
             }
             logger.info(&format!("Command {program} succeeded")).await?;
             Ok(output)
@@ -151,6 +230,9 @@ pub async fn exec_timeout(
                 .error(&format!("Command error or timeout: {parts:?}"))
                 .await?;
             logger.error(&e.to_string()).await?;
+            if e.is::<TimedOut>() {
+                return Err(e);
+            }
             Err(anyhow::anyhow!("**Command error:**: `{parts:?}`\n{e}"))
         }
     }
@@ -158,36 +240,18 @@ pub async fn exec_timeout(
 
 pub async fn exec_background(
     parts: Vec<String>,
-    ctx: &WatchContext,
+    dir: &str,
     logger: &Logger,
     env: Option<HashMap<String, String>>,
+    log: &JobLog,
 ) -> Result<(), anyhow::Error> {
     let program = &parts[0];
     let args = &parts[1..];
     logger
         .info("Command marked as blocking: running in background without waiting")
         .await?;
-    let log_path = ctx.log_path();
-
-    let stdout_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_path)?;
-    let stderr_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_path)?;
-
-    match run_command_background(
-        program,
-        args,
-        &ctx.project_dir,
-        stdout_file,
-        stderr_file,
-        env,
-    )
-    .await
-    {
+
+    match run_command_background(program, args, dir, log, env).await {
         Ok(_child) => {
             logger.info("Background command launched").await?;
         }
@@ -6,15 +6,16 @@ use bollard::Docker;
 use bollard::models::ContainerCreateBody;
 use bollard::query_parameters::{
     CreateContainerOptionsBuilder, CreateImageOptionsBuilder, LogsOptionsBuilder,
-    RemoveContainerOptionsBuilder, StartContainerOptions,
+    RemoveContainerOptionsBuilder, StartContainerOptions, WaitContainerOptions,
 };
 use futures_util::stream::StreamExt;
 
-use tokio::fs::OpenOptions;
-use tokio::io::AsyncWriteExt;
 use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
 
 use crate::core::id::short_id;
+use crate::exec::command::{CommandFailed, TimedOut, is_success};
+use crate::log::job_log::{JobLog, OutputStream};
 use crate::log::logger::Logger;
 
 async fn ensure_image(docker: &Docker, image: &str, logger: &Logger) -> Result<()> {
@@ -42,15 +43,35 @@ async fn ensure_image(docker: &Docker, image: &str, logger: &Logger) -> Result<(
     Ok(())
 }
 
+/// Exit code of a container, once it stopped
+async fn exit_code(docker: &Docker, id: &str) -> Result<i64> {
+    let mut wait = docker.wait_container(id, None::<WaitContainerOptions>);
+    match wait.next().await {
+        Some(Ok(response)) => Ok(response.status_code),
+        Some(Err(bollard::errors::Error::DockerContainerWaitError { code, .. })) => Ok(code),
+        Some(Err(e)) => Err(e.into()),
+        None => Err(anyhow::anyhow!(
+            "Container {id} stopped without an exit code"
+        )),
+    }
+}
+
+/// Runs `cmd` in a container of `image` with the `host:container` mounts of `binds`,
+/// `workdir` is the directory the command starts in.
+/// Fails with `CommandFailed` when the container exits with a code outside of `success_codes`, like a host step.
+#[allow(clippy::too_many_arguments)]
 pub async fn contain_cmd(
     image: &str,
     cmd: Vec<String>,
     env: Option<HashMap<String, String>>,
-    dir: &str,
-    log_path: &str,
-    logger: &Logger,
+    binds: Vec<String>,
+    workdir: &str,
+    job_log: &JobLog,
     timeout_secs: Option<u64>,
+    success_codes: &[i32],
+    cancel: &CancellationToken,
 ) -> Result<()> {
+    let logger = job_log.logger();
     logger.info("Building image").await?;
     let docker = Docker::connect_with_local_defaults()?;
     ensure_image(&docker, image, logger).await?;
@@ -61,16 +82,16 @@ pub async fn contain_cmd(
 
     let container_config = ContainerCreateBody {
         image: Some(image.to_string()),
-        cmd: Some(cmd),
+        cmd: Some(cmd.clone()),
         env: env.map(|m| m.iter().map(|(k, v)| format!("{k}={v}")).collect()),
         attach_stdout: Some(true),
         attach_stderr: Some(true),
         tty: Some(false),
         host_config: Some(bollard::models::HostConfig {
-            binds: Some(vec![format!("{dir}:/app",)]),
+            binds: Some(binds),
             ..Default::default()
         }),
-        working_dir: Some("/app".to_string()),
+        working_dir: Some(workdir.to_string()),
         ..Default::default()
     };
 
@@ -82,12 +103,6 @@ pub async fn contain_cmd(
         .start_container(&container.id, None::<StartContainerOptions>)
         .await?;
 
-    let mut log_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_path)
-        .await?;
-
     let logs_options = LogsOptionsBuilder::default()
         .follow(true)
         .stdout(true)
@@ -99,16 +114,35 @@ pub async fn contain_cmd(
     let logs_future = async {
         while let Some(log) = log_stream.next().await {
             match log? {
-                bollard::container::LogOutput::StdOut { message }
-                | bollard::container::LogOutput::StdErr { message } => {
-                    log_file.write_all(&message).await?;
+                bollard::container::LogOutput::StdOut { message } => {
+                    job_log.write_chunk(OutputStream::Stdout, &message).await?;
+                }
+                bollard::container::LogOutput::StdErr { message } => {
+                    job_log.write_chunk(OutputStream::Stderr, &message).await?;
                 }
                 _ => {}
             }
         }
+        let code = i32::try_from(exit_code(&docker, &container.id).await?).ok();
+        if !is_success(code, success_codes) {
+            logger
+                .error(&format!("Container command failed with exit code {code:?}"))
+                .await?;
+            return Err(CommandFailed { parts: cmd, code }.into());
+        }
         Ok::<(), anyhow::Error>(())
     };
 
+    let logs_future = async {
+        tokio::select! {
+            res = logs_future => res,
+            _ = cancel.cancelled() => {
+                logger.warning("Container execution cancelled").await?;
+                Err(anyhow::anyhow!("Container execution cancelled"))
+            }
+        }
+    };
+
     let result = if let Some(secs) = timeout_secs {
         // w Timeout
         match timeout(Duration::from_secs(secs), logs_future).await {
@@ -119,9 +153,7 @@ pub async fn contain_cmd(
                         "Container execution timed out after {secs} seconds"
                     ))
                     .await?;
-                Err(anyhow::anyhow!(
-                    "Container execution timed out after {secs} seconds"
-                ))
+                Err(TimedOut { secs }.into())
             }
         }
     } else {
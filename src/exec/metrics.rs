@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 use core::f32;
-use std::{collections::HashMap, path::PathBuf, time::Duration};
+use std::{collections::HashMap, fmt, path::PathBuf, time::Duration};
 
 use chrono::{DateTime, Utc};
 use dirs::home_dir;
@@ -11,15 +11,92 @@ use tokio::{
     time::sleep,
 };
 
-use crate::log::logger::Logger;
+use crate::{
+    config::{HistoryRetention, template::JobOutputs},
+    core::id::short_id,
+    exec::artifacts,
+    log::logger::Logger,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum JobStatus {
     Pending,
     Running,
     Succeeded,
+    /// succeeded, but a step or the job itself failed and was allowed to
+    SucceededWithWarnings,
     Failed,
     Skipped,
+    Cancelled,
+}
+
+impl JobStatus {
+    /// whether the dependents of the job can rely on it
+    pub fn succeeded(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Succeeded | JobStatus::SucceededWithWarnings
+        )
+    }
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobStatus::Pending => write!(f, "pending"),
+            JobStatus::Running => write!(f, "running"),
+            JobStatus::Succeeded => write!(f, "succeeded"),
+            JobStatus::SucceededWithWarnings => write!(f, "succeeded with warnings"),
+            JobStatus::Failed => write!(f, "failed"),
+            JobStatus::Skipped => write!(f, "skipped"),
+            JobStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// What started a pipeline run.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RunTrigger {
+    /// new commit detected by the supervisor loop
+    #[default]
+    Poll,
+    /// `fleet run <id>`
+    Manual,
+}
+
+impl fmt::Display for RunTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunTrigger::Poll => write!(f, "poll"),
+            RunTrigger::Manual => write!(f, "manual"),
+        }
+    }
+}
+
+/// Final status of a pipeline run.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum RunStatus {
+    #[default]
+    Running,
+    Succeeded,
+    Failed,
+    /// stopped by `fleet cancel`
+    Cancelled,
+    /// no change matching the `paths` filters of the pipeline
+    Skipped,
+}
+
+impl fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunStatus::Running => write!(f, "running"),
+            RunStatus::Succeeded => write!(f, "succeeded"),
+            RunStatus::Failed => write!(f, "failed"),
+            RunStatus::Cancelled => write!(f, "cancelled"),
+            RunStatus::Skipped => write!(f, "skipped"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,15 +111,80 @@ pub struct JobMetrics {
     pub mem_usage_kb: u64,
     pub max_cpu: f32,
     pub max_mem: f32,
+    /// why the job failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// why the job failed, set with `error`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<FailureReason>,
+    /// failures allowed by `allow_failure`, set with `job_finished_with_warnings`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// `KEY=value` written by the steps to `FLEET_OUTPUT`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub outputs: HashMap<String, String>,
+    /// every attempt of the steps with a retry policy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<StepAttempt>,
     #[serde(skip)]
     pub buf: Vec<(f32, u64)>,
 }
 
+/// Why a job failed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureReason {
+    /// a step failed or could not run
+    Error,
+    /// a step, job or pipeline time limit expired
+    Timeout,
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureReason::Error => write!(f, "error"),
+            FailureReason::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+/// One run of a step with a retry policy.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StepAttempt {
+    /// 1-based index of the step in the job
+    pub step: usize,
+    /// 1 for the first run of the step
+    pub attempt: u32,
+    /// none when the step did not exit by itself (timeout, container or spawn error)
+    pub exit_code: Option<i32>,
+}
+
+impl JobMetrics {
+    /// attempts made after a failure, over every step of the job
+    pub fn retries(&self) -> usize {
+        self.attempts.iter().filter(|a| a.attempt > 1).count()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecMetrics {
+    #[serde(default)]
+    pub run_id: String,
     pub project_id: String,
     pub project_name: String,
 
+    #[serde(default)]
+    pub trigger: RunTrigger,
+    #[serde(default)]
+    pub status: RunStatus,
+
+    /// commit checked out in the project dir when the pipeline started
+    #[serde(default)]
+    pub commit: String,
+    #[serde(default)]
+    pub branch: String,
+
     pub started_at: DateTime<Utc>,
     pub finished_at: Option<DateTime<Utc>>,
     pub duration_ms: Option<u128>,
@@ -60,10 +202,22 @@ pub struct ExecMetrics {
 }
 
 impl ExecMetrics {
-    pub fn new(project_id: &str, project_name: &str, logger: Logger) -> Self {
+    pub fn new(
+        project_id: &str,
+        project_name: &str,
+        commit: &str,
+        branch: &str,
+        trigger: RunTrigger,
+        logger: Logger,
+    ) -> Self {
         Self {
+            run_id: short_id(),
             project_id: project_id.to_string(),
             project_name: project_name.to_string(),
+            trigger,
+            status: RunStatus::Running,
+            commit: commit.to_string(),
+            branch: branch.to_string(),
             started_at: Utc::now(),
             finished_at: None,
             duration_ms: None,
@@ -84,7 +238,8 @@ impl ExecMetrics {
     }
 
     /// call at the end of the pipeline
-    pub fn finalize(&mut self) {
+    pub fn finalize(&mut self, status: RunStatus) {
+        self.status = status;
         let end = Utc::now();
         self.finished_at = Some(end);
         self.duration_ms = Some(
@@ -145,11 +300,97 @@ impl ExecMetrics {
                 mem_usage_kb: 0,
                 max_cpu: 0.0,
                 max_mem: 0.0,
+                error: None,
+                reason: None,
+                warnings: Vec::new(),
+                outputs: HashMap::new(),
+                attempts: Vec::new(),
                 buf: Vec::new(),
             },
         );
     }
 
+    /// job of the DAG not started yet
+    pub fn job_pending(&mut self, name: &str) {
+        self.job_started(name);
+        if let Some(j) = self.jobs.get_mut(name) {
+            j.status = JobStatus::Pending;
+        }
+    }
+
+    /// mark every job still pending as skipped
+    pub fn skip_pending(&mut self) {
+        let pending: Vec<String> = self
+            .jobs
+            .values()
+            .filter(|j| j.status == JobStatus::Pending)
+            .map(|j| j.name.clone())
+            .collect();
+        for name in pending {
+            self.job_skipped(&name);
+        }
+    }
+
+    pub fn step_attempt(&mut self, name: &str, step: usize, attempt: u32, exit_code: Option<i32>) {
+        if let Some(j) = self.jobs.get_mut(name) {
+            j.attempts.push(StepAttempt {
+                step,
+                attempt,
+                exit_code,
+            });
+        }
+    }
+
+    pub fn job_error(&mut self, name: &str, error: &str, reason: FailureReason) {
+        if let Some(j) = self.jobs.get_mut(name) {
+            j.error = Some(error.to_string());
+            j.reason = Some(reason);
+        }
+    }
+
+    pub fn job_outputs(&mut self, name: &str, outputs: HashMap<String, String>) {
+        if let Some(j) = self.jobs.get_mut(name) {
+            j.outputs = outputs;
+        }
+    }
+
+    /// outputs of the given jobs, the ones without any are left out
+    pub fn outputs_of<'a>(&self, names: impl IntoIterator<Item = &'a String>) -> JobOutputs {
+        names
+            .into_iter()
+            .filter_map(|name| self.jobs.get(name))
+            .filter(|j| !j.outputs.is_empty())
+            .map(|j| (j.name.clone(), j.outputs.clone()))
+            .collect()
+    }
+
+    /// job whose failures were allowed by `allow_failure`
+    pub fn job_finished_with_warnings(&mut self, name: &str, warnings: Vec<String>) {
+        self.job_finished(name, true);
+        if let Some(j) = self.jobs.get_mut(name) {
+            j.status = JobStatus::SucceededWithWarnings;
+            j.warnings = warnings;
+        }
+    }
+
+    /// job killed by a cancellation while running
+    pub fn job_cancelled(&mut self, name: &str) {
+        self.job_finished(name, false);
+        if let Some(j) = self.jobs.get_mut(name) {
+            j.status = JobStatus::Cancelled;
+        }
+    }
+
+    /// job that never started
+    pub fn job_skipped(&mut self, name: &str) {
+        self.job_started(name);
+        if let Some(j) = self.jobs.get_mut(name) {
+            j.finished_at = Some(j.started_at);
+            j.duration_ms = Some(0);
+            j.status = JobStatus::Skipped;
+        }
+    }
+
     pub fn job_finished(&mut self, name: &str, ok: bool) {
         if let Some(j) = self.jobs.get_mut(name) {
             if !j.buf.is_empty() {
@@ -226,22 +467,118 @@ impl ExecMetrics {
         let dir = Self::ensure_metrics_dir().await?;
         let path = dir.join(format!("{project_id}.ndjson"));
         let file = tokio::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
+            .append(true)
             .create(true)
             .open(path)
             .await?;
         Ok(file)
     }
 
+    /// append this run to the project history (one json record per line)
     pub async fn save(&self) -> anyhow::Result<()> {
         let mut file = Self::open_metrics_file(&self.project_id).await?;
-        let line = serde_json::to_string(self)?;
+        let line = serde_json::to_string(self)? + "\n";
         file.write_all(line.as_bytes()).await?;
-        file.write_all(b"\n").await?;
         file.flush().await?;
         Ok(())
     }
+
+    /// Load every recorded run of a project, oldest first.
+    /// Lines that can't be parsed are skipped.
+    pub async fn load_history(project_id: &str) -> anyhow::Result<Vec<ExecMetrics>> {
+        let path = Self::get_metrics_path_by_id(project_id)?;
+        if !fs::try_exists(&path).await? {
+            return Ok(Vec::new());
+        }
+
+        let content = fs::read_to_string(&path).await?;
+        Ok(content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .filter_map(|l| serde_json::from_str::<ExecMetrics>(l).ok())
+            .collect())
+    }
+
+    /// Look for a run in the history of every project.
+    pub async fn find_run(run_id: &str) -> anyhow::Result<Option<ExecMetrics>> {
+        let dir = Self::ensure_metrics_dir().await?;
+        let mut entries = fs::read_dir(&dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("ndjson") {
+                continue;
+            }
+            let Some(project_id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if let Some(run) = Self::load_history(project_id)
+                .await?
+                .into_iter()
+                .find(|m| m.run_id == run_id)
+            {
+                return Ok(Some(run));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Rewrite the history of a project without the runs that fall outside `retention`.
+    /// Returns the number of dropped runs.
+    pub async fn apply_retention(
+        project_id: &str,
+        retention: &HistoryRetention,
+    ) -> anyhow::Result<usize> {
+        let path = Self::get_metrics_path_by_id(project_id)?;
+        if !fs::try_exists(&path).await? {
+            return Ok(0);
+        }
+
+        let content = fs::read_to_string(&path).await?;
+        let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+
+        let oldest_allowed = retention
+            .keep_days
+            .map(|days| Utc::now() - chrono::Duration::days(days as i64));
+
+        let mut kept: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(
+                |l| match (oldest_allowed, serde_json::from_str::<ExecMetrics>(l)) {
+                    (Some(limit), Ok(m)) => m.started_at >= limit,
+                    _ => true,
+                },
+            )
+            .collect();
+
+        if kept.len() > retention.keep_runs {
+            kept.drain(..kept.len() - retention.keep_runs);
+        }
+
+        let dropped = lines.len() - kept.len();
+        if dropped == 0 {
+            return Ok(0);
+        }
+
+        // write next to the history then rename, so a crash never leaves a truncated file
+        let tmp_path = path.with_extension("ndjson.tmp");
+        let mut data = kept.join("\n");
+        if !data.is_empty() {
+            data.push('\n');
+        }
+        fs::write(&tmp_path, data).await?;
+        fs::rename(&tmp_path, &path).await?;
+
+        // the logs and artifacts of a run live as long as its history record
+        for line in lines.iter().filter(|l| !kept.contains(l)) {
+            if let Ok(m) = serde_json::from_str::<ExecMetrics>(line) {
+                Logger::rm_run_logs(project_id, &m.run_id).ok();
+                artifacts::rm_run_artifacts(project_id, &m.run_id).ok();
+            }
+        }
+        Ok(dropped)
+    }
 }
 
 pub async fn monitor_process(pid: u32) -> (f32, u64) {
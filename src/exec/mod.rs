@@ -3,8 +3,16 @@ use std::{collections::HashMap, fs::File, process::Stdio, sync::Arc};
 
 use anyhow::Result;
 use tempfile::NamedTempFile;
-use tokio::{process::Command, sync::Mutex};
+use tokio::{
+    process::{Child, Command},
+    sync::Mutex,
+    task::JoinHandle,
+};
 
+use crate::log::job_log::{JobLog, OutputStream};
+
+pub mod artifacts;
+pub mod cache;
 pub mod command;
 pub mod container;
 pub mod metrics;
@@ -14,19 +22,16 @@ pub mod runner;
 #[allow(clippy::enum_variant_names)]
 pub enum OutpuStrategy {
     ToFiles {
-        stdout: File,
-        stderr: File,
+        log: JobLog,
     },
     ToPipeOut {
         cmd: String,
         target: String,
-        stdout: File,
-        stderr: File,
+        log: JobLog,
     },
     ToPipeIn {
         target: String,
-        stdout: File,
-        stderr: File,
+        log: JobLog,
     },
 }
 
@@ -41,6 +46,46 @@ pub struct PipeRegistry {
 }
 
 impl OutpuStrategy {
+    pub fn log(&self) -> &JobLog {
+        match self {
+            OutpuStrategy::ToFiles { log }
+            | OutpuStrategy::ToPipeOut { log, .. }
+            | OutpuStrategy::ToPipeIn { log, .. } => log,
+        }
+    }
+
+    /// Same strategy with its log tagged for the given step (1-based).
+    pub fn for_step(&self, step: usize) -> Self {
+        match self {
+            OutpuStrategy::ToFiles { log } => OutpuStrategy::ToFiles {
+                log: log.for_step(step),
+            },
+            OutpuStrategy::ToPipeOut { cmd, target, log } => OutpuStrategy::ToPipeOut {
+                cmd: cmd.clone(),
+                target: target.clone(),
+                log: log.for_step(step),
+            },
+            OutpuStrategy::ToPipeIn { target, log } => OutpuStrategy::ToPipeIn {
+                target: target.clone(),
+                log: log.for_step(step),
+            },
+        }
+    }
+
+    /// Forward the piped outputs of a spawned child to the job log.
+    /// The returned handles finish once the child closes its outputs.
+    pub fn capture(&self, child: &mut Child) -> Vec<JoinHandle<()>> {
+        let log = self.log();
+        let mut handles = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            handles.push(log.capture(stdout, OutputStream::Stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            handles.push(log.capture(stderr, OutputStream::Stderr));
+        }
+        handles
+    }
+
     async fn configure(
         &self,
         cmd: &mut Command,
@@ -48,11 +93,6 @@ impl OutpuStrategy {
         reg: Arc<Mutex<PipeRegistry>>,
     ) -> Result<CMDManage> {
         match self {
-            OutpuStrategy::ToFiles { stdout, stderr } => {
-                cmd.stdout(Stdio::from(stdout.try_clone()?));
-                cmd.stderr(Stdio::from(stderr.try_clone()?));
-                Ok(CMDManage::Default)
-            }
             OutpuStrategy::ToPipeOut { cmd: c, target, .. }
                 if current == shell_words::split(c)? =>
             {
@@ -66,21 +106,7 @@ impl OutpuStrategy {
                     .insert(target.into(), tmpfile);
                 Ok(CMDManage::PipeOut)
             }
-            OutpuStrategy::ToPipeOut {
-                cmd: _,
-                target: _,
-                stdout,
-                stderr,
-            } => {
-                cmd.stdout(Stdio::from(stdout.try_clone()?));
-                cmd.stderr(Stdio::from(stderr.try_clone()?));
-                Ok(CMDManage::Default)
-            }
-            OutpuStrategy::ToPipeIn {
-                target,
-                stdout,
-                stderr,
-            } if current == shell_words::split(target)? => {
+            OutpuStrategy::ToPipeIn { target, .. } if current == shell_words::split(target)? => {
                 // read in tmp file
                 if let Some(pipe_path) = {
                     let registry: tokio::sync::MutexGuard<'_, PipeRegistry> = reg.lock().await;
@@ -94,13 +120,13 @@ impl OutpuStrategy {
                 } else {
                     cmd.stdin(Stdio::null());
                 }
-                cmd.stdout(Stdio::from(stdout.try_clone()?));
-                cmd.stderr(Stdio::from(stderr.try_clone()?));
+                cmd.stdout(Stdio::piped());
+                cmd.stderr(Stdio::piped());
                 Ok(CMDManage::PipeIn)
             }
-            OutpuStrategy::ToPipeIn { stdout, stderr, .. } => {
-                cmd.stdout(Stdio::from(stdout.try_clone()?));
-                cmd.stderr(Stdio::from(stderr.try_clone()?));
+            _ => {
+                cmd.stdout(Stdio::piped());
+                cmd.stderr(Stdio::piped());
                 Ok(CMDManage::Default)
             }
         }
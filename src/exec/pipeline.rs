@@ -1,29 +1,107 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
 use tokio::sync::Mutex;
 
 use crate::{
-    config::parser::check_dependency_graph,
-    core::watcher::WatchContext,
+    config::{
+        Artifacts, Cache, Job, NotificationEvent,
+        condition::{EvalContext, Expr},
+        parser::{check_dependency_graph, paths_match, prune_for_branch, prune_for_paths},
+        template::{JobOutputs, parse_outputs, render},
+    },
+    core::{id::format_commit, watcher::WatchContext},
     exec::{
-        PipeRegistry,
-        metrics::ExecMetrics,
-        runner::{JobNode, build_dependency_graph, run_step},
+        OutpuStrategy, PipeRegistry, artifacts, cache,
+        command::{CommandFailed, TimedOut},
+        metrics::{ExecMetrics, FailureReason, JobMetrics, JobStatus, RunStatus, RunTrigger},
+        runner::{Deadlines, JobNode, OUTPUT_ENV, build_dependency_graph, run_step, step_timeout},
     },
-    notifications::sender::{discord_send_failure, discord_send_succes},
+    git::repo::Repo,
+    log::logger::Logger,
+    notifications::sender::{discord_send_cancelled, discord_send_failure, discord_send_succes},
 };
 
-pub async fn run_pipeline(ctx: Arc<WatchContext>) -> Result<()> {
+/// Runs the pipeline of a project, returns how the run ended when it did not fail
+pub async fn run_pipeline(ctx: Arc<WatchContext>, trigger: RunTrigger) -> Result<RunStatus> {
+    // record the commit actually on disk, the watched one can be stale for manual runs
+    let commit = Repo::head_commit(&ctx.project_dir)
+        .unwrap_or_else(|_| ctx.repo.branches.last_commit.clone());
+
     let metrics = Arc::new(tokio::sync::Mutex::new(ExecMetrics::new(
         &ctx.id,
         &ctx.repo.name,
+        &commit,
+        &ctx.repo.branches.last_name,
+        trigger,
         ctx.logger.clone(),
     )));
 
+    // every record written during this run is tagged with its id,
+    // the jobs not targeting the branch of the run are left out of the DAG
+    let branch = ctx.repo.branches.current().to_string();
+    let (config, ignored) = prune_for_branch(&ctx.config, &branch)?;
+
+    // files changed since the last build of the branch, unknown for its first build
+    let base = ctx.last_built.get(&branch).cloned();
+    let changed = match &base {
+        Some(base) => match Repo::changed_paths(&ctx.project_dir, Some(base), &commit) {
+            Ok(paths) => Some(paths),
+            Err(e) => {
+                ctx.logger
+                    .warning(&format!(
+                        "Cannot diff against the last build [{}], path filters ignored: {e}",
+                        format_commit(base)
+                    ))
+                    .await?;
+                None
+            }
+        },
+        None => None,
+    };
+    let (config, unchanged) = prune_for_paths(&config, changed.as_deref())?;
+
+    let ctx = {
+        let run_id = metrics.lock().await.run_id.clone();
+        Arc::new(WatchContext {
+            logger: ctx.logger.for_run(&run_id),
+            config,
+            ..(*ctx).clone()
+        })
+    };
+    if !ignored.is_empty() {
+        ctx.logger
+            .info(&format!(
+                "{} job(s) not targeting branch {branch}: {}",
+                ignored.len(),
+                ignored.join(", ")
+            ))
+            .await?;
+    }
+
+    let pipeline = &ctx.config.pipeline;
+    if !paths_match(&pipeline.paths, &pipeline.paths_ignore, changed.as_deref())? {
+        return skip_pipeline(&metrics, &ctx, base.as_deref().unwrap_or_default()).await;
+    }
+    if !unchanged.is_empty() {
+        let mut m = metrics.lock().await;
+        for name in &unchanged {
+            m.job_skipped(name);
+        }
+        ctx.logger
+            .info(&format!(
+                "{} job(s) without matching change: {}",
+                unchanged.len(),
+                unchanged.join(", ")
+            ))
+            .await?;
+    }
+
     let pipe_registry = Arc::new(Mutex::new(PipeRegistry {
         pipes_register: HashMap::new(),
     }));
@@ -32,41 +110,91 @@ pub async fn run_pipeline(ctx: Arc<WatchContext>) -> Result<()> {
     let graph_map = build_dependency_graph(&ctx.config)?;
     let graph: Arc<Mutex<HashMap<String, JobNode>>> = Arc::new(Mutex::new(graph_map));
 
+    // every job of the DAG appears in the report, even the ones never reached
+    {
+        let mut m = metrics.lock().await;
+        for name in graph.lock().await.keys() {
+            m.job_pending(name);
+        }
+    }
+
+    // files seen by `changed()` in the `if:` conditions, the ones of the commit on a first build
+    let changed_paths = match changed {
+        Some(paths) => paths,
+        None if ctx
+            .config
+            .pipeline
+            .jobs
+            .values()
+            .any(|j| j.condition.is_some()) =>
+        {
+            Repo::changed_paths(&ctx.project_dir, None, &commit).unwrap_or_default()
+        }
+        None => Vec::new(),
+    };
+
     let ready_queue = Arc::new(Mutex::new(VecDeque::new()));
     initialize_ready_queue(&graph, &ready_queue).await;
 
+    // the steps still running when it expires are killed by their timeout
+    let deadline = ctx
+        .config
+        .pipeline
+        .deadline
+        .map(|secs| (Instant::now() + Duration::from_secs(secs), secs));
+    let mut deadline_reached = false;
+
     loop {
+        if ctx.cancel.is_cancelled() {
+            return cancel_pipeline(&metrics, &ctx).await;
+        }
+        if let Some((end, secs)) = deadline
+            && Instant::now() >= end
+        {
+            ctx.logger
+                .error(&format!("Pipeline deadline of {secs}s reached"))
+                .await?;
+            deadline_reached = true;
+            break;
+        }
+
         let ready_jobs = drain_ready_queue(&ready_queue).await;
         if ready_jobs.is_empty() {
             break;
         }
 
         // Parallel execution of ready jobs
-        let handles: Vec<_> = ready_jobs
-            .into_iter()
-            .map(|job_name| {
-                let graph_clone = Arc::clone(&graph);
-                let ready_clone = Arc::clone(&ready_queue);
-                let ctx_clone = Arc::clone(&ctx);
-                let metrics_clone = Arc::clone(&metrics);
-                let pipe_registry_clone = Arc::clone(&pipe_registry);
-
-                tokio::spawn(run_job(
-                    job_name,
-                    graph_clone,
-                    ready_clone,
-                    ctx_clone,
-                    metrics_clone,
-                    pipe_registry_clone,
-                ))
-            })
-            .collect();
+        let mut handles = Vec::new();
+        for job_name in ready_jobs {
+            match should_run(&job_name, &graph, &metrics, &ctx, &commit, &changed_paths).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    skip_job(&job_name, &graph, &ready_queue, &metrics, &ctx).await?;
+                    continue;
+                }
+                Err(e) => {
+                    handle_job_failure(&ctx, &metrics, &job_name, e).await?;
+                    release_dependents(&job_name, &graph, &ready_queue).await;
+                    continue;
+                }
+            }
 
-        wait_jobs(handles, &metrics, &ctx).await?;
+            let handle = tokio::spawn(run_job(
+                job_name.clone(),
+                Arc::clone(&graph),
+                Arc::clone(&ready_queue),
+                Arc::clone(&ctx),
+                Arc::clone(&metrics),
+                Arc::clone(&pipe_registry),
+                deadline,
+            ));
+            handles.push((job_name, handle));
+        }
+
+        wait_jobs(handles, &graph, &ready_queue, &metrics, &ctx).await?;
     }
 
-    finalize_pipeline(&metrics, &ctx).await?;
-    Ok(())
+    finalize_pipeline(&metrics, &ctx, deadline_reached).await
 }
 
 /// Init job queue with job ready to execute based on graph
@@ -89,6 +217,91 @@ async fn drain_ready_queue(ready_queue: &Arc<Mutex<VecDeque<String>>>) -> Vec<St
     queue.drain(..).collect()
 }
 
+/// Decide if a job whose dependencies are over runs.
+/// Without `if:` it runs only when its dependencies succeeded, and with `fail_fast`
+/// nothing else failed in the run.
+async fn should_run(
+    job_name: &str,
+    graph: &Arc<Mutex<HashMap<String, JobNode>>>,
+    metrics: &Arc<Mutex<ExecMetrics>>,
+    ctx: &WatchContext,
+    commit: &str,
+    changed_paths: &[String],
+) -> Result<bool> {
+    let g = graph.lock().await;
+    let m = metrics.lock().await;
+    let node = &g[job_name];
+    let status_of = |name: &str| m.jobs.get(name).map(|j| j.status.clone());
+
+    // every job the node depends on, even indirectly
+    let mut upstream = HashSet::new();
+    let mut stack = node.depend_on.clone();
+    while let Some(name) = stack.pop() {
+        if upstream.insert(name.clone())
+            && let Some(dep) = g.get(&name)
+        {
+            stack.extend(dep.depend_on.iter().cloned());
+        }
+    }
+
+    let failure = upstream
+        .iter()
+        .any(|name| status_of(name) == Some(JobStatus::Failed));
+    let outputs = m.outputs_of(&upstream);
+    // with fail_fast, a failure anywhere in the run stops the jobs not asking for it
+    let stopped =
+        ctx.config.pipeline.fail_fast && m.jobs.values().any(|j| j.status == JobStatus::Failed);
+    let success = !failure
+        && !stopped
+        && node
+            .depend_on
+            .iter()
+            .all(|name| status_of(name).is_some_and(|s| s.succeeded()));
+
+    let Some(condition) = &node.job.condition else {
+        return Ok(success);
+    };
+    Expr::parse(condition)?.eval(&EvalContext {
+        branch: ctx.repo.branches.current(),
+        commit,
+        changed_paths,
+        env: node.job.env.as_ref(),
+        success,
+        failure,
+        outputs: Some(&outputs),
+    })
+}
+
+/// Record a job that will not run, its dependents are evaluated in turn
+async fn skip_job(
+    job_name: &str,
+    graph: &Arc<Mutex<HashMap<String, JobNode>>>,
+    ready_queue: &Arc<Mutex<VecDeque<String>>>,
+    metrics: &Arc<Mutex<ExecMetrics>>,
+    ctx: &WatchContext,
+) -> Result<()> {
+    metrics.lock().await.job_skipped(job_name);
+    let condition = graph.lock().await[job_name].job.condition.clone();
+    // jobs skipped after a failure are counted once the run is over
+    if let Some(condition) = condition {
+        ctx.logger
+            .for_job(job_name)
+            .info(&format!("Job {job_name} skipped (if: {condition})"))
+            .await?;
+    }
+    release_dependents(job_name, graph, ready_queue).await;
+    Ok(())
+}
+
+async fn release_dependents(
+    job_name: &str,
+    graph: &Arc<Mutex<HashMap<String, JobNode>>>,
+    ready_queue: &Arc<Mutex<VecDeque<String>>>,
+) {
+    let dependents = graph.lock().await[job_name].dependents.clone();
+    update_dependents(graph, ready_queue, &dependents).await;
+}
+
 /// Runs a single job with step and dependency management
 async fn run_job(
     job_name: String,
@@ -97,6 +310,7 @@ async fn run_job(
     ctx: Arc<WatchContext>,
     metrics: Arc<Mutex<ExecMetrics>>,
     pipe_registry: Arc<Mutex<PipeRegistry>>,
+    deadline: Option<(Instant, u64)>,
 ) -> Result<bool> {
     let (job_arc, dependents) = {
         let g = graph.lock().await;
@@ -105,42 +319,476 @@ async fn run_job(
     };
 
     // init job metrics
-    {
+    let run_id = {
         let mut m = metrics.lock().await;
         m.job_started(&job_name);
-    }
-    ctx.logger.job_start(&job_name).await?;
+        m.run_id.clone()
+    };
+    let logger = ctx.logger.for_job(&job_name);
+    logger.job_start(&job_name).await?;
 
-    let output_strategy = ctx.config.drop_strategy(&job_name, &ctx)?;
-    for step in &job_arc.steps {
-        if let Err(e) = run_step(
+    let output_strategy = ctx.config.drop_strategy(&job_name, &run_id, &ctx)?;
+    let output_file = tempfile::NamedTempFile::new()?;
+    let outputs = {
+        let m = metrics.lock().await;
+        m.outputs_of(m.jobs.keys())
+    };
+    let job = prepare_env(&job_arc, &outputs, output_file.path())?;
+    let deadlines = Deadlines {
+        job: job
+            .timeout
+            .map(|secs| (Instant::now() + Duration::from_secs(secs), secs)),
+        pipeline: deadline,
+    };
+    if let Err(e) = restore_artifacts(&ctx, &logger, &run_id, &job).await {
+        return fail_job(
+            &ctx,
+            &metrics,
+            &graph,
+            &ready_queue,
+            &job_name,
+            &dependents,
+            e,
+        )
+        .await;
+    }
+    let job_cache = match restore_cache(&ctx, &logger, &job, &outputs).await {
+        Ok(job_cache) => job_cache,
+        Err(e) => {
+            return fail_job(
+                &ctx,
+                &metrics,
+                &graph,
+                &ready_queue,
+                &job_name,
+                &dependents,
+                e,
+            )
+            .await;
+        }
+    };
+    let binds = job_cache.as_ref().map_or(&[][..], |c| &c.binds);
+    // failures of the steps allowed to fail
+    let mut warnings = Vec::new();
+    for i in 0..job.steps.len() {
+        if ctx.cancel.is_cancelled() {
+            return job_cancelled(&metrics, &logger, &job_name).await;
+        }
+        if let Err(e) = run_step_with_retry(
             &ctx,
-            step,
-            &job_arc.env,
-            &output_strategy,
+            &job_name,
+            &job,
+            i + 1,
+            &output_strategy.for_step(i + 1),
+            &metrics,
             Arc::clone(&pipe_registry),
+            &deadlines,
+            binds,
         )
         .await
         {
-            handle_job_failure(&ctx, &metrics, &job_name, e).await?;
-            return Err(anyhow::anyhow!("Job failed: {job_name}"));
+            if ctx.cancel.is_cancelled() {
+                return job_cancelled(&metrics, &logger, &job_name).await;
+            }
+            if job.steps[i].allow_failure {
+                logger
+                    .warning(&format!(
+                        "Step {} failed, continuing (allow_failure)",
+                        i + 1
+                    ))
+                    .await?;
+                warnings.push(format!("step {}: {e}", i + 1));
+                continue;
+            }
+            if job.allow_failure {
+                logger
+                    .warning(&format!(
+                        "Job {job_name} failed, continuing (allow_failure)"
+                    ))
+                    .await?;
+                warnings.push(e.to_string());
+                break;
+            }
+            collect_outputs(&metrics, &logger, &job_name, output_file.path()).await?;
+            return fail_job(
+                &ctx,
+                &metrics,
+                &graph,
+                &ready_queue,
+                &job_name,
+                &dependents,
+                e,
+            )
+            .await;
         }
     }
-
-    // set job  as finished in metrics
+    collect_outputs(&metrics, &logger, &job_name, output_file.path()).await?;
+    if let Some(kept) = &job.artifacts
+        && let Err(e) = save_artifacts(&ctx, &logger, &run_id, &job_name, kept).await
     {
-        let mut m = metrics.lock().await;
-        m.job_finished(&job_name, true);
+        return fail_job(
+            &ctx,
+            &metrics,
+            &graph,
+            &ready_queue,
+            &job_name,
+            &dependents,
+            e,
+        )
+        .await;
+    }
+    if let (Some(kept), Some(job_cache)) = (&job.cache, &job_cache) {
+        // a job cut short by its `allow_failure` would store a half-built cache under the key
+        if !warnings.is_empty() {
+            logger
+                .warning("Cache not saved: the job has allowed failures")
+                .await?;
+        } else if let Err(e) = save_cache(&ctx, &logger, kept, job_cache).await {
+            logger.warning(&format!("Cache not saved: {e}")).await?;
+        }
     }
 
-    ctx.logger
-        .info(&format!("Job {job_name} succeeded"))
-        .await?;
+    // set job  as finished in metrics
+    if warnings.is_empty() {
+        metrics.lock().await.job_finished(&job_name, true);
+        logger.info(&format!("Job {job_name} succeeded")).await?;
+    } else {
+        metrics
+            .lock()
+            .await
+            .job_finished_with_warnings(&job_name, warnings);
+        logger
+            .warning(&format!("Job {job_name} succeeded with warnings"))
+            .await?;
+    }
     update_dependents(&graph, &ready_queue, &dependents).await;
-    ctx.logger.job_end(&job_name).await?;
+    logger.job_end(&job_name).await?;
     Ok(true)
 }
 
+/// The job with the outputs of the upstream jobs substituted in its `env` and the ones of its steps,
+/// `FLEET_OUTPUT` gives its steps the file collecting its own outputs
+fn prepare_env(job: &Job, outputs: &JobOutputs, output_file: &Path) -> Result<Job> {
+    let mut job = job.clone();
+    let envs = std::iter::once(&mut job.env).chain(job.steps.iter_mut().map(|s| &mut s.env));
+    for value in envs.flatten().flat_map(|env| env.values_mut()) {
+        *value = render(value, outputs)?;
+    }
+    job.env
+        .get_or_insert_default()
+        .insert(OUTPUT_ENV.to_string(), output_file.display().to_string());
+    Ok(job)
+}
+
+/// Record the failure of a started job and release its dependents
+async fn fail_job(
+    ctx: &Arc<WatchContext>,
+    metrics: &Arc<Mutex<ExecMetrics>>,
+    graph: &Arc<Mutex<HashMap<String, JobNode>>>,
+    ready_queue: &Arc<Mutex<VecDeque<String>>>,
+    job_name: &str,
+    dependents: &[String],
+    error: anyhow::Error,
+) -> Result<bool> {
+    handle_job_failure(ctx, metrics, job_name, error).await?;
+    update_dependents(graph, ready_queue, dependents).await;
+    Err(anyhow::anyhow!("Job failed: {job_name}"))
+}
+
+/// Copy the artifacts of the jobs listed in `download_artifacts` into the working tree
+async fn restore_artifacts(
+    ctx: &Arc<WatchContext>,
+    logger: &Logger,
+    run_id: &str,
+    job: &Job,
+) -> Result<()> {
+    for dep in &job.download_artifacts {
+        let source = artifacts::job_dir(&ctx.id, run_id, dep);
+        if !source.exists() {
+            logger
+                .warning(&format!("No artifacts of {dep} to restore"))
+                .await?;
+            continue;
+        }
+        let project_dir = PathBuf::from(&ctx.project_dir);
+        let restored =
+            tokio::task::spawn_blocking(move || artifacts::restore(&source, &project_dir))
+                .await??;
+        logger
+            .info(&format!("{restored} artifact file(s) of {dep} restored"))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Keep the `artifacts` of a job that succeeded
+async fn save_artifacts(
+    ctx: &Arc<WatchContext>,
+    logger: &Logger,
+    run_id: &str,
+    job_name: &str,
+    kept: &Artifacts,
+) -> Result<()> {
+    let dest = artifacts::job_dir(&ctx.id, run_id, job_name);
+    let project_dir = PathBuf::from(&ctx.project_dir);
+    let paths = kept.paths.clone();
+    let saved =
+        tokio::task::spawn_blocking(move || artifacts::save(&project_dir, &paths, &dest)).await??;
+    if saved == 0 {
+        logger.warning("No file matches the artifact paths").await?;
+    } else {
+        logger
+            .info(&format!("{saved} artifact file(s) saved"))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Cache of a running job: its rendered key, whether an entry of this very key was restored,
+/// and the host directories mounted in its containers
+struct JobCache {
+    key: String,
+    hit: bool,
+    mount_dir: Option<tempfile::TempDir>,
+    binds: Vec<String>,
+}
+
+/// Render the cache key of a job and restore its entry, a restore failing is only logged
+async fn restore_cache(
+    ctx: &Arc<WatchContext>,
+    logger: &Logger,
+    job: &Job,
+    outputs: &JobOutputs,
+) -> Result<Option<JobCache>> {
+    let Some(kept) = job.cache.clone() else {
+        return Ok(None);
+    };
+    let project_dir = PathBuf::from(&ctx.project_dir);
+    let outputs = outputs.clone();
+    let (key, restore_keys) = {
+        let project_dir = project_dir.clone();
+        let kept = kept.clone();
+        tokio::task::spawn_blocking(move || {
+            let key = cache::render_key(&kept.key, &outputs, &project_dir)?;
+            let restore_keys = kept
+                .restore_keys
+                .iter()
+                .map(|k| cache::render_key(k, &outputs, &project_dir))
+                .collect::<Result<Vec<_>>>()?;
+            Ok::<_, anyhow::Error>((key, restore_keys))
+        })
+        .await??
+    };
+
+    let mut job_cache = JobCache {
+        key: key.clone(),
+        hit: false,
+        mount_dir: None,
+        binds: Vec::new(),
+    };
+    if kept.paths.iter().any(|p| p.starts_with('/')) {
+        let mount_dir = tempfile::tempdir()?;
+        job_cache.binds = cache::mounts(&kept.paths, mount_dir.path())?;
+        job_cache.mount_dir = Some(mount_dir);
+    }
+
+    let mount_dir = job_cache.mount_dir.as_ref().map(|d| d.path().to_path_buf());
+    let id = ctx.id.clone();
+    let restored = tokio::task::spawn_blocking(move || {
+        let Some((dir, entry)) = cache::lookup(&id, &key, &restore_keys)? else {
+            return Ok(None);
+        };
+        let files = cache::restore(&dir, &project_dir, mount_dir.as_deref())?;
+        Ok::<_, anyhow::Error>(Some((entry.key, files)))
+    })
+    .await?;
+    match restored {
+        Ok(Some((found, files))) if found == job_cache.key => {
+            job_cache.hit = true;
+            logger
+                .info(&format!(
+                    "Cache hit for key {found}, {files} file(s) restored"
+                ))
+                .await?;
+        }
+        Ok(Some((found, files))) => {
+            logger
+                .info(&format!(
+                    "Cache miss for key {}, {files} file(s) restored from key {found}",
+                    job_cache.key
+                ))
+                .await?;
+        }
+        Ok(None) => {
+            logger
+                .info(&format!("Cache miss for key {}", job_cache.key))
+                .await?;
+        }
+        Err(e) => {
+            logger.warning(&format!("Cache not restored: {e}")).await?;
+        }
+    }
+    Ok(Some(job_cache))
+}
+
+/// Store the cache of a job that succeeded when its key had no entry, then evict the least recently used
+/// entries over the size limit.
+async fn save_cache(
+    ctx: &Arc<WatchContext>,
+    logger: &Logger,
+    kept: &Cache,
+    job_cache: &JobCache,
+) -> Result<()> {
+    if job_cache.hit {
+        return Ok(());
+    }
+    let id = ctx.id.clone();
+    let key = job_cache.key.clone();
+    let project_dir = PathBuf::from(&ctx.project_dir);
+    let paths = kept.paths.clone();
+    let mount_dir = job_cache.mount_dir.as_ref().map(|d| d.path().to_path_buf());
+    let saved = tokio::task::spawn_blocking(move || {
+        let entry = cache::save(&id, &key, &project_dir, &paths, mount_dir.as_deref())?;
+        let evicted = cache::evict(cache::max_size())?;
+        Ok::<_, anyhow::Error>((entry, evicted))
+    })
+    .await?;
+    let (entry, evicted) = saved?;
+    if let Some(entry) = entry {
+        logger
+            .info(&format!(
+                "Cache saved under key {} ({} bytes)",
+                entry.key, entry.size
+            ))
+            .await?;
+    }
+    for evicted in evicted {
+        logger
+            .info(&format!("Cache entry {evicted} evicted"))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Record the `KEY=value` lines written by the steps of a job to `FLEET_OUTPUT`
+async fn collect_outputs(
+    metrics: &Arc<Mutex<ExecMetrics>>,
+    logger: &Logger,
+    job_name: &str,
+    output_file: &Path,
+) -> Result<()> {
+    let content = tokio::fs::read_to_string(output_file)
+        .await
+        .unwrap_or_default();
+    let (outputs, invalid) = parse_outputs(&content);
+    for line in invalid {
+        logger
+            .warning(&format!("Output line ignored, expected KEY=value: {line}"))
+            .await?;
+    }
+    metrics.lock().await.job_outputs(job_name, outputs);
+    Ok(())
+}
+
+/// Run a step (1-based index), again while it fails and its retry policy allows it.
+/// Every attempt of a step with a policy is recorded in the metrics of the job.
+/// A step killed by the timeout of its job or the deadline of the pipeline is not retried.
+#[allow(clippy::too_many_arguments)]
+async fn run_step_with_retry(
+    ctx: &Arc<WatchContext>,
+    job_name: &str,
+    job: &Job,
+    step_index: usize,
+    output_strategy: &OutpuStrategy,
+    metrics: &Arc<Mutex<ExecMetrics>>,
+    pipe_registry: Arc<Mutex<PipeRegistry>>,
+    deadlines: &Deadlines,
+    binds: &[String],
+) -> Result<()> {
+    let step = &job.steps[step_index - 1];
+    let logger = output_strategy.log().logger();
+    let step_secs = step_timeout(ctx, step);
+    let run = |pipe_registry| async move {
+        let (timeout, scope) = deadlines.step_timeout(step_secs);
+        let result = if timeout == 0 {
+            Err(TimedOut { secs: 0 }.into())
+        } else {
+            run_step(
+                ctx,
+                job,
+                step,
+                output_strategy,
+                pipe_registry,
+                timeout,
+                binds,
+            )
+            .await
+        };
+        match (result, deadlines.limit(scope)) {
+            (Err(e), Some(limit)) if e.is::<TimedOut>() => {
+                logger
+                    .error(&format!(
+                        "Job {job_name} timed out ({scope} timeout: {limit}s)"
+                    ))
+                    .await?;
+                Err(TimedOut { secs: limit }.into())
+            }
+            (result, _) => result,
+        }
+    };
+
+    let Some(retry) = step.retry.as_ref().or(job.retry.as_ref()) else {
+        run(pipe_registry).await?;
+        return Ok(());
+    };
+
+    let attempts = retry.attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        let result = run(Arc::clone(&pipe_registry)).await;
+        let exit_code = match &result {
+            Ok(output) => Some(output.as_ref().and_then(|o| o.status_code).unwrap_or(0)),
+            Err(e) => e.downcast_ref::<CommandFailed>().and_then(|f| f.code),
+        };
+        metrics
+            .lock()
+            .await
+            .step_attempt(job_name, step_index, attempt, exit_code);
+
+        let err = match result {
+            Ok(_) => {
+                if attempt > 1 {
+                    logger
+                        .info(&format!(
+                            "Step {step_index} succeeded on attempt {attempt}/{attempts}"
+                        ))
+                        .await?;
+                }
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        // the job or the pipeline is out of time, another attempt would be killed at once
+        let out_of_time = err.is::<TimedOut>() && deadlines.step_timeout(step_secs).0 == 0;
+        if attempt >= attempts || ctx.cancel.is_cancelled() || out_of_time {
+            return Err(err);
+        }
+
+        let delay = retry.delay_after(attempt);
+        logger
+            .warning(&format!(
+                "Step {step_index} failed (attempt {attempt}/{attempts}), retrying in {}s",
+                delay.as_secs()
+            ))
+            .await?;
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = ctx.cancel.cancelled() => return Err(err),
+        }
+        attempt += 1;
+    }
+}
+
 async fn update_dependents(
     graph: &Arc<Mutex<HashMap<String, JobNode>>>,
     ready_queue: &Arc<Mutex<VecDeque<String>>>,
@@ -149,78 +797,62 @@ async fn update_dependents(
     let mut g = graph.lock().await;
     for dep_name in dependents {
         let dep_node = g.get_mut(dep_name).unwrap();
-        if dep_node.remaining_dependencies != usize::MAX {
-            dep_node.remaining_dependencies -= 1;
-            if dep_node.remaining_dependencies == 0 {
-                ready_queue.lock().await.push_back(dep_name.clone());
-            }
+        dep_node.remaining_dependencies -= 1;
+        if dep_node.remaining_dependencies == 0 {
+            ready_queue.lock().await.push_back(dep_name.clone());
         }
     }
 }
 
-/// Manage job failure (log, métrics, notifications)
+/// Record a job stopped by a cancellation, no failure notification is sent for it
+async fn job_cancelled(
+    metrics: &Arc<Mutex<ExecMetrics>>,
+    logger: &Logger,
+    job_name: &str,
+) -> Result<bool> {
+    metrics.lock().await.job_cancelled(job_name);
+    logger.warning(&format!("Job {job_name} cancelled")).await?;
+    Err(anyhow::anyhow!("Job cancelled: {job_name}"))
+}
+
+/// Manage job failure (log, métrics), the failure is notified once the pipeline is over
 async fn handle_job_failure(
     ctx: &Arc<WatchContext>,
     metrics: &Arc<Mutex<ExecMetrics>>,
     job_name: &str,
     error: anyhow::Error,
 ) -> Result<()> {
-    let mut m = metrics.lock().await;
-    m.job_finished(job_name, false);
-
-    let need_notif_on_failure = ctx
-        .config
-        .pipeline
-        .notifications
-        .as_ref()
-        .map(|notif| notif.on.contains(&"failure".to_string()))
-        .unwrap_or(false);
-
-    if need_notif_on_failure {
-        let err = error.to_string();
-        let lines: Vec<&str> = err.lines().collect();
-        let first_line = lines.first().unwrap_or(&"");
-        let second_line = lines.get(1).unwrap_or(&"");
-        discord_send_failure(
-            ctx,
-            &format!(
-                "**Job** `{job_name}` **failed**
-                {first_line}
-                **Error:** `{second_line}`",
-            ),
-            &m,
-        )
-        .await?;
+    {
+        let mut m = metrics.lock().await;
+        let reason = if error.is::<TimedOut>() {
+            FailureReason::Timeout
+        } else {
+            FailureReason::Error
+        };
+        m.job_finished(job_name, false);
+        m.job_error(job_name, &error.to_string(), reason);
     }
 
-    ctx.logger.error(&format!("Job {job_name} failed")).await?;
+    ctx.logger
+        .for_job(job_name)
+        .error(&format!("Job {job_name} failed"))
+        .await?;
     Ok(())
 }
 
+/// Wait for every job of the wave
 async fn wait_jobs(
-    handles: Vec<tokio::task::JoinHandle<Result<bool, anyhow::Error>>>,
+    handles: Vec<(String, tokio::task::JoinHandle<Result<bool, anyhow::Error>>)>,
+    graph: &Arc<Mutex<HashMap<String, JobNode>>>,
+    ready_queue: &Arc<Mutex<VecDeque<String>>>,
     metrics: &Arc<Mutex<ExecMetrics>>,
     ctx: &Arc<WatchContext>,
 ) -> Result<()> {
-    for h in handles {
-        match h.await {
-            Ok(inner) => match inner {
-                Ok(_) => {}
-                Err(e) => {
-                    let mut m = metrics.lock().await;
-                    m.finalize();
-                    m.save().await.ok();
-                    ctx.logger.error(&format!("Pipeline failed: {e}")).await?;
-                    return Err(anyhow::anyhow!("Pipeline failed: {e}"));
-                }
-            },
-            Err(e) => {
-                let mut m = metrics.lock().await;
-                m.finalize();
-                m.save().await.ok();
-                ctx.logger.error(&format!("Pipeline failed: {e}")).await?;
-                return Err(anyhow::anyhow!("Pipeline failed: {e}"));
-            }
+    for (job_name, h) in handles {
+        if let Err(e) = h.await {
+            // the job task panicked, nothing recorded its failure
+            handle_job_failure(ctx, metrics, &job_name, e.into()).await?;
+            release_dependents(&job_name, graph, ready_queue).await;
         }
     }
     Ok(())
@@ -229,22 +861,146 @@ async fn wait_jobs(
 async fn finalize_pipeline(
     metrics: &Arc<Mutex<ExecMetrics>>,
     ctx: &Arc<WatchContext>,
-) -> Result<()> {
+    deadline_reached: bool,
+) -> Result<RunStatus> {
     let mut m = metrics.lock().await;
-    m.finalize();
-    m.save().await?;
+    // still pending without failure nor deadline: jobs waiting on each other never became ready
+    let mut stuck: Vec<String> = m
+        .jobs
+        .values()
+        .filter(|j| j.status == JobStatus::Pending)
+        .map(|j| j.name.clone())
+        .collect();
+    stuck.sort();
+    // never reached, a job task panicked or the deadline expired
+    m.skip_pending();
 
-    let need_notif_on_success = ctx
-        .config
+    let mut failed: Vec<&JobMetrics> = m
+        .jobs
+        .values()
+        .filter(|j| j.status == JobStatus::Failed)
+        .collect();
+    if failed.is_empty() && !deadline_reached && stuck.is_empty() {
+        m.finalize(RunStatus::Succeeded);
+        persist_metrics(&m, ctx).await?;
+
+        if notify_on(ctx, NotificationEvent::Success) {
+            discord_send_succes(ctx, &m).await?;
+        }
+        return Ok(RunStatus::Succeeded);
+    }
+
+    failed.sort_by_key(|j| j.finished_at);
+    let description = match failed.first() {
+        Some(j) if j.reason == Some(FailureReason::Timeout) => format!(
+            "**Job** `{}` **timed out**\n{}",
+            j.name,
+            j.error.as_deref().unwrap_or_default()
+        ),
+        Some(j) => {
+            let err = j.error.clone().unwrap_or_default();
+            let lines: Vec<&str> = err.lines().collect();
+            let first_line = lines.first().unwrap_or(&"");
+            let second_line = lines.get(1).unwrap_or(&"");
+            format!(
+                "**Job** `{}` **failed**
+                {first_line}
+                **Error:** `{second_line}`",
+                j.name
+            )
+        }
+        None if deadline_reached => "**Pipeline deadline reached**".to_string(),
+        None => format!("**Jobs never ready:** `{}`", stuck.join("`, `")),
+    };
+    let names = if failed.is_empty() && deadline_reached {
+        "deadline reached".to_string()
+    } else if failed.is_empty() {
+        format!("jobs never ready ({})", stuck.join(", "))
+    } else {
+        failed
+            .iter()
+            .map(|j| j.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    m.finalize(RunStatus::Failed);
+    persist_metrics(&m, ctx).await.ok();
+    ctx.logger
+        .error(&format!("Pipeline failed: {names}"))
+        .await?;
+    // the skipped jobs are listed in the run report (`fleet show`)
+    let skipped = m
+        .jobs
+        .values()
+        .filter(|j| j.status == JobStatus::Skipped)
+        .count();
+    if skipped > 0 {
+        ctx.logger
+            .warning(&format!("{skipped} job(s) skipped"))
+            .await?;
+    }
+
+    if notify_on(ctx, NotificationEvent::Failure) {
+        discord_send_failure(ctx, &description, &m).await?;
+    }
+    Err(anyhow::anyhow!("Pipeline failed: {names}"))
+}
+
+/// Close a run without any change matching the `paths` filters of the pipeline,
+/// it is recorded in the history as skipped
+async fn skip_pipeline(
+    metrics: &Arc<Mutex<ExecMetrics>>,
+    ctx: &Arc<WatchContext>,
+    base: &str,
+) -> Result<RunStatus> {
+    let mut m = metrics.lock().await;
+    m.finalize(RunStatus::Skipped);
+    persist_metrics(&m, ctx).await?;
+    ctx.logger
+        .info(&format!(
+            "No change matching the paths of the pipeline since [{}], run skipped",
+            format_commit(base)
+        ))
+        .await?;
+    Ok(RunStatus::Skipped)
+}
+
+/// Close a cancelled run: jobs that never started are skipped, the run is recorded as cancelled
+async fn cancel_pipeline(
+    metrics: &Arc<Mutex<ExecMetrics>>,
+    ctx: &Arc<WatchContext>,
+) -> Result<RunStatus> {
+    let mut m = metrics.lock().await;
+    m.skip_pending();
+    m.finalize(RunStatus::Cancelled);
+    persist_metrics(&m, ctx).await.ok();
+    ctx.logger.warning("Pipeline cancelled").await?;
+
+    if notify_on(ctx, NotificationEvent::Cancelled) || notify_on(ctx, NotificationEvent::Failure) {
+        discord_send_cancelled(ctx, &m).await?;
+    }
+    Err(anyhow::anyhow!("Pipeline cancelled"))
+}
+
+/// Whether the notifications of the project are enabled for `event`
+fn notify_on(ctx: &WatchContext, event: NotificationEvent) -> bool {
+    ctx.config
         .pipeline
         .notifications
         .as_ref()
-        .map(|notif| notif.on.contains(&"success".to_string()))
-        .unwrap_or(false);
+        .map(|notif| notif.on.contains(&event))
+        .unwrap_or(false)
+}
 
-    if need_notif_on_success {
-        discord_send_succes(ctx, &m).await?;
+/// Append the run to the project history and drop the runs outside the retention policy
+async fn persist_metrics(m: &ExecMetrics, ctx: &Arc<WatchContext>) -> Result<()> {
+    m.save().await?;
+    let dropped = ExecMetrics::apply_retention(&ctx.id, &ctx.config.history).await?;
+    if dropped > 0 {
+        ctx.logger
+            .info(&format!("{dropped} old run(s) removed from history"))
+            .await?;
     }
-
     Ok(())
 }
@@ -1,11 +1,16 @@
 #![allow(dead_code)]
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
 use anyhow::Result;
 use tokio::sync::Mutex;
 
 use crate::{
-    config::{Cmd, Job, ProjectConfig},
+    config::{Cmd, Job, ProjectConfig, relative_dir},
     core::watcher::WatchContext,
     exec::{
         OutpuStrategy, PipeRegistry,
@@ -16,6 +21,77 @@ use crate::{
 };
 
 const DEFAULT_TIMEOUT: u64 = 300;
+
+/// Env variable giving the steps the file their outputs are written to
+pub const OUTPUT_ENV: &str = "FLEET_OUTPUT";
+
+/// Path of the output file inside a container
+const CONTAINER_OUTPUT: &str = "/fleet/output";
+
+/// Seconds a step may run on its own: its `timeout`, else the one of the project.
+pub fn step_timeout(ctx: &WatchContext, step: &Cmd) -> u64 {
+    step.timeout
+        .or(ctx.config.timeout)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// The limit a step timeout comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutScope {
+    Step,
+    Job,
+    Pipeline,
+}
+
+impl std::fmt::Display for TimeoutScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeoutScope::Step => write!(f, "step"),
+            TimeoutScope::Job => write!(f, "job"),
+            TimeoutScope::Pipeline => write!(f, "pipeline"),
+        }
+    }
+}
+
+/// Time limits around the steps of a job: the `timeout` of the job and the `deadline` of the pipeline,
+/// each one as (expiry, configured seconds).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Deadlines {
+    pub job: Option<(Instant, u64)>,
+    pub pipeline: Option<(Instant, u64)>,
+}
+
+impl Deadlines {
+    /// Timeout of a step allowed `step_secs` on its own, and the limit it comes from.
+    /// 0 once the job or the pipeline is out of time.
+    pub fn step_timeout(&self, step_secs: u64) -> (u64, TimeoutScope) {
+        let now = Instant::now();
+        let remaining =
+            |end: Instant| end.saturating_duration_since(now).as_secs_f64().ceil() as u64;
+
+        let mut timeout = (step_secs, TimeoutScope::Step);
+        if let Some((end, _)) = self.job
+            && remaining(end) < timeout.0
+        {
+            timeout = (remaining(end), TimeoutScope::Job);
+        }
+        if let Some((end, _)) = self.pipeline
+            && remaining(end) < timeout.0
+        {
+            timeout = (remaining(end), TimeoutScope::Pipeline);
+        }
+        timeout
+    }
+
+    /// configured seconds of a job or pipeline limit
+    pub fn limit(&self, scope: TimeoutScope) -> Option<u64> {
+        match scope {
+            TimeoutScope::Step => None,
+            TimeoutScope::Job => self.job.map(|(_, secs)| secs),
+            TimeoutScope::Pipeline => self.pipeline.map(|(_, secs)| secs),
+        }
+    }
+}
 pub struct JobNode {
     pub job: Arc<Job>,
     pub depend_on: Vec<String>, // names of jobs this job depends on
@@ -31,13 +107,7 @@ pub fn build_dependency_graph(config: &ProjectConfig) -> Result<HashMap<String,
             return Err(anyhow::anyhow!("Job: {} cannot depend on itself", name));
         }
 
-        let job_needs = if !job.pipe.is_empty() && !job.needs.contains(&job.pipe) {
-            let mut j = job.needs.clone();
-            j.push(job.pipe.clone());
-            j
-        } else {
-            job.needs.clone()
-        };
+        let job_needs: Vec<String> = job.dependencies().cloned().collect();
 
         let node = JobNode {
             job: Arc::new(job.clone()),
@@ -63,36 +133,61 @@ pub fn build_dependency_graph(config: &ProjectConfig) -> Result<HashMap<String,
 
 pub async fn run_step(
     ctx: &WatchContext,
+    job: &Job,
     step: &Cmd,
-    env: &Option<HashMap<String, String>>,
     output_strategy: &OutpuStrategy,
     pipe_registry: Arc<Mutex<PipeRegistry>>,
+    timeout: u64,
+    cache_binds: &[String],
 ) -> Result<Option<CommandOutput>> {
-    let parts = shell_words::split(&step.cmd)?;
+    let shell = job.shell.as_ref().or(ctx.config.pipeline.shell.as_ref());
+    let parts = step.argv(shell.map(String::as_str))?;
+    let env = &step.merged_env(job);
+    let rel_dir = working_dir(ctx, job, step)?;
+    let dir = Path::new(&ctx.project_dir).join(&rel_dir);
+    let dir = &dir.to_string_lossy();
 
     if let Some(container) = &step.container {
+        let mut binds = vec![format!("{}:/app", ctx.project_dir)];
+        binds.extend(cache_binds.iter().cloned());
+        let mut env = env.clone();
+        if let Some(output) = env.as_mut().and_then(|e| e.get_mut(OUTPUT_ENV)) {
+            binds.push(format!("{output}:{CONTAINER_OUTPUT}"));
+            *output = CONTAINER_OUTPUT.to_string();
+        }
         contain_cmd(
             container,
             parts,
-            env.clone(),
-            &ctx.project_dir,
-            &ctx.log_path().display().to_string(),
-            &ctx.logger,
-            Some(ctx.config.timeout.unwrap_or(DEFAULT_TIMEOUT)),
+            env,
+            binds,
+            &Path::new("/app").join(&rel_dir).to_string_lossy(),
+            output_strategy.log(),
+            Some(timeout),
+            &step.success_codes,
+            &ctx.cancel,
         )
         .await?;
     } else if step.blocking {
-        background_process(ctx, parts, &ctx.logger, env.clone()).await?;
+        background_process(
+            parts,
+            dir,
+            output_strategy.log().logger(),
+            env.clone(),
+            output_strategy,
+        )
+        .await?;
     } else {
         return Ok(Some(
             timeout_process(
                 ctx,
                 parts,
-                &ctx.logger,
+                dir,
+                output_strategy.log().logger(),
                 env.clone(),
-                ctx.config.timeout.unwrap_or(DEFAULT_TIMEOUT),
+                timeout,
                 output_strategy,
                 pipe_registry,
+                &step.success_codes,
             )
             .await?,
         ));
@@ -100,13 +195,38 @@ pub async fn run_step(
     Ok(None)
 }
 
+/// Directory of a step relative to the repository root, empty for the root itself.
+/// A symlink leading out of the repository is rejected like a `..` would be.
+fn working_dir(ctx: &WatchContext, job: &Job, step: &Cmd) -> Result<PathBuf> {
+    let Some(dir) = step
+        .working_directory
+        .as_ref()
+        .or(job.working_directory.as_ref())
+    else {
+        return Ok(PathBuf::new());
+    };
+    let rel_dir = relative_dir(dir)?;
+    let root = Path::new(&ctx.project_dir).canonicalize()?;
+    let resolved = root
+        .join(&rel_dir)
+        .canonicalize()
+        .map_err(|e| anyhow::anyhow!("working_directory '{dir}' not found: {e}"))?;
+    if !resolved.starts_with(&root) || !resolved.is_dir() {
+        return Err(anyhow::anyhow!(
+            "working_directory '{dir}' is not a directory of the repository"
+        ));
+    }
+    Ok(rel_dir)
+}
+
 async fn background_process(
-    ctx: &WatchContext,
     parts: Vec<String>,
+    dir: &str,
     logger: &Logger,
     env: Option<HashMap<String, String>>,
+    output_strategy: &OutpuStrategy,
 ) -> Result<(), anyhow::Error> {
-    match exec_background(parts.clone(), ctx, logger, env).await {
+    match exec_background(parts.clone(), dir, logger, env, output_strategy.log()).await {
         Ok(_) => {}
         Err(e) => {
             return Err(e);
@@ -115,23 +235,28 @@ async fn background_process(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn timeout_process(
     ctx: &WatchContext,
     parts: Vec<String>,
+    dir: &str,
     logger: &Logger,
     env: Option<HashMap<String, String>>,
     default_timeout: u64,
     output_strategy: &OutpuStrategy,
     pipe_registry: Arc<Mutex<PipeRegistry>>,
+    success_codes: &[i32],
 ) -> Result<CommandOutput, anyhow::Error> {
     match exec_timeout(
         parts.clone(),
         ctx,
+        dir,
         logger,
         default_timeout,
         env,
         output_strategy,
         pipe_registry,
+        success_codes,
     )
     .await
     {
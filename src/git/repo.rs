@@ -1,4 +1,8 @@
-use git2::{Cred, Error, FetchOptions, RemoteCallbacks, Repository};
+use std::fmt;
+
+use git2::{
+    Cred, Error, FetchOptions, Oid, RemoteCallbacks, Repository, ResetType, Status, StatusOptions,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{core::watcher::WatchContext, git::remote::find_ssh_key};
@@ -27,7 +31,36 @@ pub struct Branches {
     pub name: String, // the name of the last branch in branches: Vec<Branch> (used for ps command)
 }
 
+/// How the working tree was brought to the requested commit by [`Repo::sync_to_commit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    UpToDate,
+    FastForward,
+    Reset,
+}
+
+impl fmt::Display for SyncOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncOutcome::UpToDate => write!(f, "already up to date"),
+            SyncOutcome::FastForward => write!(f, "fast-forward"),
+            SyncOutcome::Reset => write!(f, "hard reset"),
+        }
+    }
+}
+
 impl Branches {
+    /// Branch of the current run, without the `origin/` prefix.
+    /// Falls back on the last watched branch when no commit triggered the run.
+    pub fn current(&self) -> &str {
+        let name = if self.last_name.is_empty() {
+            &self.name
+        } else {
+            &self.last_name
+        };
+        name.strip_prefix("origin/").unwrap_or(name)
+    }
+
     pub fn last_mut(&mut self) -> anyhow::Result<&mut Branch> {
         if let Some(last) = self.branches.last_mut() {
             Ok(last)
@@ -187,20 +220,141 @@ impl Repo {
     }
 
     pub fn pull(repo: &Repository, branch_name: &str) -> anyhow::Result<()> {
-        let ssh_key_path = find_ssh_key()?;
+        let mut fo = Repo::fetch_options();
+        let mut remote = repo.find_remote("origin")?;
+        remote.fetch(&[branch_name], Some(&mut fo), None)?;
+        Ok(())
+    }
 
+    /// Fetch options using the local ssh key, resolved only when the remote asks for it
+    /// (local and `file://` remotes never do).
+    fn fetch_options<'a>() -> FetchOptions<'a> {
         let mut cb = RemoteCallbacks::new();
 
         cb.credentials(|_, username_from_url, _| {
-            Cred::ssh_key(username_from_url.unwrap(), None, &ssh_key_path, None)
+            let ssh_key_path = find_ssh_key()?;
+            Cred::ssh_key(
+                username_from_url.unwrap_or("git"),
+                None,
+                &ssh_key_path,
+                None,
+            )
         });
 
         let mut fo = FetchOptions::new();
         fo.remote_callbacks(cb);
+        fo
+    }
 
-        let mut remote = repo.find_remote("origin")?;
-        remote.fetch(&[branch_name], Some(&mut fo), None)?;
-        Ok(())
+    /// Returns the hash of the commit currently checked out in `project_dir`.
+    pub fn head_commit(project_dir: &str) -> anyhow::Result<String> {
+        let repo = Repository::open(project_dir)?;
+        let commit = repo.head()?.peel_to_commit()?;
+        Ok(commit.id().to_string())
+    }
+
+    /// Lists the files changed between `since` and `commit`.
+    /// Without `since` the commit is compared to its first parent (to an empty tree for a root commit).
+    pub fn changed_paths(
+        project_dir: &str,
+        since: Option<&str>,
+        commit: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let repo = Repository::open(project_dir)?;
+        let new_tree = repo.find_commit(Oid::from_str(commit)?)?.tree()?;
+        let old_tree = match since {
+            Some(since) => Some(repo.find_commit(Oid::from_str(since)?)?.tree()?),
+            None => match repo.find_commit(Oid::from_str(commit)?)?.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            },
+        };
+
+        let diff = repo.diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), None)?;
+        let mut paths: Vec<String> = diff
+            .deltas()
+            .flat_map(|d| [d.old_file().path(), d.new_file().path()])
+            .flatten()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
+    }
+
+    /// Lists the tracked files with uncommitted changes (untracked and ignored files are not reported).
+    pub fn local_modifications(repo: &Repository) -> anyhow::Result<Vec<String>> {
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(false)
+            .include_ignored(false)
+            .exclude_submodules(true);
+
+        let statuses = repo.statuses(Some(&mut opts))?;
+        Ok(statuses
+            .iter()
+            .filter(|s| s.status() != Status::CURRENT)
+            .filter_map(|s| s.path().map(String::from))
+            .collect())
+    }
+
+    /// Brings the checkout in `project_dir` to exactly `commit` on `remote_branch`.
+    /// - Refuses to touch a working tree with local modifications.
+    /// - Fetches the branch from `origin` so the commit seen by `watch_once` is available locally.
+    /// - Moves the local branch to `commit` (fast-forward when possible, hard reset otherwise)
+    ///   and checks it out.
+    pub fn sync_to_commit(
+        project_dir: &str,
+        remote_branch: &str,
+        commit: &str,
+    ) -> anyhow::Result<SyncOutcome> {
+        let repo = Repository::open(project_dir)?;
+
+        let branch_name = remote_branch
+            .strip_prefix("origin/")
+            .unwrap_or(remote_branch);
+
+        let modified = Repo::local_modifications(&repo)?;
+        if !modified.is_empty() {
+            anyhow::bail!(
+                "Working tree of {} has local modifications ({}), refusing to sync",
+                project_dir,
+                modified.join(", ")
+            );
+        }
+
+        let refspec = format!("+refs/heads/{branch_name}:refs/remotes/origin/{branch_name}");
+        let mut fo = Repo::fetch_options();
+        repo.find_remote("origin")?
+            .fetch(&[refspec.as_str()], Some(&mut fo), None)?;
+
+        let oid = Oid::from_str(commit)?;
+        let target = repo.find_commit(oid).map_err(|_| {
+            anyhow::anyhow!("Commit {commit} not found after fetching branch `{branch_name}`")
+        })?;
+
+        let local_ref = format!("refs/heads/{branch_name}");
+        let previous = repo
+            .find_reference(&local_ref)
+            .ok()
+            .and_then(|r| r.target());
+        let head = repo.head().ok().and_then(|h| h.target());
+
+        let outcome = match previous {
+            Some(prev) if prev == oid && head == Some(oid) => SyncOutcome::UpToDate,
+            Some(prev) if prev == oid || repo.graph_descendant_of(oid, prev)? => {
+                SyncOutcome::FastForward
+            }
+            Some(_) => SyncOutcome::Reset,
+            None => SyncOutcome::FastForward,
+        };
+
+        if previous.is_none() {
+            repo.branch(branch_name, &target, false)?;
+        }
+        repo.set_head(&local_ref)?;
+        repo.reset(target.as_object(), ResetType::Hard, None)?;
+
+        Ok(outcome)
     }
 
     pub fn switch_branch(ctx: &WatchContext, remote_branch: &str) -> anyhow::Result<()> {
@@ -0,0 +1,140 @@
+use std::{path::PathBuf, sync::Arc};
+
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    sync::Mutex,
+    task::JoinHandle,
+};
+
+use crate::log::logger::{LogFormat, Logger};
+
+/// Which output of a command a line comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    fn tag(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "out",
+            OutputStream::Stderr => "err",
+        }
+    }
+
+    /// level of the structured records holding command output
+    fn level(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "STDOUT",
+            OutputStream::Stderr => "STDERR",
+        }
+    }
+}
+
+/// Log sink of a single job inside a run.
+///
+/// Every line is tagged with the job, the step and the stream it comes from,
+/// written to `~/.fleet/logs/<project_id>/<run_id>/<job>.log`
+/// and mirrored to the project log so `fleet logs` still shows the whole pipeline.
+#[derive(Debug, Clone)]
+pub struct JobLog {
+    pub job: String,
+    pub step: usize,
+    file: Arc<Mutex<File>>,
+    path: PathBuf,
+    logger: Logger,
+}
+
+impl JobLog {
+    pub fn open(
+        project_id: &str,
+        run_id: &str,
+        job: &str,
+        project: Logger,
+    ) -> anyhow::Result<Self> {
+        let path = Logger::job_path(project_id, run_id, job);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+
+        Ok(Self {
+            job: job.to_string(),
+            step: 0,
+            file: Arc::new(Mutex::new(File::from_std(file))),
+            path,
+            logger: project.for_run(run_id).for_job(job),
+        })
+    }
+
+    /// Same sink, tagged with another step number (1-based).
+    pub fn for_step(&self, step: usize) -> Self {
+        Self {
+            step,
+            logger: self.logger.for_step(step),
+            ..self.clone()
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Project logger tagged with the run, job and step of this sink.
+    pub fn logger(&self) -> &Logger {
+        &self.logger
+    }
+
+    /// Write a single line of command output.
+    pub async fn write(&self, stream: OutputStream, line: &str) -> anyhow::Result<()> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let tagged = match self.logger.format() {
+            LogFormat::Text => format!("[{}:{}][{}] {}\n", self.job, self.step, stream.tag(), line),
+            LogFormat::Json => self.logger.format_line(stream.level(), line),
+        };
+        {
+            let mut f = self.file.lock().await;
+            f.write_all(tagged.as_bytes()).await?;
+            f.flush().await?;
+        }
+        self.logger.write_raw(&tagged).await
+    }
+
+    /// Write a chunk of output that may hold several lines (e.g. docker logs).
+    pub async fn write_chunk(&self, stream: OutputStream, chunk: &[u8]) -> anyhow::Result<()> {
+        let text = String::from_utf8_lossy(chunk);
+        for line in text.lines() {
+            self.write(stream, line).await?;
+        }
+        Ok(())
+    }
+
+    /// Copy everything `reader` produces into the log, line by line, until EOF.
+    pub fn capture<R>(&self, reader: R, stream: OutputStream) -> JoinHandle<()>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let log = self.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(reader);
+            let mut buf = Vec::new();
+            loop {
+                buf.clear();
+                match reader.read_until(b'\n', &mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        let line = String::from_utf8_lossy(&buf);
+                        if log.write(stream, &line).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
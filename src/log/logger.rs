@@ -1,8 +1,9 @@
 use std::{io::SeekFrom, path::PathBuf, sync::Arc};
 
 use anyhow::Ok;
-use chrono::Local;
+use chrono::{DateTime, Local, Utc};
 use dirs::home_dir;
+use serde::{Deserialize, Serialize};
 use tokio::{
     fs::{File, remove_file},
     io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
@@ -14,6 +15,51 @@ pub struct Logger {
     pub file: Arc<Mutex<tokio::fs::File>>,
     path: String,
     color_enable: bool,
+    format: LogFormat,
+    fields: LogFields,
+}
+
+/// How lines are written to the log files.
+/// Selected for the whole daemon with `FLEET_LOG_FORMAT` (`text` by default, or `json`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `[date] LEVEL: message`, with ANSI colours unless `FLEET_NO_COLOR=1`
+    #[default]
+    Text,
+    /// one [`LogRecord`] per line (NDJSON)
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_env() -> Self {
+        match std::env::var("FLEET_LOG_FORMAT").ok().as_deref() {
+            Some("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+/// Where a log line comes from, attached to every structured record.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LogFields {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub project_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub run_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub job: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub step: Option<usize>,
+}
+
+/// A line of the structured (json) log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    pub timestamp: DateTime<Utc>,
+    pub level: String,
+    #[serde(flatten)]
+    pub fields: LogFields,
+    pub message: String,
 }
 
 const RESET: &str = "\x1b[0m";
@@ -42,9 +88,52 @@ impl Logger {
             file: Arc::new(Mutex::new(file)),
             path: String::from(path.to_str().unwrap_or("")),
             color_enable: !no_color,
+            format: LogFormat::from_env(),
+            fields: LogFields::default(),
         })
     }
 
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    pub fn fields(&self) -> &LogFields {
+        &self.fields
+    }
+
+    /// Same file, records tagged with the project id.
+    pub fn for_project(mut self, project_id: &str) -> Self {
+        self.fields.project_id = Some(project_id.to_string());
+        self
+    }
+
+    /// Same file, records tagged with a run.
+    pub fn for_run(&self, run_id: &str) -> Self {
+        let mut logger = self.clone();
+        logger.fields.run_id = Some(run_id.to_string());
+        logger
+    }
+
+    /// Same file, records tagged with a job of the run.
+    pub fn for_job(&self, job: &str) -> Self {
+        let mut logger = self.clone();
+        logger.fields.job = Some(job.to_string());
+        logger.fields.step = None;
+        logger
+    }
+
+    /// Same file, records tagged with a step of the job (1-based).
+    pub fn for_step(&self, step: usize) -> Self {
+        let mut logger = self.clone();
+        logger.fields.step = Some(step);
+        logger
+    }
+
     pub fn path_by_id(id: &str) -> PathBuf {
         let home = home_dir().unwrap();
 
@@ -52,12 +141,67 @@ impl Logger {
         log_dir.join(id.to_string() + ".log")
     }
 
+    /// directory holding the per-run logs of a project: `~/.fleet/logs/<id>/`
+    pub fn runs_dir_by_id(id: &str) -> PathBuf {
+        let home = home_dir().unwrap();
+
+        home.join(".fleet").join("logs").join(id)
+    }
+
+    /// `~/.fleet/logs/<id>/<run_id>/`
+    pub fn run_dir(id: &str, run_id: &str) -> PathBuf {
+        Logger::runs_dir_by_id(id).join(run_id)
+    }
+
+    /// `~/.fleet/logs/<id>/<run_id>/<job>.log`
+    pub fn job_path(id: &str, run_id: &str, job: &str) -> PathBuf {
+        let file_name: String = job
+            .chars()
+            .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+            .collect();
+        Logger::run_dir(id, run_id).join(file_name + ".log")
+    }
+
+    /// Run directory of a project that was written last, if any.
+    pub fn latest_run_dir(id: &str) -> anyhow::Result<Option<PathBuf>> {
+        let dir = Logger::runs_dir_by_id(id);
+        if !dir.exists() {
+            return Ok(None);
+        }
+
+        let mut latest: Option<(std::time::SystemTime, PathBuf)> = None;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let modified = entry.metadata()?.modified()?;
+            if latest.as_ref().is_none_or(|(t, _)| modified > *t) {
+                latest = Some((modified, entry.path()));
+            }
+        }
+        Ok(latest.map(|(_, p)| p))
+    }
+
     pub fn rm_logs_by_id(id: &str) -> anyhow::Result<()> {
         let path = Logger::path_by_id(id);
 
         if path.exists() {
             std::fs::remove_file(path)?;
         }
+
+        let runs = Logger::runs_dir_by_id(id);
+        if runs.exists() {
+            std::fs::remove_dir_all(runs)?;
+        }
+        Ok(())
+    }
+
+    pub fn rm_run_logs(id: &str, run_id: &str) -> anyhow::Result<()> {
+        let dir = Logger::run_dir(id, run_id);
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
         Ok(())
     }
 
@@ -120,6 +264,8 @@ impl Logger {
             ))),
             path: String::new(),
             color_enable: false,
+            format: LogFormat::Text,
+            fields: LogFields::default(),
         }
     }
 
@@ -134,15 +280,75 @@ impl Logger {
         }
     }
 
+    /// Format a line in the format of this logger (newline included).
+    pub fn format_line(&self, level: &str, msg: &str) -> String {
+        match self.format {
+            LogFormat::Text => {
+                let level = if self.color_enable {
+                    Logger::paint_level(level)
+                } else {
+                    level.to_string()
+                };
+                format!(
+                    "[{}] {}: {}\n",
+                    Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    level,
+                    msg
+                )
+            }
+            LogFormat::Json => {
+                let record = LogRecord {
+                    timestamp: Utc::now(),
+                    level: level.to_string(),
+                    fields: self.fields.clone(),
+                    message: msg.to_string(),
+                };
+                serde_json::to_string(&record).unwrap_or_default() + "\n"
+            }
+        }
+    }
+
     pub async fn log(&self, level: &str, msg: &str) -> anyhow::Result<()> {
+        let line = self.format_line(level, msg);
+        self.write_raw(&line).await
+    }
+
+    /// Render a line read from a log file for display.
+    /// Structured records are turned back into text, colours are only kept when `color` is set.
+    pub fn render_line(line: &str, color: bool) -> String {
+        if let std::result::Result::Ok(record) = serde_json::from_str::<LogRecord>(line) {
+            let level = if color {
+                Logger::paint_level(&record.level)
+            } else {
+                record.level.clone()
+            };
+            let origin = match (&record.fields.job, record.fields.step) {
+                (Some(job), Some(step)) => format!("[{job}:{step}] "),
+                (Some(job), None) => format!("[{job}] "),
+                _ => String::new(),
+            };
+            return format!(
+                "[{}] {}: {}{}",
+                record
+                    .timestamp
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S"),
+                level,
+                origin,
+                record.message
+            );
+        }
+
+        if color {
+            line.to_string()
+        } else {
+            strip_ansi(line)
+        }
+    }
+
+    /// Append an already formatted line (command output) to the log file.
+    pub async fn write_raw(&self, line: &str) -> anyhow::Result<()> {
         let mut f = self.file.lock().await;
-        let now = Local::now();
-        let line = format!(
-            "[{}] {}: {}\n",
-            now.format("%Y-%m-%d %H:%M:%S"),
-            Logger::paint_level(level),
-            msg
-        );
         f.write_all(line.as_bytes()).await?;
         f.flush().await?;
         Ok(())
@@ -209,3 +415,22 @@ impl Logger {
         }
     }
 }
+
+/// Remove the ANSI escape sequences (`ESC [ ... letter`) from a line.
+pub fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
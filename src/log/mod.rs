@@ -1,2 +1,3 @@
 #![allow(dead_code)]
+pub mod job_log;
 pub mod logger;
@@ -4,8 +4,9 @@ use reqwest::Client;
 use serde_json::json;
 
 use crate::{
-    core::watcher::WatchContext,
-    exec::metrics::ExecMetrics,
+    config::ChannelService,
+    core::{id::format_commit, watcher::WatchContext},
+    exec::metrics::{ExecMetrics, FailureReason, JobStatus},
     notifications::{DiscordEmbed, DiscordField, DiscordFooter, DiscordImage},
 };
 
@@ -40,33 +41,7 @@ pub async fn discord_send_succes(ctx: &WatchContext, m: &ExecMetrics) -> Result<
         description: format!("Pipeline **{}** executed successfully", ctx.repo.name),
         color: 0x2ECC71,
         thumbnail: DiscordImage::load(notification_config.thumbnail.clone()),
-        fields: vec![
-            DiscordField {
-                name: "Service name".into(),
-                value: format!("`{}`", ctx.repo.name.clone()),
-                inline: false,
-            },
-            DiscordField {
-                name: "Duration".into(),
-                value: format!("`{:.2}s`", (m.duration_ms.unwrap_or(1) as f64) / 1000.0),
-                inline: true,
-            },
-            DiscordField {
-                name: "CPU".into(),
-                value: format!("`{:.2}%`", m.cpu_usage),
-                inline: true,
-            },
-            DiscordField {
-                name: "Mem (%)".into(),
-                value: format!("`{:.2}%`", m.mem_usage),
-                inline: true,
-            },
-            DiscordField {
-                name: "Mem (Mb)".into(),
-                value: format!("`{}Mb`", m.mem_usage_kb / (1024 * 1024)),
-                inline: true,
-            },
-        ],
+        fields: run_fields(ctx, m),
         footer: Some(DiscordFooter {
             text: "Fleet CI/CD Pipeline".into(),
         }),
@@ -74,7 +49,7 @@ pub async fn discord_send_succes(ctx: &WatchContext, m: &ExecMetrics) -> Result<
     };
 
     for c in notification_config.channels.iter() {
-        if c.service == "discord" {
+        if c.service == ChannelService::Discord {
             discord_sender(&c.url, &embed).await?;
         }
     }
@@ -95,42 +70,114 @@ pub async fn discord_send_failure(ctx: &WatchContext, msg: &str, m: &ExecMetrics
         description: String::from(msg),
         color: 0xE74C3C,
         thumbnail: DiscordImage::load(notification_config.thumbnail.clone()),
-        fields: vec![
-            DiscordField {
-                name: "Service name".into(),
-                value: format!("`{}`", ctx.repo.name.clone()),
-                inline: false,
-            },
-            DiscordField {
-                name: "Duration".into(),
-                value: format!("`{:.2}s`", (m.duration_ms.unwrap_or(1) as f64) / 1000.0),
-                inline: true,
-            },
-            DiscordField {
-                name: "CPU".into(),
-                value: format!("`{:.2}%`", m.cpu_usage),
-                inline: true,
-            },
-            DiscordField {
-                name: "Mem (%)".into(),
-                value: format!("`{:.2}%`", m.mem_usage),
-                inline: true,
-            },
-            DiscordField {
-                name: "Mem (Mb)".into(),
-                value: format!("`{}Mb`", m.mem_usage_kb / (1024 * 1024)),
-                inline: true,
-            },
-        ],
+        fields: run_fields(ctx, m),
         footer: Some(DiscordFooter {
             text: "Fleet CI/CD Pipeline".into(),
         }),
         timestamp: Some(m.finished_at.unwrap_or(Utc::now())),
     };
     for c in notification_config.channels.iter() {
-        if c.service == "discord" {
+        if c.service == ChannelService::Discord {
             discord_sender(&c.url, &embed).await?;
         }
     }
     Ok(())
 }
+
+pub async fn discord_send_cancelled(ctx: &WatchContext, m: &ExecMetrics) -> Result<()> {
+    if ctx.config.pipeline.notifications.is_none() {
+        return Ok(());
+    }
+
+    let notification_config = ctx.config.pipeline.notifications.as_ref().unwrap();
+
+    let embed = DiscordEmbed {
+        title: "🛑 Pipeline cancelled".into(),
+        description: format!("Pipeline **{}** was cancelled", ctx.repo.name),
+        color: 0x95A5A6,
+        thumbnail: DiscordImage::load(notification_config.thumbnail.clone()),
+        fields: run_fields(ctx, m),
+        footer: Some(DiscordFooter {
+            text: "Fleet CI/CD Pipeline".into(),
+        }),
+        timestamp: Some(m.finished_at.unwrap_or(Utc::now())),
+    };
+    for c in notification_config.channels.iter() {
+        if c.service == ChannelService::Discord {
+            discord_sender(&c.url, &embed).await?;
+        }
+    }
+    Ok(())
+}
+
+/// fields shared by every run notification
+fn run_fields(ctx: &WatchContext, m: &ExecMetrics) -> Vec<DiscordField> {
+    vec![
+        DiscordField {
+            name: "Service name".into(),
+            value: format!("`{}`", ctx.repo.name.clone()),
+            inline: false,
+        },
+        DiscordField {
+            name: "Commit".into(),
+            value: format!("`{}` on `{}`", format_commit(&m.commit), m.branch),
+            inline: false,
+        },
+        DiscordField {
+            name: "Duration".into(),
+            value: format!("`{:.2}s`", (m.duration_ms.unwrap_or(1) as f64) / 1000.0),
+            inline: true,
+        },
+        DiscordField {
+            name: "CPU".into(),
+            value: format!("`{:.2}%`", m.cpu_usage),
+            inline: true,
+        },
+        DiscordField {
+            name: "Mem (%)".into(),
+            value: format!("`{:.2}%`", m.mem_usage),
+            inline: true,
+        },
+        DiscordField {
+            name: "Mem (Mb)".into(),
+            value: format!("`{}Mb`", m.mem_usage_kb / (1024 * 1024)),
+            inline: true,
+        },
+        DiscordField {
+            name: "Jobs".into(),
+            value: jobs_summary(m),
+            inline: false,
+        },
+    ]
+}
+
+/// one line per job of the run with its final status
+fn jobs_summary(m: &ExecMetrics) -> String {
+    let mut jobs: Vec<_> = m.jobs.values().collect();
+    jobs.sort_by(|a, b| a.name.cmp(&b.name));
+    if jobs.is_empty() {
+        return "-".into();
+    }
+    jobs.iter()
+        .map(|j| {
+            let icon = match j.status {
+                JobStatus::Succeeded => "✅",
+                JobStatus::SucceededWithWarnings => "⚠️",
+                JobStatus::Failed => "❌",
+                JobStatus::Skipped => "⏭",
+                JobStatus::Cancelled => "🛑",
+                JobStatus::Pending | JobStatus::Running => "⏳",
+            };
+            let status = match j.reason {
+                Some(FailureReason::Timeout) => format!("{} (timeout)", j.status),
+                _ => j.status.to_string(),
+            };
+            match j.retries() {
+                0 => format!("{icon} `{}` {status}", j.name),
+                1 => format!("{icon} `{}` {status} (1 retry)", j.name),
+                n => format!("{icon} `{}` {status} ({n} retries)", j.name),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,428 @@
+use std::fs;
+
+use core_lib::{
+    cli::graph::{GraphFormat, render},
+    config::{
+        parser::{load_config, read_config},
+        validate::validate,
+    },
+    exec::metrics::{ExecMetrics, RunTrigger},
+    log::logger::Logger,
+};
+
+#[tokio::test]
+async fn test_step_env_secrets_resolved() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(
+        &config_path,
+        "branches: [main]\npipeline:\n  jobs:\n    b:\n      steps:\n        - cmd: echo\n    a:\n      needs: [b]\n      env:\n        JOB_HOME: $HOME\n      steps:\n        - cmd: echo\n          env:\n            STEP_HOME: $HOME\n            OUT: ${{ jobs.b.outputs.x }}\n",
+    )?;
+    let config = load_config(&config_path)?;
+    let home = std::env::var("HOME")?;
+    let job = &config.pipeline.jobs["a"];
+    assert_eq!(job.env.as_ref().unwrap()["JOB_HOME"], home);
+    let step_env = job.steps[0].env.as_ref().unwrap();
+    assert_eq!(step_env["STEP_HOME"], home);
+    assert_eq!(step_env["OUT"], "${{ jobs.b.outputs.x }}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_include_and_extends() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let shared_name = format!("fleet-test-{}.yml", std::process::id());
+    let shared = core_lib::config::include::templates_dir().join(&shared_name);
+    fs::create_dir_all(shared.parent().unwrap())?;
+    fs::write(
+        &shared,
+        r#"
+timeout: 60
+pipeline:
+  jobs:
+    .base:
+      env: { STAGE: shared, SHARED: "yes" }
+      steps:
+        - run: echo "base $STAGE $SHARED $LOCAL"
+"#,
+    )?;
+    fs::create_dir_all(dir.path().join("ci"))?;
+    fs::write(
+        dir.path().join("ci/common.yml"),
+        format!(
+            r#"
+include: {shared_name}
+branches: [main]
+pipeline:
+  jobs:
+    .local:
+      extends: .base
+      env: {{ LOCAL: common }}
+    lint:
+      steps:
+        - run: echo lint
+"#
+        ),
+    )?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(
+        &config_path,
+        r#"
+include: [ci/common.yml]
+timeout: 120
+pipeline:
+  jobs:
+    build:
+      extends: [.local]
+      env: { STAGE: build }
+    deploy:
+      extends: build
+      needs: [build]
+      steps:
+        - run: echo "deploy $STAGE $SHARED $LOCAL"
+"#,
+    )?;
+
+    let config = load_config(&config_path)?;
+    fs::remove_file(&shared)?;
+    assert_eq!(config.timeout, Some(120));
+    assert_eq!(config.branches, vec!["main".to_string()]);
+    let mut names: Vec<&String> = config.pipeline.jobs.keys().collect();
+    names.sort();
+    assert_eq!(names, vec!["build", "deploy", "lint"]);
+
+    let build = &config.pipeline.jobs["build"];
+    let env = build.env.as_ref().unwrap();
+    assert_eq!(env["STAGE"], "build");
+    assert_eq!(env["SHARED"], "yes");
+    assert_eq!(env["LOCAL"], "common");
+    assert_eq!(
+        build.steps[0].run.as_deref(),
+        Some("echo \"base $STAGE $SHARED $LOCAL\"")
+    );
+    let deploy = &config.pipeline.jobs["deploy"];
+    assert_eq!(deploy.needs, vec!["build".to_string()]);
+    assert_eq!(deploy.steps.len(), 1);
+    assert_eq!(deploy.env, build.env);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_include_and_extends_rejected() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(dir.path().join("loop.yml"), "include: fleet.yml\n")?;
+    for (content, expected) in [
+        ("include: missing.yml\n", "Included file 'missing.yml'"),
+        ("include: loop.yml\n", "includes itself"),
+        (
+            "pipeline:\n  jobs:\n    a:\n      extends: b\n    b:\n      extends: a\n",
+            "extends itself",
+        ),
+        (
+            "pipeline:\n  jobs:\n    a:\n      extends: .nope\n      steps: []\n",
+            "Job 'a' extends unknown job '.nope'",
+        ),
+    ] {
+        fs::write(&config_path, content)?;
+        let err = format!("{:#}", load_config(&config_path).expect_err(expected));
+        assert!(err.contains(expected), "{err}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_validate_reports_every_problem_with_its_position() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(
+        &config_path,
+        "ENV: &env\n  A: b\nbranches: [main]\npipeline:\n  notifications:\n    on: [success, failed]\n    channels:\n      - service: slack\n        url: http://example.com\n  jobs:\n    build:\n      stpes:\n        - cmd: echo\n    test:\n      needs: [build, lint]\n      pipe: nowhere\n      env: *env\n      steps:\n        - cmd: |\n            echo a: b\n          blocking: maybe\n    empty:\n      steps: []\n",
+    )?;
+
+    let problems: Vec<(usize, usize, String)> = validate(&config_path)?
+        .into_iter()
+        .map(|p| {
+            assert_eq!(p.file, config_path);
+            (p.line, p.column, p.message)
+        })
+        .collect();
+    let expected = [
+        (6, 5, "Invalid notification event: unknown variant `failed`"),
+        (8, 9, "Invalid `service`: unknown variant `slack`"),
+        (11, 5, "Job 'build' has no steps"),
+        (
+            12,
+            7,
+            "Unknown key `stpes` in job 'build', did you mean `steps`?",
+        ),
+        (15, 7, "Job 'test' depends on unknown job 'lint'"),
+        (16, 7, "Job 'test' pipes into unknown job 'nowhere'"),
+        (21, 11, "Invalid `blocking`"),
+        (23, 7, "Job 'empty' has no steps"),
+    ];
+    assert_eq!(problems.len(), expected.len(), "{problems:?}");
+    for ((line, column, message), (e_line, e_column, e_message)) in problems.iter().zip(expected) {
+        assert_eq!((*line, *column), (e_line, e_column), "{message}");
+        assert!(message.starts_with(e_message), "{message}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_validate_checks_jobs_past_a_typo() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(
+        &config_path,
+        "branches: [main]\ntimout: 10\npipeline:\n  jobs:\n    a:\n      timout: 10\n      steps:\n        - cmd: echo\n    b:\n      needs: [a]\n      steps:\n        - cmd: echo\n    c:\n      env:\n        X: ${{ jobs.a.outputs.x }}\n      steps:\n        - cmd: echo\n",
+    )?;
+
+    let problems: Vec<(usize, String)> = validate(&config_path)?
+        .into_iter()
+        .map(|p| (p.line, p.message))
+        .collect();
+    let expected = [
+        (2, "Unknown key `timout` in the config"),
+        (6, "Unknown key `timout` in job 'a'"),
+        (13, "Job 'c' uses the outputs of 'a' which it does not need"),
+    ];
+    assert_eq!(problems.len(), expected.len(), "{problems:?}");
+    for ((line, message), (e_line, e_message)) in problems.iter().zip(expected) {
+        assert_eq!(*line, e_line, "{message}");
+        assert!(message.starts_with(e_message), "{message}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_validate_accepts_every_key() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(
+        &config_path,
+        r#"branches: [main]
+timeout: 60
+concurrency: cancel-in-progress
+history:
+  keep_runs: 10
+  keep_days: 7
+pipeline:
+  fail_fast: false
+  paths: ["src/**"]
+  paths_ignore: ["*.md"]
+  deadline: 600
+  shell: bash
+  notifications:
+    on: [success, failure, cancelled]
+    thumbnail: http://example.com/logo.png
+    channels:
+      - service: discord
+        url: http://example.com
+  jobs:
+    build:
+      env:
+        MODE: release
+      if: branch == 'main'
+      branches: [main]
+      ignore_branches: [wip]
+      paths: ["src/**"]
+      paths_ignore: ["*.md"]
+      retry:
+        attempts: 2
+        delay: 1
+        backoff: linear
+      timeout: 30
+      allow_failure: true
+      shell: sh
+      working_directory: .
+      artifacts:
+        paths: [target]
+      cache:
+        key: deps
+        paths: [target]
+        restore_keys: [dep]
+      steps:
+        - cmd: echo build
+          shell: sh
+          working_directory: .
+          env:
+            STEP: one
+          blocking: true
+          retry:
+            attempts: 2
+          timeout: 10
+          allow_failure: true
+          success_codes: [0, 1]
+        - run: echo run
+          continue_on_error: true
+        - cmd: echo container
+          container: alpine
+    test:
+      needs: [build]
+      pipe: build
+      download_artifacts: [build]
+      continue_on_error: true
+      steps:
+        - cmd: cat
+    lint:
+      matrix:
+        os: [linux]
+        include: []
+        exclude: []
+      steps:
+        - cmd: echo lint
+"#,
+    )?;
+    assert_eq!(validate(&config_path)?, vec![]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_validate_syntax_errors_and_valid_config() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+
+    fs::write(
+        &config_path,
+        "branches: [main]\npipeline:\n  jobs:\n    a:\n      steps:\n        - cmd: echo\n       bad: [\n",
+    )?;
+    let problems = validate(&config_path)?;
+    assert_eq!(problems.len(), 1, "{problems:?}");
+    assert_eq!((problems[0].line, problems[0].column), (7, 8));
+
+    fs::write(
+        dir.path().join("shared.yml"),
+        "pipeline:\n  jobs:\n    lint:\n      step:\n        - cmd: echo\n",
+    )?;
+    fs::write(
+        &config_path,
+        "include: shared.yml\nbranches: [main]\npipeline:\n  jobs:\n    a:\n      extends: .nope\n      steps:\n        - cmd: echo\n",
+    )?;
+    let problems = validate(&config_path)?;
+    assert_eq!(problems.len(), 1, "{problems:?}");
+    assert_eq!((problems[0].line, problems[0].column), (6, 7));
+    assert!(problems[0].message.contains("extends unknown job '.nope'"));
+
+    fs::write(
+        &config_path,
+        "include: shared.yml\nbranches: [main]\npipeline:\n  jobs:\n    a:\n      steps:\n        - cmd: echo\n",
+    )?;
+    let problems = validate(&config_path)?;
+    assert_eq!(problems.len(), 2, "{problems:?}");
+    assert_eq!(problems[0].file, dir.path().join("shared.yml"));
+    assert_eq!((problems[0].line, problems[0].column), (3, 5));
+    assert_eq!(problems[0].message, "Job 'lint' has no steps");
+    assert_eq!((problems[1].line, problems[1].column), (4, 7));
+
+    fs::write(
+        &config_path,
+        "branches: [main]\npipeline:\n  jobs:\n    a:\n      steps:\n        - cmd: echo\n    b:\n      needs: [a]\n      steps:\n        - cmd: echo\n",
+    )?;
+    assert!(validate(&config_path)?.is_empty());
+
+    assert!(validate(&dir.path().join("missing.yml")).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unknown_keys_and_references_rejected() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    let job = |extra: &str| {
+        format!(
+            "branches: [main]\npipeline:\n  jobs:\n    a:\n      steps:\n        - cmd: echo\n    b:\n{extra}      steps:\n        - cmd: cat\n"
+        )
+    };
+    for (content, expected) in [
+        (job("      need: [a]\n"), "unknown field `need`"),
+        (job("      pipe: nowhere\n"), "Job 'b' pipes into unknown job 'nowhere'"),
+        (job("      pipe: b\n"), "Job 'b' cannot pipe into itself"),
+        (job("      needs: [c]\n"), "Job 'b' depends on unknown job 'c'"),
+        (format!("timout: 10\n{}", job("")), "unknown field `timout`"),
+        (job("").replace("- cmd: cat", "- cmd: cat\n          shel: bash"), "unknown field `shel`"),
+        (
+            job("").replace("pipeline:\n", "pipeline:\n  notifications:\n    on: [failed]\n    channels: []\n"),
+            "unknown variant `failed`",
+        ),
+        (
+            job("").replace(
+                "pipeline:\n",
+                "pipeline:\n  notifications:\n    on: [failure]\n    channels:\n      - service: slack\n        url: http://example.com\n",
+            ),
+            "unknown variant `slack`",
+        ),
+    ] {
+        fs::write(&config_path, &content)?;
+        let err = format!("{:#}", load_config(&config_path).expect_err(expected));
+        assert!(err.contains(expected), "{err}");
+    }
+
+    // top-level anchor holders are left out
+    fs::write(
+        &config_path,
+        format!(
+            "ENV: &env\n  A: b\nx-steps: &steps\n  - cmd: echo\n{}",
+            job("      env: *env\n")
+        ),
+    )?;
+    let config = load_config(&config_path)?;
+    assert_eq!(config.pipeline.jobs["b"].env.as_ref().unwrap()["A"], "b");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_graph_renders_scheduler_edges() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(
+        &config_path,
+        "branches: [main]\npipeline:\n  jobs:\n    build:\n      env:\n        TOKEN: $FLEET_GRAPH_UNSET_SECRET\n      steps:\n        - cmd: echo\n    test:\n      needs: [build]\n      matrix:\n        os: [linux, mac]\n      steps:\n        - cmd: echo\n    filter:\n      pipe: build\n      steps:\n        - cmd: cat\n    deploy:\n      needs: [test, filter]\n      steps:\n        - cmd: echo\n",
+    )?;
+    // the secrets are left unresolved
+    let config = read_config(&config_path)?;
+
+    let ascii = render(&config, GraphFormat::Ascii, None)?;
+    assert_eq!(
+        ascii,
+        "stage 1\n  build\nstage 2\n  filter <- build (pipe)\n  test (linux) <- build\n  test (mac) <- build\nstage 3\n  deploy <- filter, test (linux), test (mac)\n"
+    );
+
+    let mut run = ExecMetrics::new(
+        "graph",
+        "name",
+        "abc",
+        "main",
+        RunTrigger::Manual,
+        Logger::placeholder(),
+    );
+    run.job_started("build");
+    run.job_finished("build", true);
+    run.job_started("filter");
+    run.job_finished("filter", false);
+
+    let dot = render(&config, GraphFormat::Dot, Some(&run))?;
+    assert!(dot.starts_with("digraph pipeline {"), "{dot}");
+    assert!(
+        dot.contains("\"build\" -> \"filter\" [style=dashed, label=\"pipe\"];"),
+        "{dot}"
+    );
+    assert!(dot.contains("\"build\" -> \"test (linux)\";"), "{dot}");
+    assert!(
+        dot.contains("\"filter\" [label=\"filter\\nfailed, "),
+        "{dot}"
+    );
+    assert!(dot.contains("color=red"), "{dot}");
+    assert!(dot.contains("  \"deploy\";"), "{dot}");
+
+    let mermaid = render(&config, GraphFormat::Mermaid, Some(&run))?;
+    assert!(
+        mermaid.starts_with("flowchart LR\n  job0[\"build<br/>succeeded, "),
+        "{mermaid}"
+    );
+    assert!(mermaid.contains("  job0 -. pipe .-> job1\n"), "{mermaid}");
+    assert!(mermaid.contains("  job4[\"deploy\"]\n"), "{mermaid}");
+    assert!(mermaid.contains("classDef failed"), "{mermaid}");
+    assert!(!mermaid.contains("classDef skipped"), "{mermaid}");
+    Ok(())
+}
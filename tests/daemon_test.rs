@@ -1,13 +1,18 @@
 use std::{collections::HashMap, env::temp_dir, sync::Arc};
 
 use core_lib::{
-    config::ProjectConfig,
-    core::{self, state::AppState, watcher::WatchContextBuilder},
+    config::{Cmd, ConcurrencyPolicy, Job, Pipeline, ProjectConfig},
+    core::{
+        self, manager::run_tracked, pool::WorkerPool, run_lock::RunDecision, state::AppState,
+        watcher::WatchContextBuilder,
+    },
     daemon::server::{
-        DaemonResponse, handle_list_watches, handle_rm_watch, handle_stop_watch, handle_up_watch,
+        DaemonResponse, handle_cancel_run, handle_get_run, handle_list_runs, handle_list_watches,
+        handle_rm_watch, handle_stop_watch, handle_up_watch,
     },
+    exec::metrics::{ExecMetrics, JobStatus, RunStatus, RunTrigger},
     git::repo::{Branch, Branches, Repo},
-    log::logger::Logger,
+    log::logger::{LogFormat, LogRecord, Logger},
 };
 use pretty_assertions::assert_eq;
 use tokio::{fs, sync::RwLock};
@@ -94,6 +99,48 @@ async fn test_concurent_log_writes() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_json_log_records() -> anyhow::Result<()> {
+    let dir = temp_dir();
+    let file_path = dir.join("structured.log");
+    let _ = fs::remove_file(&file_path).await;
+    let logger = Logger::new(&file_path)
+        .await?
+        .with_format(LogFormat::Json)
+        .for_project("proj");
+
+    logger
+        .for_run("run1")
+        .for_job("build")
+        .for_step(2)
+        .info("compiling")
+        .await?;
+
+    let contents = fs::read_to_string(&file_path).await?;
+    let record: LogRecord = serde_json::from_str(contents.lines().next().unwrap())?;
+    assert_eq!(record.level, "INFO");
+    assert_eq!(record.message, "compiling");
+    assert_eq!(record.fields.project_id.as_deref(), Some("proj"));
+    assert_eq!(record.fields.run_id.as_deref(), Some("run1"));
+    assert_eq!(record.fields.job.as_deref(), Some("build"));
+    assert_eq!(record.fields.step, Some(2));
+
+    let rendered = Logger::render_line(contents.lines().next().unwrap(), false);
+    assert!(rendered.ends_with("INFO: [build:2] compiling"));
+    assert!(!rendered.contains('\x1b'));
+    Ok(())
+}
+
+#[test]
+fn test_render_line_strips_colours() {
+    let line = "[2025-01-01 00:00:00] \x1b[44mINFO\x1b[0m: hello";
+    assert_eq!(
+        Logger::render_line(line, false),
+        "[2025-01-01 00:00:00] INFO: hello"
+    );
+    assert_eq!(Logger::render_line(line, true), line);
+}
+
 #[tokio::test]
 async fn test_handle_stop_watch_existing() -> anyhow::Result<()> {
     AppState::init_watch_file().await?;
@@ -112,6 +159,7 @@ async fn test_handle_stop_watch_existing() -> anyhow::Result<()> {
     map.insert(id.clone(), ctx);
     let state = Arc::new(AppState {
         watches: RwLock::new(map),
+        ..Default::default()
     });
 
     let response = handle_stop_watch(state.clone(), id.clone()).await;
@@ -147,6 +195,7 @@ async fn test_handle_up_watch_existing() -> anyhow::Result<()> {
     map.insert(id.clone(), ctx);
     let state = Arc::new(AppState {
         watches: RwLock::new(map),
+        ..Default::default()
     });
 
     let response = handle_up_watch(state.clone(), id.clone()).await;
@@ -180,6 +229,7 @@ async fn test_handle_rm_watch_existing() -> anyhow::Result<()> {
 
     let state = Arc::new(AppState {
         watches: RwLock::new(map),
+        ..Default::default()
     });
 
     let response = handle_rm_watch(state.clone(), id.clone()).await;
@@ -196,6 +246,7 @@ async fn test_handle_rm_watch_existing() -> anyhow::Result<()> {
 async fn test_handle_rm_non_existing() -> anyhow::Result<()> {
     let state = Arc::new(AppState {
         watches: RwLock::new(HashMap::new()),
+        ..Default::default()
     });
 
     let response = handle_rm_watch(state.clone(), "unknown".to_string()).await;
@@ -225,6 +276,7 @@ async fn test_handle_list_watches_existing() -> anyhow::Result<()> {
 
     let state = Arc::new(AppState {
         watches: RwLock::new(map),
+        ..Default::default()
     });
 
     let response = handle_list_watches(state.clone(), false).await;
@@ -243,6 +295,7 @@ async fn test_handle_list_watches_existing() -> anyhow::Result<()> {
 async fn test_handle_list_watches_empty() -> anyhow::Result<()> {
     let state = Arc::new(AppState {
         watches: RwLock::new(HashMap::new()),
+        ..Default::default()
     });
 
     let response = handle_list_watches(state.clone(), false).await;
@@ -253,3 +306,269 @@ async fn test_handle_list_watches_empty() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+#[tokio::test]
+async fn test_handle_list_and_get_runs() -> anyhow::Result<()> {
+    AppState::init_watch_file().await?;
+    let id = "runs_history".to_string();
+    let ctx = WatchContextBuilder::new(
+        build_repo(),
+        ProjectConfig::default(),
+        "dir".to_string(),
+        id.clone(),
+    )
+    .build()
+    .await?;
+    AppState::add_watch(&ctx).await?;
+    ExecMetrics::rm_metrics_by_id(&id)?;
+
+    let mut first = ExecMetrics::new(
+        &id,
+        "name",
+        "0123456789abcdef",
+        "main",
+        RunTrigger::Poll,
+        Logger::placeholder(),
+    );
+    first.job_started("build");
+    first.job_finished("build", false);
+    first.finalize(RunStatus::Failed);
+    first.save().await?;
+
+    let mut second = ExecMetrics::new(
+        &id,
+        "name",
+        "fedcba9876543210",
+        "main",
+        RunTrigger::Manual,
+        Logger::placeholder(),
+    );
+    second.finalize(RunStatus::Succeeded);
+    second.save().await?;
+
+    let list = handle_list_runs(id.clone()).await;
+    let run = handle_get_run(first.run_id.clone()).await;
+    let missing = handle_get_run("unknown".to_string()).await;
+
+    ExecMetrics::rm_metrics_by_id(&id)?;
+    AppState::remove_watch_by_id(&id).await?;
+    ctx.logger.clean().await?;
+
+    match list {
+        DaemonResponse::ListRuns(runs) => {
+            assert_eq!(runs.len(), 2);
+            assert_eq!(runs[0].run_id, second.run_id); // most recent first
+            assert_eq!(runs[1].status, RunStatus::Failed);
+        }
+        _ => panic!("Expected list runs"),
+    }
+    match run {
+        DaemonResponse::ShowRun(info, jobs) => {
+            assert_eq!(info.commit, "0123456789abcdef");
+            assert_eq!(jobs.len(), 1);
+            assert_eq!(jobs[0].name, "build");
+            assert_eq!(jobs[0].status, JobStatus::Failed);
+        }
+        _ => panic!("Expected run details"),
+    }
+    match missing {
+        DaemonResponse::Error(msg) => assert!(msg.contains("Run not found")),
+        _ => panic!("Expected error response"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_handle_cancel_run() -> anyhow::Result<()> {
+    AppState::init_watch_file().await?;
+    let id = "cancel_run".to_string();
+    let ctx = WatchContextBuilder::new(
+        build_repo(),
+        ProjectConfig::default(),
+        "dir".to_string(),
+        id.clone(),
+    )
+    .build()
+    .await?;
+    AppState::add_watch(&ctx).await?;
+    let state = Arc::new(AppState::default());
+
+    let idle = handle_cancel_run(state.clone(), id.clone()).await;
+    let slot = state.run_slot(&id).await;
+    let (_, permit) = slot
+        .acquire(ConcurrencyPolicy::Queue, &Logger::placeholder())
+        .await;
+    let permit = permit.unwrap();
+    let running = handle_cancel_run(state.clone(), id.clone()).await;
+    let token = permit.cancel.clone();
+    drop(permit);
+
+    AppState::remove_watch_by_id(&id).await?;
+    ctx.logger.clean().await?;
+
+    match idle {
+        DaemonResponse::Error(msg) => assert!(msg.contains("No pipeline running")),
+        _ => panic!("Expected error response"),
+    }
+    assert!(matches!(running, DaemonResponse::Success(_)));
+    assert!(token.is_cancelled());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_lock_policies() -> anyhow::Result<()> {
+    let logger = Logger::placeholder();
+    let state = Arc::new(AppState::default());
+    let slot = state.run_slot("run_lock").await;
+
+    let (decision, first) = slot.acquire(ConcurrencyPolicy::Skip, &logger).await;
+    assert_eq!(decision, RunDecision::Started);
+    let first = first.unwrap();
+    assert!(slot.is_running());
+
+    // skip: the new run is dropped
+    let (decision, skipped) = slot.acquire(ConcurrencyPolicy::Skip, &logger).await;
+    assert_eq!(decision, RunDecision::Skipped);
+    assert!(skipped.is_none());
+
+    // queue: the new run waits for the current one
+    let queued = {
+        let slot = slot.clone();
+        tokio::spawn(async move {
+            let logger = Logger::placeholder();
+            slot.acquire(ConcurrencyPolicy::Queue, &logger).await.0
+        })
+    };
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(slot.waiting(), 1);
+    assert!(!first.cancel.is_cancelled());
+    drop(first);
+    assert_eq!(queued.await?, RunDecision::Queued);
+
+    // cancel-in-progress: the current run is cancelled for the new one
+    let (_, current) = slot.acquire(ConcurrencyPolicy::Queue, &logger).await;
+    let current = current.unwrap();
+    let newest = {
+        let slot = slot.clone();
+        tokio::spawn(async move {
+            let logger = Logger::placeholder();
+            let (decision, permit) = slot
+                .acquire(ConcurrencyPolicy::CancelInProgress, &logger)
+                .await;
+            (decision, permit.is_some())
+        })
+    };
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(current.cancel.is_cancelled());
+    drop(current);
+    assert_eq!(newest.await?, (RunDecision::Superseded, true));
+    assert_eq!(slot.last_decision(), Some(RunDecision::Superseded));
+    assert!(!slot.is_running());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_worker_pool_limits_parallel_runs() -> anyhow::Result<()> {
+    let state = Arc::new(AppState {
+        pool: WorkerPool::new(1),
+        ..Default::default()
+    });
+    let mut ctxs = Vec::new();
+    for id in ["pool_a", "pool_b"] {
+        let job = Job {
+            steps: vec![Cmd {
+                cmd: "sleep 0.5".into(),
+                blocking: false,
+                container: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let config = ProjectConfig {
+            pipeline: Pipeline {
+                jobs: HashMap::from([("wait".to_string(), job)]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let ctx = WatchContextBuilder::new(build_repo(), config, ".".to_string(), id.to_string())
+            .build()
+            .await?;
+        ctxs.push(ctx);
+    }
+
+    let handles: Vec<_> = ctxs
+        .iter()
+        .cloned()
+        .map(|ctx| {
+            let state = state.clone();
+            tokio::spawn(async move { run_tracked(&state, ctx, RunTrigger::Manual).await })
+        })
+        .collect();
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let active = state.active_runs().await;
+    assert_eq!(active.len(), 1, "only one worker is available");
+    assert!(state.pool.try_acquire().is_none(), "the worker is taken");
+
+    for h in handles {
+        h.await??;
+    }
+    assert!(state.active_runs().await.is_empty());
+    assert!(
+        state.pool.try_acquire().is_some(),
+        "the worker is given back"
+    );
+
+    for ctx in ctxs {
+        ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+        Logger::rm_logs_by_id(&ctx.id)?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_successful_run_records_last_build() -> anyhow::Result<()> {
+    let job = Job {
+        steps: vec![Cmd {
+            cmd: "echo built".into(),
+            blocking: false,
+            container: None,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let config = ProjectConfig {
+        pipeline: Pipeline {
+            jobs: HashMap::from([("build".to_string(), job)]),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx = WatchContextBuilder::new(
+        build_repo(),
+        config,
+        ".".to_string(),
+        "last_build".to_string(),
+    )
+    .build()
+    .await?;
+    let state = Arc::new(AppState::default());
+    state
+        .watches
+        .write()
+        .await
+        .insert(ctx.id.clone(), ctx.clone());
+
+    run_tracked(&state, ctx.clone(), RunTrigger::Manual).await?;
+
+    let watches = state.watches.read().await;
+    assert_eq!(
+        watches[&ctx.id].last_built.get("main"),
+        Some(&Repo::head_commit(".")?)
+    );
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
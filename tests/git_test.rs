@@ -1,7 +1,10 @@
 use std::fs::{self};
 
 use anyhow::Result;
-use core_lib::git::remote::branch_wildcard_from_repo;
+use core_lib::git::{
+    remote::branch_wildcard_from_repo,
+    repo::{Repo, SyncOutcome},
+};
 use git2::Repository;
 use tempfile::tempdir;
 
@@ -71,3 +74,126 @@ fn test_multiple_remotes() -> Result<()> {
 
     Ok(())
 }
+
+fn commit_file(repo: &Repository, name: &str, content: &str) -> Result<String> {
+    let workdir = repo.workdir().unwrap().to_path_buf();
+    fs::write(workdir.join(name), content)?;
+
+    let mut index = repo.index()?;
+    index.add_path(std::path::Path::new(name))?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let sig = git2::Signature::now("fleet", "fleet@test")?;
+
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    let oid = repo.commit(Some("HEAD"), &sig, &sig, name, &tree, &parents)?;
+    Ok(oid.to_string())
+}
+
+/// origin with one commit on `main`, cloned into a second directory
+fn origin_and_clone() -> Result<(tempfile::TempDir, Repository, tempfile::TempDir)> {
+    let origin_dir = tempdir()?;
+    let origin = Repository::init(origin_dir.path())?;
+    origin.set_head("refs/heads/main")?;
+    commit_file(&origin, "README.md", "v1")?;
+
+    let clone_dir = tempdir()?;
+    Repository::clone(origin_dir.path().to_str().unwrap(), clone_dir.path())?;
+    Ok((origin_dir, origin, clone_dir))
+}
+
+#[test]
+fn test_sync_to_commit_fast_forward() -> Result<()> {
+    let (_origin_dir, origin, clone_dir) = origin_and_clone()?;
+    let new_commit = commit_file(&origin, "README.md", "v2")?;
+    let project_dir = clone_dir.path().to_str().unwrap();
+
+    let outcome = Repo::sync_to_commit(project_dir, "origin/main", &new_commit)?;
+
+    assert_eq!(outcome, SyncOutcome::FastForward);
+    assert_eq!(Repo::head_commit(project_dir)?, new_commit);
+    assert_eq!(
+        fs::read_to_string(clone_dir.path().join("README.md"))?,
+        "v2"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_sync_to_commit_pins_detected_hash() -> Result<()> {
+    let (_origin_dir, origin, clone_dir) = origin_and_clone()?;
+    let detected = commit_file(&origin, "README.md", "v2")?;
+    commit_file(&origin, "README.md", "v3")?; // pushed after detection
+    let project_dir = clone_dir.path().to_str().unwrap();
+
+    Repo::sync_to_commit(project_dir, "main", &detected)?;
+
+    assert_eq!(Repo::head_commit(project_dir)?, detected);
+    assert_eq!(
+        fs::read_to_string(clone_dir.path().join("README.md"))?,
+        "v2"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_sync_to_commit_resets_diverged_branch() -> Result<()> {
+    let (_origin_dir, origin, clone_dir) = origin_and_clone()?;
+    let local = Repository::open(clone_dir.path())?;
+    commit_file(&local, "local.txt", "local only")?;
+    let new_commit = commit_file(&origin, "README.md", "v2")?;
+    let project_dir = clone_dir.path().to_str().unwrap();
+
+    let outcome = Repo::sync_to_commit(project_dir, "origin/main", &new_commit)?;
+
+    assert_eq!(outcome, SyncOutcome::Reset);
+    assert_eq!(Repo::head_commit(project_dir)?, new_commit);
+    assert!(!clone_dir.path().join("local.txt").exists());
+    Ok(())
+}
+
+#[test]
+fn test_sync_to_commit_refuses_local_modifications() -> Result<()> {
+    let (_origin_dir, origin, clone_dir) = origin_and_clone()?;
+    let before = Repo::head_commit(clone_dir.path().to_str().unwrap())?;
+    let new_commit = commit_file(&origin, "README.md", "v2")?;
+    fs::write(clone_dir.path().join("README.md"), "edited on the host")?;
+    let project_dir = clone_dir.path().to_str().unwrap();
+
+    let result = Repo::sync_to_commit(project_dir, "origin/main", &new_commit);
+
+    assert!(result.is_err());
+    assert!(result.unwrap_err().to_string().contains("README.md"));
+    assert_eq!(Repo::head_commit(project_dir)?, before);
+    assert_eq!(
+        fs::read_to_string(clone_dir.path().join("README.md"))?,
+        "edited on the host"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_changed_paths_since_commit() -> Result<()> {
+    let dir = tempdir()?;
+    let repo = Repository::init(dir.path())?;
+    let first = commit_file(&repo, "README.md", "v1")?;
+    commit_file(&repo, "main.rs", "fn main() {}")?;
+    let last = commit_file(&repo, "Cargo.toml", "[package]")?;
+    let project_dir = dir.path().to_str().unwrap();
+
+    assert_eq!(
+        Repo::changed_paths(project_dir, Some(&first), &last)?,
+        vec!["Cargo.toml".to_string(), "main.rs".to_string()]
+    );
+    // without a base, the commit is compared to its parent
+    assert_eq!(
+        Repo::changed_paths(project_dir, None, &last)?,
+        vec!["Cargo.toml".to_string()]
+    );
+    assert_eq!(
+        Repo::changed_paths(project_dir, None, &first)?,
+        vec!["README.md".to_string()]
+    );
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use core_lib::{
+    config::HistoryRetention,
+    exec::metrics::{ExecMetrics, RunStatus, RunTrigger},
+    log::logger::Logger,
+};
+use pretty_assertions::assert_eq;
+
+fn build_run(project_id: &str, commit: &str) -> ExecMetrics {
+    let mut m = ExecMetrics::new(
+        project_id,
+        "name",
+        commit,
+        "main",
+        RunTrigger::Poll,
+        Logger::placeholder(),
+    );
+    m.finalize(RunStatus::Succeeded);
+    m
+}
+
+#[tokio::test]
+async fn test_runs_are_appended_to_history() -> anyhow::Result<()> {
+    let id = "metrics_append";
+    ExecMetrics::rm_metrics_by_id(id)?;
+
+    let first = build_run(id, "aaa");
+    let second = build_run(id, "bbb");
+    first.save().await?;
+    second.save().await?;
+
+    let history = ExecMetrics::load_history(id).await?;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].commit, "aaa");
+    assert_eq!(history[1].commit, "bbb");
+    assert_eq!(history[1].status, RunStatus::Succeeded);
+    assert_eq!(history[1].trigger, RunTrigger::Poll);
+    assert_ne!(history[0].run_id, history[1].run_id);
+
+    ExecMetrics::rm_metrics_by_id(id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retention_keeps_last_runs() -> anyhow::Result<()> {
+    let id = "metrics_keep_runs";
+    ExecMetrics::rm_metrics_by_id(id)?;
+
+    for commit in ["c1", "c2", "c3", "c4"] {
+        build_run(id, commit).save().await?;
+    }
+
+    let retention = HistoryRetention {
+        keep_runs: 2,
+        keep_days: None,
+    };
+    let dropped = ExecMetrics::apply_retention(id, &retention).await?;
+    let history = ExecMetrics::load_history(id).await?;
+
+    assert_eq!(dropped, 2);
+    assert_eq!(
+        history
+            .iter()
+            .map(|m| m.commit.as_str())
+            .collect::<Vec<_>>(),
+        vec!["c3", "c4"]
+    );
+
+    ExecMetrics::rm_metrics_by_id(id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retention_drops_old_runs() -> anyhow::Result<()> {
+    let id = "metrics_keep_days";
+    ExecMetrics::rm_metrics_by_id(id)?;
+
+    let mut old = build_run(id, "old");
+    old.started_at = chrono::Utc::now() - chrono::Duration::days(10);
+    old.save().await?;
+    build_run(id, "recent").save().await?;
+
+    let retention = HistoryRetention {
+        keep_days: Some(7),
+        ..Default::default()
+    };
+    ExecMetrics::apply_retention(id, &retention).await?;
+    let history = ExecMetrics::load_history(id).await?;
+
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].commit, "recent");
+
+    ExecMetrics::rm_metrics_by_id(id)?;
+    Ok(())
+}
@@ -1,10 +1,19 @@
-use std::{collections::HashMap, fs, sync::Arc};
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
 
 use core_lib::{
-    config::{Cmd, Job, Pipeline, ProjectConfig},
+    config::{
+        Artifacts, Backoff, Cache, Cmd, HistoryRetention, Job, Pipeline, ProjectConfig, Retry,
+        parser::load_config,
+    },
     core::watcher::{WatchContext, WatchContextBuilder},
-    exec::pipeline::run_pipeline,
+    exec::{
+        artifacts,
+        cache::{self, CacheEntry},
+        metrics::{ExecMetrics, FailureReason, JobStatus, RunStatus, RunTrigger, StepAttempt},
+        pipeline::run_pipeline,
+    },
     git::repo::{Branch, Branches, Repo},
+    log::logger::Logger,
 };
 
 fn build_repo() -> Repo {
@@ -25,19 +34,53 @@ fn build_repo() -> Repo {
 }
 
 async fn build_test_ctx(id: &str, jobs: HashMap<String, Job>) -> anyhow::Result<Arc<WatchContext>> {
-    let config = ProjectConfig {
+    Ok(Arc::new(build_ctx(id, Path::new("."), config(jobs)).await?))
+}
+
+fn config(jobs: HashMap<String, Job>) -> ProjectConfig {
+    ProjectConfig {
         pipeline: Pipeline {
             jobs,
             ..Default::default()
         },
-        branches: vec![],
-        timeout: None,
-    };
-    Ok(Arc::new(
-        WatchContextBuilder::new(build_repo(), config, ".".to_string(), id.to_string())
-            .build()
-            .await?,
-    ))
+        ..Default::default()
+    }
+}
+
+/// Context of the project `id` checked out in `dir`
+async fn build_ctx(id: &str, dir: &Path, config: ProjectConfig) -> anyhow::Result<WatchContext> {
+    WatchContextBuilder::new(
+        build_repo(),
+        config,
+        dir.to_string_lossy().into_owned(),
+        id.to_string(),
+    )
+    .build()
+    .await
+}
+
+/// A job of a single `cmd` step, run after the jobs of `needs`
+fn job(cmd: &str, needs: &[&str]) -> Job {
+    Job {
+        steps: vec![step(cmd)],
+        needs: needs.iter().map(|n| n.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn step(cmd: &str) -> Cmd {
+    Cmd {
+        cmd: cmd.into(),
+        ..Default::default()
+    }
+}
+
+/// A step running `run` with the shell of its job
+fn script(run: &str) -> Cmd {
+    Cmd {
+        run: Some(run.into()),
+        ..Default::default()
+    }
 }
 
 fn assert_in_log_order(log: &str, a: &str, b: &str) {
@@ -67,10 +110,12 @@ async fn test_simple_dependency_order() -> anyhow::Result<()> {
                     cmd: "echo job1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 needs: vec![],
                 pipe: String::new(),
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -80,10 +125,12 @@ async fn test_simple_dependency_order() -> anyhow::Result<()> {
                     cmd: "echo job2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -92,7 +139,7 @@ async fn test_simple_dependency_order() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let content = fs::read_to_string(ctx.log_path())?;
     let idx1 = content.find("job1").unwrap();
     let idx2 = content.find("job2").unwrap();
@@ -111,10 +158,12 @@ async fn test_independent_jobs_run_in_parallel() -> anyhow::Result<()> {
                     cmd: "echo job1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -124,10 +173,12 @@ async fn test_independent_jobs_run_in_parallel() -> anyhow::Result<()> {
                     cmd: "echo job2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -136,7 +187,7 @@ async fn test_independent_jobs_run_in_parallel() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
 
     dbg!(&ctx);
     eprintln!("debug: log_path: {}", ctx.log_path().display());
@@ -160,10 +211,12 @@ async fn test_long_chain_dependency_order() -> anyhow::Result<()> {
                     cmd: "echo job1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -173,10 +226,12 @@ async fn test_long_chain_dependency_order() -> anyhow::Result<()> {
                     cmd: "echo job2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -186,10 +241,12 @@ async fn test_long_chain_dependency_order() -> anyhow::Result<()> {
                     cmd: "echo job3".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job2".into()],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -199,10 +256,12 @@ async fn test_long_chain_dependency_order() -> anyhow::Result<()> {
                     cmd: "echo job4".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job3".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -211,7 +270,7 @@ async fn test_long_chain_dependency_order() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let content = fs::read_to_string(ctx.log_path())?;
 
     let idx1 = content.find("job1").unwrap();
@@ -233,10 +292,12 @@ async fn test_single_job_pipeline() -> anyhow::Result<()> {
                 cmd: "echo single".into(),
                 blocking: false,
                 container: None,
+                ..Default::default()
             }],
             pipe: String::new(),
             needs: vec![],
             env: None,
+            ..Default::default()
         },
     )]
     .into_iter()
@@ -244,7 +305,7 @@ async fn test_single_job_pipeline() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let log = fs::read_to_string(ctx.log_path())?;
     assert_in_log(&log, "single");
     ctx.logger.clean().await?;
@@ -261,10 +322,12 @@ async fn test_jobs_converging() -> anyhow::Result<()> {
                     cmd: "echo job1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -274,10 +337,12 @@ async fn test_jobs_converging() -> anyhow::Result<()> {
                     cmd: "echo job2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -287,10 +352,12 @@ async fn test_jobs_converging() -> anyhow::Result<()> {
                     cmd: "echo job3".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -300,10 +367,12 @@ async fn test_jobs_converging() -> anyhow::Result<()> {
                     cmd: "echo job4".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job2".into(), "job3".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -312,7 +381,7 @@ async fn test_jobs_converging() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let log = fs::read_to_string(ctx.log_path())?;
     assert_in_log_order(&log, "job1", "job2");
     assert_in_log_order(&log, "job1", "job3");
@@ -326,8 +395,10 @@ async fn test_jobs_converging() -> anyhow::Result<()> {
 async fn test_pipeline_empty() -> anyhow::Result<()> {
     let jobs: HashMap<String, Job> = HashMap::new();
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
+    // a full history would log the retention cleanup
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
 
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let log = fs::read_to_string(ctx.log_path())?;
     assert!(log.is_empty(), "Expected empty log for empty pipeline");
     ctx.logger.clean().await?;
@@ -343,10 +414,12 @@ async fn test_missing_dependency() -> anyhow::Result<()> {
                 cmd: "echo job2".into(),
                 blocking: false,
                 container: None,
+                ..Default::default()
             }],
             pipe: String::new(),
             needs: vec!["ghost".into()],
             env: None,
+            ..Default::default()
         },
     )]
     .into_iter()
@@ -354,7 +427,7 @@ async fn test_missing_dependency() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    let result = run_pipeline(ctx.clone()).await;
+    let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
     assert!(
         result.is_err(),
         "Pipeline should fail on missing dependency"
@@ -373,10 +446,12 @@ async fn test_job_failure_blocks_dependents() -> anyhow::Result<()> {
                     cmd: "exit 1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -386,10 +461,12 @@ async fn test_job_failure_blocks_dependents() -> anyhow::Result<()> {
                     cmd: "echo job2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -398,7 +475,7 @@ async fn test_job_failure_blocks_dependents() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    let result = run_pipeline(ctx.clone()).await;
+    let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
     assert!(result.is_err(), "Pipeline should fail because job1 failed");
     let log = fs::read_to_string(ctx.log_path())?;
     assert_in_log(&log, "job1");
@@ -420,10 +497,12 @@ async fn test_cyclic_dependency_detected() -> anyhow::Result<()> {
                     cmd: "echo job1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job2".into()],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -433,10 +512,12 @@ async fn test_cyclic_dependency_detected() -> anyhow::Result<()> {
                     cmd: "echo job2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -445,7 +526,7 @@ async fn test_cyclic_dependency_detected() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    let result = run_pipeline(ctx.clone()).await;
+    let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
     assert!(
         result.is_err(),
         "Scheduler should reject cyclic dependencies"
@@ -464,16 +545,19 @@ async fn test_multiple_steps_in_one_job() -> anyhow::Result<()> {
                     cmd: "echo step1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 },
                 Cmd {
                     cmd: "echo step2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 },
             ],
             pipe: String::new(),
             needs: vec![],
             env: None,
+            ..Default::default()
         },
     )]
     .into_iter()
@@ -481,7 +565,7 @@ async fn test_multiple_steps_in_one_job() -> anyhow::Result<()> {
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
 
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let log = fs::read_to_string(ctx.log_path())?;
     assert_in_log(&log, "step1");
     assert_in_log(&log, "step2");
@@ -499,10 +583,12 @@ async fn test_timeout_job() -> anyhow::Result<()> {
                 cmd: "sleep 5".into(),
                 blocking: false,
                 container: None,
+                ..Default::default()
             }],
             pipe: String::new(),
             needs: vec![],
             env: None,
+            ..Default::default()
         },
     )]
     .into_iter()
@@ -515,6 +601,7 @@ async fn test_timeout_job() -> anyhow::Result<()> {
         },
         branches: vec![],
         timeout: Some(2),
+        ..Default::default()
     };
     let ctx = Arc::new(
         WatchContextBuilder::new(
@@ -527,7 +614,7 @@ async fn test_timeout_job() -> anyhow::Result<()> {
         .await?,
     );
 
-    let result = run_pipeline(ctx.clone()).await;
+    let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
     assert!(
         result.is_err(),
         "Pipeline should fail because job exceeded timeout"
@@ -546,10 +633,12 @@ async fn test_multiple_dependencies() -> anyhow::Result<()> {
                     cmd: "echo job1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -559,10 +648,12 @@ async fn test_multiple_dependencies() -> anyhow::Result<()> {
                     cmd: "echo job2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -572,10 +663,12 @@ async fn test_multiple_dependencies() -> anyhow::Result<()> {
                     cmd: "echo job3".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into(), "job2".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -583,7 +676,7 @@ async fn test_multiple_dependencies() -> anyhow::Result<()> {
     .collect();
 
     let ctx = build_test_ctx("test_multiple_dependencies", jobs).await?;
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let log = fs::read_to_string(ctx.log_path())?;
     assert_in_log_order(&log, "job1", "job3");
     assert_in_log_order(&log, "job2", "job3");
@@ -601,10 +694,12 @@ async fn test_parallel_then_dependent_job() -> anyhow::Result<()> {
                     cmd: "echo A".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -614,10 +709,12 @@ async fn test_parallel_then_dependent_job() -> anyhow::Result<()> {
                     cmd: "echo B".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -627,10 +724,12 @@ async fn test_parallel_then_dependent_job() -> anyhow::Result<()> {
                     cmd: "echo C".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into(), "job2".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -638,7 +737,7 @@ async fn test_parallel_then_dependent_job() -> anyhow::Result<()> {
     .collect();
 
     let ctx = build_test_ctx("test_parallel_then_dependent_job", jobs).await?;
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let log = fs::read_to_string(ctx.log_path())?;
     assert_in_log_order(&log, "A", "C");
     assert_in_log_order(&log, "B", "C");
@@ -655,17 +754,19 @@ async fn test_env_variables_in_job() -> anyhow::Result<()> {
                 cmd: "env".into(),
                 blocking: false,
                 container: None,
+                ..Default::default()
             }],
             pipe: String::new(),
             needs: vec![],
             env: Some(HashMap::from([("CUSTOM_ENV".into(), "VALUE123".into())])),
+            ..Default::default()
         },
     )]
     .into_iter()
     .collect();
 
     let ctx = build_test_ctx("test_env_variables_in_job", jobs).await?;
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let log = fs::read_to_string(ctx.log_path())?;
     assert_in_log(&log, "VALUE123");
     ctx.logger.clean().await?;
@@ -682,10 +783,12 @@ async fn test_failing_job_stops_pipeline() -> anyhow::Result<()> {
                     cmd: "exit 1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -695,10 +798,12 @@ async fn test_failing_job_stops_pipeline() -> anyhow::Result<()> {
                     cmd: "echo should_not_run".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -706,7 +811,7 @@ async fn test_failing_job_stops_pipeline() -> anyhow::Result<()> {
     .collect();
 
     let ctx = build_test_ctx("test_failing_job_stops_pipeline", jobs).await?;
-    let result = run_pipeline(ctx.clone()).await;
+    let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
     // dbg!(&result);
     assert!(result.is_err(), "Pipeline should fail");
     let log = fs::read_to_string(ctx.log_path())?;
@@ -731,10 +836,12 @@ async fn test_mixed_parallel_and_sequential() -> anyhow::Result<()> {
                     cmd: "echo job1".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec![],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -744,10 +851,12 @@ async fn test_mixed_parallel_and_sequential() -> anyhow::Result<()> {
                     cmd: "echo job2".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -757,10 +866,12 @@ async fn test_mixed_parallel_and_sequential() -> anyhow::Result<()> {
                     cmd: "echo job3".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job1".into()],
                 env: None,
+                ..Default::default()
             },
         ),
         (
@@ -770,10 +881,12 @@ async fn test_mixed_parallel_and_sequential() -> anyhow::Result<()> {
                     cmd: "echo job4".into(),
                     blocking: false,
                     container: None,
+                    ..Default::default()
                 }],
                 pipe: String::new(),
                 needs: vec!["job2".into(), "job3".into()],
                 env: None,
+                ..Default::default()
             },
         ),
     ]
@@ -781,7 +894,7 @@ async fn test_mixed_parallel_and_sequential() -> anyhow::Result<()> {
     .collect();
 
     let ctx = build_test_ctx("test_mixed_parallel_and_sequential", jobs).await?;
-    run_pipeline(ctx.clone()).await.unwrap();
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
     let log = fs::read_to_string(ctx.log_path())?;
     assert_in_log_order(&log, "job1", "job2");
     assert_in_log_order(&log, "job1", "job3");
@@ -790,3 +903,1623 @@ async fn test_mixed_parallel_and_sequential() -> anyhow::Result<()> {
     ctx.logger.clean().await?;
     Ok(())
 }
+
+#[tokio::test]
+async fn test_job_output_split_per_run_and_job() -> anyhow::Result<()> {
+    let jobs: HashMap<String, Job> = vec![
+        (
+            "job1".into(),
+            Job {
+                steps: vec![Cmd {
+                    cmd: "echo from_job1".into(),
+                    blocking: false,
+                    container: None,
+                    ..Default::default()
+                }],
+                pipe: String::new(),
+                needs: vec![],
+                env: None,
+                ..Default::default()
+            },
+        ),
+        (
+            "job2".into(),
+            Job {
+                steps: vec![
+                    Cmd {
+                        cmd: "echo first_step".into(),
+                        blocking: false,
+                        container: None,
+                        ..Default::default()
+                    },
+                    Cmd {
+                        cmd: r#"sh -c "echo from_job2 >&2""#.into(),
+                        blocking: false,
+                        container: None,
+                        ..Default::default()
+                    },
+                ],
+                pipe: String::new(),
+                needs: vec![],
+                env: None,
+                ..Default::default()
+            },
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let ctx = build_test_ctx("test_job_output_split", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await.unwrap();
+
+    let run_id = ExecMetrics::load_history(&ctx.id)
+        .await?
+        .last()
+        .unwrap()
+        .run_id
+        .clone();
+    let job1 = fs::read_to_string(Logger::job_path(&ctx.id, &run_id, "job1"))?;
+    let job2 = fs::read_to_string(Logger::job_path(&ctx.id, &run_id, "job2"))?;
+    let project = fs::read_to_string(ctx.log_path())?;
+
+    assert_in_log(&job1, "[job1:1][out] from_job1");
+    assert!(!job1.contains("from_job2"));
+    assert_in_log(&job2, "[job2:1][out] first_step");
+    assert_in_log(&job2, "[job2:2][err] from_job2");
+    assert_in_log(&project, "[job2:2][err] from_job2");
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_pipeline() -> anyhow::Result<()> {
+    let pid_file = std::env::temp_dir().join("fleet_cancel_grandchild.pid");
+    let _ = fs::remove_file(&pid_file);
+    let jobs: HashMap<String, Job> = vec![
+        (
+            "slow".into(),
+            Job {
+                steps: vec![Cmd {
+                    cmd: format!(
+                        r#"sh -c "sleep 30 & echo $! > {}; sleep 30""#,
+                        pid_file.display()
+                    ),
+                    blocking: false,
+                    container: None,
+                    ..Default::default()
+                }],
+                pipe: String::new(),
+                needs: vec![],
+                env: None,
+                ..Default::default()
+            },
+        ),
+        (
+            "after".into(),
+            Job {
+                steps: vec![Cmd {
+                    cmd: "echo after".into(),
+                    blocking: false,
+                    container: None,
+                    ..Default::default()
+                }],
+                pipe: String::new(),
+                needs: vec!["slow".into()],
+                env: None,
+                ..Default::default()
+            },
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let ctx = build_test_ctx("test_cancel_pipeline", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    let cancel = ctx.cancel.clone();
+    let run = tokio::spawn(run_pipeline(ctx.clone(), RunTrigger::Manual));
+
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    cancel.cancel();
+    let result = tokio::time::timeout(std::time::Duration::from_secs(10), run).await??;
+    assert!(result.is_err(), "a cancelled pipeline should not succeed");
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Cancelled);
+    assert_eq!(run.jobs["slow"].status, JobStatus::Cancelled);
+    assert_eq!(run.jobs["after"].status, JobStatus::Skipped);
+
+    // the whole process group is killed, not only the direct child
+    let pid = fs::read_to_string(&pid_file)?.trim().to_string();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let alive = fs::read_to_string(format!("/proc/{pid}/stat"))
+        .map(|stat| !stat.contains(") Z"))
+        .unwrap_or(false);
+    assert!(!alive, "grandchild {pid} should have been killed");
+
+    fs::remove_file(&pid_file)?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+fn failing_dag() -> HashMap<String, Job> {
+    vec![
+        ("broken".to_string(), job("exit 1", &[])),
+        (
+            "after_broken".to_string(),
+            job("echo after_broken", &["broken"]),
+        ),
+        (
+            "after_after".to_string(),
+            job("echo after_after", &["after_broken"]),
+        ),
+        ("other".to_string(), job("sleep 0.2", &[])),
+        (
+            "after_other".to_string(),
+            job("echo after_other", &["other"]),
+        ),
+    ]
+    .into_iter()
+    .collect()
+}
+
+#[tokio::test]
+async fn test_failure_skips_transitive_dependents() -> anyhow::Result<()> {
+    let ctx = build_test_ctx("test_failure_skips_dependents", failing_dag()).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+
+    let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
+    assert!(result.is_err());
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Failed);
+    assert_eq!(run.jobs.len(), 5, "every job of the DAG is recorded");
+    assert_eq!(run.jobs["broken"].status, JobStatus::Failed);
+    assert_eq!(run.jobs["after_broken"].status, JobStatus::Skipped);
+    assert_eq!(run.jobs["after_after"].status, JobStatus::Skipped);
+    // fail fast: the running branch finishes, nothing new is started
+    assert_eq!(run.jobs["other"].status, JobStatus::Succeeded);
+    assert_eq!(run.jobs["after_other"].status, JobStatus::Skipped);
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_no_fail_fast_runs_independent_branches() -> anyhow::Result<()> {
+    let config = ProjectConfig {
+        pipeline: Pipeline {
+            jobs: failing_dag(),
+            fail_fast: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx = Arc::new(build_ctx("test_no_fail_fast", Path::new("."), config).await?);
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+
+    let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
+    assert!(result.is_err(), "the pipeline still fails");
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Failed);
+    assert_eq!(run.jobs["broken"].status, JobStatus::Failed);
+    assert_eq!(run.jobs["after_broken"].status, JobStatus::Skipped);
+    assert_eq!(run.jobs["after_after"].status, JobStatus::Skipped);
+    assert_eq!(run.jobs["other"].status, JobStatus::Succeeded);
+    assert_eq!(run.jobs["after_other"].status, JobStatus::Succeeded);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert_in_log(&log, "after_other");
+    assert!(!log.contains("[after_broken:1][out]"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cycle_through_pipe_rejected() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(
+        &config_path,
+        "branches: [main]\npipeline:\n  jobs:\n    a:\n      pipe: b\n      steps:\n        - cmd: cat\n    b:\n      needs: [a]\n      steps:\n        - cmd: echo\n",
+    )?;
+    let err = load_config(&config_path).expect_err("a cycle through pipe");
+    assert!(err.to_string().contains("Cycle detected"), "{err}");
+
+    let jobs: HashMap<String, Job> = vec![
+        (
+            "a".to_string(),
+            Job {
+                pipe: "b".into(),
+                steps: vec![Cmd {
+                    cmd: "cat".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        ),
+        (
+            "b".to_string(),
+            Job {
+                needs: vec!["a".into()],
+                steps: vec![Cmd {
+                    cmd: "echo b".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        ),
+    ]
+    .into_iter()
+    .collect();
+    let ctx = build_test_ctx("test_cycle_through_pipe", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+
+    let err = run_pipeline(ctx.clone(), RunTrigger::Manual)
+        .await
+        .expect_err("the run never succeeds");
+    assert!(err.to_string().contains("Cycle detected"), "{err}");
+    let log = fs::read_to_string(ctx.log_path()).unwrap_or_default();
+    assert!(!log.contains("[b:1][out]"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_if_condition_on_branch() -> anyhow::Result<()> {
+    let jobs: HashMap<String, Job> = vec![
+        ("build".to_string(), job("echo build", &[])),
+        (
+            "deploy".to_string(),
+            Job {
+                condition: Some("branch == 'main'".into()),
+                ..job("echo deploy", &["build"])
+            },
+        ),
+        (
+            "release".to_string(),
+            Job {
+                condition: Some("startsWith(branch, 'release/')".into()),
+                ..job("echo release", &["build"])
+            },
+        ),
+        ("announce".to_string(), job("echo announce", &["release"])),
+    ]
+    .into_iter()
+    .collect();
+
+    let ctx = build_test_ctx("test_if_condition_on_branch", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Succeeded);
+    assert_eq!(run.jobs["deploy"].status, JobStatus::Succeeded);
+    assert_eq!(run.jobs["release"].status, JobStatus::Skipped);
+    assert_eq!(run.jobs["announce"].status, JobStatus::Skipped);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert_in_log(
+        &log,
+        "Job release skipped (if: startsWith(branch, 'release/'))",
+    );
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_if_failure_and_always_run_after_failure() -> anyhow::Result<()> {
+    let jobs: HashMap<String, Job> = vec![
+        ("broken".to_string(), job("exit 1", &[])),
+        (
+            "report".to_string(),
+            Job {
+                condition: Some("failure()".into()),
+                ..job("echo report", &["broken"])
+            },
+        ),
+        (
+            "cleanup".to_string(),
+            Job {
+                condition: Some("always()".into()),
+                ..job("echo cleanup", &["broken"])
+            },
+        ),
+        (
+            "publish".to_string(),
+            Job {
+                condition: Some("branch == 'main'".into()),
+                ..job("echo publish", &["broken"])
+            },
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let ctx = build_test_ctx("test_if_failure_and_always", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
+    assert!(result.is_err());
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Failed);
+    assert_eq!(run.jobs["report"].status, JobStatus::Succeeded);
+    assert_eq!(run.jobs["cleanup"].status, JobStatus::Succeeded);
+    // without a status function the condition only applies after a success
+    assert_eq!(run.jobs["publish"].status, JobStatus::Skipped);
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_if_condition_rejected() -> anyhow::Result<()> {
+    for condition in [
+        "branch = 'main'",
+        "deployed()",
+        "(branch == 'main'",
+        "tag == 'v1'",
+    ] {
+        let jobs: HashMap<String, Job> = HashMap::from([(
+            "job".to_string(),
+            Job {
+                condition: Some(condition.into()),
+                ..job("echo job", &[])
+            },
+        )]);
+        let ctx = build_test_ctx("test_invalid_if_condition", jobs).await?;
+        let result = run_pipeline(ctx.clone(), RunTrigger::Manual).await;
+        let err = result.expect_err(condition).to_string();
+        assert!(err.contains("invalid if"), "{condition}: {err}");
+        Logger::rm_logs_by_id(&ctx.id)?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_jobs_pruned_by_branch() -> anyhow::Result<()> {
+    let on_branches = |cmd: &str, branches: &[&str], ignore: &[&str]| Job {
+        branches: branches.iter().map(|b| b.to_string()).collect(),
+        ignore_branches: ignore.iter().map(|b| b.to_string()).collect(),
+        ..job(cmd, &[])
+    };
+    let jobs: HashMap<String, Job> = vec![
+        ("test".to_string(), on_branches("echo test", &[], &[])),
+        (
+            "deploy".to_string(),
+            on_branches("echo deploy", &["main"], &[]),
+        ),
+        (
+            "preview".to_string(),
+            on_branches("echo preview", &["feature/*"], &[]),
+        ),
+        (
+            "lint".to_string(),
+            on_branches("echo lint", &["*"], &["origin/main"]),
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let ctx = build_test_ctx("test_jobs_pruned_by_branch", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    let mut ran: Vec<&str> = run.jobs.keys().map(String::as_str).collect();
+    ran.sort();
+    assert_eq!(ran, ["deploy", "test"]);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert_in_log(&log, "2 job(s) not targeting branch main: lint, preview");
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_needs_on_pruned_job_rejected() -> anyhow::Result<()> {
+    let jobs: HashMap<String, Job> = vec![
+        (
+            "package".to_string(),
+            Job {
+                branches: vec!["release/*".into()],
+                ..job("echo package", &[])
+            },
+        ),
+        ("deploy".to_string(), job("echo deploy", &["package"])),
+    ]
+    .into_iter()
+    .collect();
+
+    let ctx = build_test_ctx("test_needs_on_pruned_job", jobs).await?;
+    let err = run_pipeline(ctx.clone(), RunTrigger::Manual)
+        .await
+        .expect_err("deploy cannot run without package");
+    assert!(
+        err.to_string()
+            .contains("Job 'deploy' depends on 'package' which does not run on branch 'main'"),
+        "{err}"
+    );
+
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+/// commits `files` in a new repository, returns the hash of the commit
+fn commit_files(repo: &git2::Repository, files: &[&str]) -> anyhow::Result<String> {
+    let workdir = repo.workdir().unwrap().to_path_buf();
+    let mut index = repo.index()?;
+    for file in files {
+        let path = workdir.join(file);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, file)?;
+        index.add_path(Path::new(file))?;
+    }
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let sig = git2::Signature::now("fleet", "fleet@test")?;
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    Ok(repo
+        .commit(Some("HEAD"), &sig, &sig, "commit", &tree, &parents)?
+        .to_string())
+}
+
+/// repository of a first commit, then of one with `changes`, with the hash of the first one
+fn repo_with_changes(changes: &[&str]) -> anyhow::Result<(tempfile::TempDir, String)> {
+    let dir = tempfile::tempdir()?;
+    let repo = git2::Repository::init(dir.path())?;
+    let first = commit_files(&repo, &["README.md"])?;
+    commit_files(&repo, changes)?;
+    Ok((dir, first))
+}
+
+#[tokio::test]
+async fn test_job_paths_filters() -> anyhow::Result<()> {
+    let on_paths = |cmd: &str, needs: &[&str], paths: &[&str]| Job {
+        paths: paths.iter().map(|p| p.to_string()).collect(),
+        ..job(cmd, needs)
+    };
+    let jobs: HashMap<String, Job> = vec![
+        ("web".to_string(), on_paths("echo web", &[], &["web/**"])),
+        ("api".to_string(), on_paths("echo api", &[], &["api/**"])),
+        (
+            "deploy".to_string(),
+            on_paths("echo deploy", &["web", "api"], &[]),
+        ),
+    ]
+    .into_iter()
+    .collect();
+    let pipeline = Pipeline {
+        jobs,
+        ..Default::default()
+    };
+
+    let (dir, first) = repo_with_changes(&["api/src/main.rs"])?;
+    let config = ProjectConfig {
+        pipeline,
+        ..Default::default()
+    };
+    let mut ctx = build_ctx("test_job_paths_filters", dir.path(), config).await?;
+    // the last build is the first commit
+    ctx.last_built.insert("main".to_string(), first);
+    let ctx = Arc::new(ctx);
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    let status = run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+    assert_eq!(status, RunStatus::Succeeded);
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.jobs["web"].status, JobStatus::Skipped);
+    assert_eq!(run.jobs["api"].status, JobStatus::Succeeded);
+    // needs on a job without change are dropped
+    assert_eq!(run.jobs["deploy"].status, JobStatus::Succeeded);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert_in_log(&log, "1 job(s) without matching change: web");
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pipeline_paths_ignore_skips_run() -> anyhow::Result<()> {
+    let pipeline = Pipeline {
+        jobs: HashMap::from([("build".to_string(), job("echo build", &[]))]),
+        paths_ignore: vec!["docs/**".into(), "*.md".into()],
+        ..Default::default()
+    };
+
+    let (dir, first) = repo_with_changes(&["docs/guide.txt", "CHANGELOG.md"])?;
+    let config = ProjectConfig {
+        pipeline,
+        ..Default::default()
+    };
+    let mut ctx = build_ctx("test_pipeline_paths_ignore", dir.path(), config).await?;
+    ctx.last_built.insert("main".to_string(), first);
+    let ctx = Arc::new(ctx);
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    let status = run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+    assert_eq!(status, RunStatus::Skipped);
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Skipped);
+    assert!(run.jobs.is_empty());
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert_in_log(&log, "No change matching the paths of the pipeline");
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+/// command failing until its `succeed_on`th run, counted in `counter`
+fn flaky_cmd(counter: &Path, succeed_on: u32) -> String {
+    format!(
+        "sh -c 'n=$(cat {0} 2>/dev/null || echo 0); n=$((n+1)); echo $n > {0}; [ $n -ge {succeed_on} ]'",
+        counter.display()
+    )
+}
+
+#[tokio::test]
+async fn test_step_retry_until_success() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let retry = Retry {
+        attempts: 3,
+        delay: 0,
+        backoff: Backoff::Fixed,
+    };
+    let jobs = HashMap::from([(
+        "fetch".to_string(),
+        Job {
+            steps: vec![Cmd {
+                retry: Some(retry),
+                ..step(&flaky_cmd(&dir.path().join("count"), 3))
+            }],
+            ..Default::default()
+        },
+    )]);
+
+    let ctx = build_test_ctx("test_step_retry_until_success", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    let job = &run.jobs["fetch"];
+    assert_eq!(job.status, JobStatus::Succeeded);
+    assert_eq!(
+        job.attempts,
+        vec![
+            StepAttempt {
+                step: 1,
+                attempt: 1,
+                exit_code: Some(1)
+            },
+            StepAttempt {
+                step: 1,
+                attempt: 2,
+                exit_code: Some(1)
+            },
+            StepAttempt {
+                step: 1,
+                attempt: 3,
+                exit_code: Some(0)
+            },
+        ]
+    );
+    assert_eq!(job.retries(), 2);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert_in_log(&log, "Step 1 failed (attempt 1/3), retrying in 0s");
+    assert_in_log(&log, "Step 1 succeeded on attempt 3/3");
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_job_retry_exhausted() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    // the policy of the job applies to steps without their own
+    let job = Job {
+        retry: Some(Retry {
+            attempts: 2,
+            delay: 0,
+            backoff: Backoff::Exponential,
+        }),
+        ..job(&flaky_cmd(&dir.path().join("count"), 10), &[])
+    };
+    let jobs = HashMap::from([("fetch".to_string(), job)]);
+
+    let ctx = build_test_ctx("test_job_retry_exhausted", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    assert!(run_pipeline(ctx.clone(), RunTrigger::Manual).await.is_err());
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    let job = &run.jobs["fetch"];
+    assert_eq!(job.status, JobStatus::Failed);
+    assert_eq!(job.attempts.len(), 2);
+    assert!(job.attempts.iter().all(|a| a.exit_code == Some(1)));
+    assert_eq!(fs::read_to_string(dir.path().join("count"))?.trim(), "2");
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[test]
+fn test_retry_backoff_delays() {
+    let retry = |backoff| Retry {
+        attempts: 4,
+        delay: 2,
+        backoff,
+    };
+    let secs = |r: &Retry| {
+        (1..=3)
+            .map(|a| r.delay_after(a).as_secs())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(secs(&retry(Backoff::Fixed)), [2, 2, 2]);
+    assert_eq!(secs(&retry(Backoff::Linear)), [2, 4, 6]);
+    assert_eq!(secs(&retry(Backoff::Exponential)), [2, 4, 8]);
+}
+
+#[tokio::test]
+async fn test_step_timeout_overrides_global() -> anyhow::Result<()> {
+    let jobs = HashMap::from([(
+        "slow".to_string(),
+        Job {
+            steps: vec![Cmd {
+                timeout: Some(1),
+                ..step("sleep 5")
+            }],
+            ..Default::default()
+        },
+    )]);
+    let ctx = build_test_ctx("test_step_timeout_overrides_global", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+
+    let started = std::time::Instant::now();
+    assert!(run_pipeline(ctx.clone(), RunTrigger::Manual).await.is_err());
+    assert!(started.elapsed().as_secs() < 4);
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    let job = &run.jobs["slow"];
+    assert_eq!(job.status, JobStatus::Failed);
+    assert_eq!(job.reason, Some(FailureReason::Timeout));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_job_timeout_spans_steps() -> anyhow::Result<()> {
+    let jobs = HashMap::from([
+        (
+            "slow".to_string(),
+            Job {
+                // each step fits in the default timeout, not the three of them in the job one
+                steps: vec![step("sleep 1"), step("sleep 1"), step("sleep 1")],
+                timeout: Some(2),
+                ..Default::default()
+            },
+        ),
+        (
+            "broken".to_string(),
+            Job {
+                steps: vec![Cmd {
+                    cmd: "false".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let ctx = build_test_ctx("test_job_timeout_spans_steps", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    assert!(run_pipeline(ctx.clone(), RunTrigger::Manual).await.is_err());
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.jobs["slow"].reason, Some(FailureReason::Timeout));
+    assert_eq!(run.jobs["broken"].reason, Some(FailureReason::Error));
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("Job slow timed out (job timeout: 2s)"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pipeline_deadline_stops_run() -> anyhow::Result<()> {
+    let jobs = HashMap::from([
+        (
+            "slow".to_string(),
+            Job {
+                steps: vec![step("sleep 10")],
+                ..Default::default()
+            },
+        ),
+        (
+            "after".to_string(),
+            Job {
+                needs: vec!["slow".into()],
+                steps: vec![Cmd {
+                    cmd: "echo after".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let config = ProjectConfig {
+        pipeline: Pipeline {
+            jobs,
+            deadline: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx =
+        Arc::new(build_ctx("test_pipeline_deadline_stops_run", Path::new("."), config).await?);
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+
+    let started = std::time::Instant::now();
+    assert!(run_pipeline(ctx.clone(), RunTrigger::Manual).await.is_err());
+    assert!(started.elapsed().as_secs() < 5);
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Failed);
+    assert_eq!(run.jobs["slow"].reason, Some(FailureReason::Timeout));
+    assert_eq!(run.jobs["after"].status, JobStatus::Skipped);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("Job slow timed out (pipeline timeout: 1s)"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_step_allow_failure_and_success_codes() -> anyhow::Result<()> {
+    let jobs = HashMap::from([
+        (
+            "lint".to_string(),
+            Job {
+                steps: vec![
+                    Cmd {
+                        allow_failure: true,
+                        ..step("sh -c 'exit 3'")
+                    },
+                    step("sh -c 'echo linted'"),
+                ],
+                ..Default::default()
+            },
+        ),
+        (
+            "diff".to_string(),
+            Job {
+                steps: vec![Cmd {
+                    success_codes: vec![0, 1],
+                    ..step("sh -c 'exit 1'")
+                }],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let ctx = build_test_ctx("test_step_allow_failure_and_success_codes", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Succeeded);
+    assert_eq!(run.jobs["lint"].status, JobStatus::SucceededWithWarnings);
+    assert_eq!(run.jobs["lint"].warnings.len(), 1);
+    assert_eq!(run.jobs["diff"].status, JobStatus::Succeeded);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("linted"));
+    assert!(log.contains("Step 1 failed, continuing (allow_failure)"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_container_step_exit_code() -> anyhow::Result<()> {
+    // needs a docker daemon, like the container steps themselves
+    let Ok(docker) = bollard::Docker::connect_with_local_defaults() else {
+        return Ok(());
+    };
+    if docker.ping().await.is_err() {
+        return Ok(());
+    }
+    let dir = tempfile::tempdir()?;
+    let container_step = |cmd: &str, success_codes: Vec<i32>| Cmd {
+        container: Some("alpine".into()),
+        success_codes,
+        ..step(cmd)
+    };
+    let jobs = HashMap::from([
+        (
+            "fails".to_string(),
+            Job {
+                steps: vec![
+                    container_step("sh -c 'exit 3'", vec![]),
+                    step("sh -c 'echo unreachable'"),
+                ],
+                ..Default::default()
+            },
+        ),
+        (
+            "accepted".to_string(),
+            Job {
+                steps: vec![container_step("sh -c 'exit 3'", vec![0, 3])],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let mut config = config(jobs);
+    config.pipeline.fail_fast = false;
+    let ctx = Arc::new(build_ctx("test_container_step_exit_code", dir.path(), config).await?);
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Failed);
+    assert_eq!(run.jobs["fails"].status, JobStatus::Failed);
+    assert_eq!(run.jobs["accepted"].status, JobStatus::Succeeded);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("Container command failed with exit code Some(3)"));
+    assert!(!log.contains("unreachable"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_job_allow_failure_keeps_dependents() -> anyhow::Result<()> {
+    let jobs = HashMap::from([
+        (
+            "audit".to_string(),
+            Job {
+                allow_failure: true,
+                steps: vec![step("sh -c 'exit 1'"), step("sh -c 'echo unreachable'")],
+                ..Default::default()
+            },
+        ),
+        (
+            "deploy".to_string(),
+            Job {
+                needs: vec!["audit".into()],
+                steps: vec![step("sh -c 'echo deployed'")],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let ctx = build_test_ctx("test_job_allow_failure_keeps_dependents", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.status, RunStatus::Succeeded);
+    assert_eq!(run.jobs["audit"].status, JobStatus::SucceededWithWarnings);
+    assert_eq!(run.jobs["deploy"].status, JobStatus::Succeeded);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(!log.contains("unreachable"));
+    assert!(log.contains("deployed"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_scripts_through_shell() -> anyhow::Result<()> {
+    let jobs = HashMap::from([
+        (
+            "script".to_string(),
+            Job {
+                steps: vec![
+                    Cmd {
+                        shell: Some("sh".into()),
+                        ..script("NAME=fleet\necho \"hello $NAME\" | tr a-z A-Z")
+                    },
+                    script("print(\"answer\", 6 * 7)"),
+                ],
+                ..Default::default()
+            },
+        ),
+        (
+            "strict".to_string(),
+            Job {
+                shell: Some("bash -euo pipefail".into()),
+                steps: vec![script("false | true\necho after-pipe")],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let config = ProjectConfig {
+        pipeline: Pipeline {
+            jobs,
+            shell: Some("python3".into()),
+            fail_fast: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ctx = Arc::new(build_ctx("test_run_scripts_through_shell", Path::new("."), config).await?);
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    assert!(run_pipeline(ctx.clone(), RunTrigger::Manual).await.is_err());
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.jobs["script"].status, JobStatus::Succeeded);
+    assert_eq!(run.jobs["strict"].status, JobStatus::Failed);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("HELLO FLEET"));
+    assert!(log.contains("answer 42"));
+    assert!(!log.contains("after-pipe"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_step_needs_cmd_or_run() -> anyhow::Result<()> {
+    for (step, expected) in [
+        (
+            Cmd {
+                cmd: "echo cmd".into(),
+                ..script("echo run")
+            },
+            "both cmd and run",
+        ),
+        (Cmd::default(), "neither cmd nor run"),
+        (
+            Cmd {
+                shell: Some("".into()),
+                ..script("echo run")
+            },
+            "empty shell",
+        ),
+    ] {
+        let jobs = HashMap::from([(
+            "job".to_string(),
+            Job {
+                steps: vec![step],
+                ..Default::default()
+            },
+        )]);
+        let ctx = build_test_ctx("test_step_needs_cmd_or_run", jobs).await?;
+        let err = run_pipeline(ctx.clone(), RunTrigger::Manual)
+            .await
+            .expect_err(expected)
+            .to_string();
+        assert!(err.contains(expected), "{err}");
+        Logger::rm_logs_by_id(&ctx.id)?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_step_working_directory_and_env() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::create_dir_all(dir.path().join("services/api"))?;
+    let env = |pairs: &[(&str, &str)]| {
+        Some(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    };
+    let jobs = HashMap::from([(
+        "api".to_string(),
+        Job {
+            working_directory: Some("services/api".into()),
+            env: env(&[("A", "job"), ("B", "job")]),
+            steps: vec![
+                script("echo \"in $(basename \"$PWD\")\""),
+                Cmd {
+                    working_directory: Some("./services/../services".into()),
+                    env: env(&[("B", "step")]),
+                    ..script("echo \"in $(basename \"$PWD\") $A-$B\"")
+                },
+            ],
+            ..Default::default()
+        },
+    )]);
+    let ctx = Arc::new(
+        build_ctx(
+            "test_step_working_directory_and_env",
+            dir.path(),
+            config(jobs),
+        )
+        .await?,
+    );
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("in api"));
+    assert!(log.contains("in services job-step"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_working_directory_outside_repo_rejected() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let outside = tempfile::tempdir()?;
+    std::os::unix::fs::symlink(outside.path(), dir.path().join("link"))?;
+    let in_dir = |working_directory: &str| Job {
+        working_directory: Some(working_directory.into()),
+        steps: vec![script("pwd")],
+        ..Default::default()
+    };
+
+    for escaping in ["../elsewhere", "/tmp", "services/../../elsewhere"] {
+        let jobs = HashMap::from([("job".to_string(), in_dir(escaping))]);
+        let ctx = Arc::new(
+            build_ctx(
+                "test_working_directory_outside_repo",
+                dir.path(),
+                config(jobs),
+            )
+            .await?,
+        );
+        let err = run_pipeline(ctx.clone(), RunTrigger::Manual)
+            .await
+            .expect_err(escaping)
+            .to_string();
+        assert!(
+            err.contains("invalid working_directory"),
+            "{escaping}: {err}"
+        );
+        Logger::rm_logs_by_id(&ctx.id)?;
+    }
+
+    // only seen once the symlink is resolved, the job fails
+    let jobs = HashMap::from([("job".to_string(), in_dir("link"))]);
+    let ctx = Arc::new(
+        build_ctx(
+            "test_working_directory_outside_repo",
+            dir.path(),
+            config(jobs),
+        )
+        .await?,
+    );
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    assert!(run_pipeline(ctx.clone(), RunTrigger::Manual).await.is_err());
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.jobs["job"].status, JobStatus::Failed);
+    assert!(
+        run.jobs["job"]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("not a directory of the repository")
+    );
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_job_outputs_in_env_and_if() -> anyhow::Result<()> {
+    let env = |key: &str, value: &str| Some(HashMap::from([(key.to_string(), value.to_string())]));
+    let jobs = HashMap::from([
+        (
+            "build".to_string(),
+            Job {
+                steps: vec![
+                    script("echo version=1.2.3 >> \"$FLEET_OUTPUT\""),
+                    script("echo \"tag=v$((1 + 1))\" >> \"$FLEET_OUTPUT\""),
+                ],
+                ..Default::default()
+            },
+        ),
+        (
+            "deploy".to_string(),
+            Job {
+                needs: vec!["build".into()],
+                condition: Some("jobs.build.outputs.tag == 'v2'".into()),
+                env: env("VERSION", "${{ jobs.build.outputs.version }}"),
+                steps: vec![Cmd {
+                    env: env("IMAGE", "app:${{jobs.build.outputs.tag}}"),
+                    ..script("echo \"deploying $VERSION as $IMAGE\"")
+                }],
+                ..Default::default()
+            },
+        ),
+        (
+            "rollback".to_string(),
+            Job {
+                needs: vec!["deploy".into()],
+                condition: Some("${{ jobs.build.outputs.tag == 'v3' }}".into()),
+                steps: vec![script("echo rolling back")],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let ctx = build_test_ctx("test_job_outputs_in_env_and_if", jobs).await?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.jobs["build"].outputs["version"], "1.2.3");
+    assert_eq!(run.jobs["build"].outputs["tag"], "v2");
+    assert_eq!(run.jobs["deploy"].status, JobStatus::Succeeded);
+    assert_eq!(run.jobs["rollback"].status, JobStatus::Skipped);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("deploying 1.2.3 as app:v2"));
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_outputs_of_unrelated_job_rejected() -> anyhow::Result<()> {
+    for (value, expected) in [
+        (
+            "${{ jobs.other.outputs.version }}",
+            "uses the outputs of 'other' which it does not need",
+        ),
+        ("${{ secrets.token }}", "invalid env"),
+        ("${{ jobs.other.outputs.version", "invalid env"),
+    ] {
+        let jobs = HashMap::from([
+            (
+                "other".to_string(),
+                Job {
+                    steps: vec![script("true")],
+                    ..Default::default()
+                },
+            ),
+            (
+                "job".to_string(),
+                Job {
+                    env: Some(HashMap::from([("VERSION".to_string(), value.to_string())])),
+                    steps: vec![script("true")],
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let ctx = build_test_ctx("test_outputs_of_unrelated_job", jobs).await?;
+        let err = run_pipeline(ctx.clone(), RunTrigger::Manual)
+            .await
+            .expect_err(value)
+            .to_string();
+        assert!(err.contains(expected), "{value}: {err}");
+        Logger::rm_logs_by_id(&ctx.id)?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_artifacts_restored_for_dependents() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let jobs = HashMap::from([
+        (
+            "build".to_string(),
+            Job {
+                artifacts: Some(Artifacts {
+                    paths: vec!["dist".into(), "*.log".into()],
+                }),
+                steps: vec![script(
+                    "mkdir -p dist && echo payload-bin > dist/app && ln -s app dist/run && echo payload-log > build.log",
+                )],
+                ..Default::default()
+            },
+        ),
+        (
+            "clean".to_string(),
+            Job {
+                needs: vec!["build".into()],
+                steps: vec![script("rm -rf dist build.log")],
+                ..Default::default()
+            },
+        ),
+        (
+            "deploy".to_string(),
+            Job {
+                needs: vec!["clean".into()],
+                download_artifacts: vec!["build".into()],
+                steps: vec![script(
+                    "cat dist/app build.log && echo \"link-$(readlink dist/run)\"",
+                )],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let ctx = Arc::new(
+        build_ctx(
+            "test_artifacts_restored_for_dependents",
+            dir.path(),
+            config(jobs),
+        )
+        .await?,
+    );
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    artifacts::rm_artifacts_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let run = ExecMetrics::load_history(&ctx.id).await?.pop().unwrap();
+    assert_eq!(run.jobs["deploy"].status, JobStatus::Succeeded);
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("payload-bin"));
+    assert!(log.contains("payload-log"));
+    assert!(log.contains("link-app"), "symlinks are kept as symlinks");
+
+    let kept = artifacts::list(&ctx.id, &run.run_id)?;
+    let paths: Vec<&str> = kept.iter().map(|a| a.path.as_str()).collect();
+    assert_eq!(paths, vec!["build.log", "dist/app", "dist/run"]);
+    assert!(kept.iter().all(|a| a.job == "build"));
+
+    let retention = HistoryRetention {
+        keep_runs: 0,
+        keep_days: None,
+    };
+    ExecMetrics::apply_retention(&ctx.id, &retention).await?;
+    assert!(artifacts::list(&ctx.id, &run.run_id)?.is_empty());
+    assert!(!artifacts::run_dir(&ctx.id, &run.run_id).exists());
+
+    artifacts::rm_artifacts_by_id(&ctx.id)?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_download_artifacts_of_unrelated_job_rejected() -> anyhow::Result<()> {
+    for (needs, artifacts, expected) in [
+        (
+            vec![],
+            Some(Artifacts {
+                paths: vec!["dist".into()],
+            }),
+            "downloads the artifacts of 'build' which it does not need",
+        ),
+        (
+            vec!["build".to_string()],
+            None,
+            "downloads the artifacts of 'build' which keeps none",
+        ),
+    ] {
+        let jobs = HashMap::from([
+            (
+                "build".to_string(),
+                Job {
+                    artifacts,
+                    steps: vec![script("true")],
+                    ..Default::default()
+                },
+            ),
+            (
+                "deploy".to_string(),
+                Job {
+                    needs,
+                    download_artifacts: vec!["build".into()],
+                    steps: vec![script("true")],
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let ctx = build_test_ctx("test_download_artifacts_of_unrelated_job", jobs).await?;
+        let err = run_pipeline(ctx.clone(), RunTrigger::Manual)
+            .await
+            .expect_err(expected)
+            .to_string();
+        assert!(err.contains(expected), "{err}");
+        Logger::rm_logs_by_id(&ctx.id)?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cache_keyed_by_file_hashes() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("Cargo.lock"), "v1")?;
+    let jobs = HashMap::from([(
+        "build".to_string(),
+        Job {
+            cache: Some(Cache {
+                key: "deps-${{ hashFiles('Cargo.lock') }}".into(),
+                paths: vec!["target".into()],
+                restore_keys: vec!["deps-".into()],
+            }),
+            steps: vec![script(
+                "echo \"link-$(readlink target/bin 2>/dev/null)\"; cat target/marker 2>/dev/null || echo fresh; mkdir -p target && echo \"cached-$(cat Cargo.lock)\" > target/marker && ln -sfn marker target/bin",
+            )],
+            ..Default::default()
+        },
+    )]);
+    let ctx =
+        Arc::new(build_ctx("test_cache_keyed_by_file_hashes", dir.path(), config(jobs)).await?);
+    cache::rm_cache_by_id(&ctx.id)?;
+    let run = || async {
+        fs::remove_dir_all(dir.path().join("target")).ok();
+        let before = fs::read_to_string(ctx.log_path()).unwrap_or_default().len();
+        run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+        anyhow::Ok(fs::read_to_string(ctx.log_path())?[before..].to_string())
+    };
+
+    let first = run().await?;
+    assert!(first.contains("fresh"));
+    assert!(first.contains("Cache miss for key deps-"));
+    let second = run().await?;
+    assert!(second.contains("cached-v1"));
+    assert!(
+        second.contains("link-marker"),
+        "symlinks are restored as symlinks"
+    );
+    assert!(second.contains("Cache hit for key deps-"));
+
+    fs::write(dir.path().join("Cargo.lock"), "v2")?;
+    let third = run().await?;
+    assert!(third.contains("cached-v1"));
+    assert!(third.contains("restored from key deps-"));
+    let stored = fs::read_dir(cache::cache_dir_by_id(&ctx.id))?.count();
+    assert_eq!(stored, 2);
+
+    cache::rm_cache_by_id(&ctx.id)?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cache_not_saved_after_allowed_failure() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let jobs = HashMap::from([(
+        "build".to_string(),
+        Job {
+            allow_failure: true,
+            cache: Some(Cache {
+                key: "partial".into(),
+                paths: vec!["target".into()],
+                restore_keys: vec![],
+            }),
+            steps: vec![script(
+                "mkdir -p target && echo half > target/marker && exit 1",
+            )],
+            ..Default::default()
+        },
+    )]);
+    let ctx = Arc::new(
+        build_ctx(
+            "test_cache_not_saved_after_allowed_failure",
+            dir.path(),
+            config(jobs),
+        )
+        .await?,
+    );
+    cache::rm_cache_by_id(&ctx.id)?;
+
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+    let logs = fs::read_to_string(ctx.log_path())?;
+    assert!(logs.contains("Cache not saved"), "{logs}");
+    assert!(!cache::cache_dir_by_id(&ctx.id).join("partial").exists());
+
+    cache::rm_cache_by_id(&ctx.id)?;
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[test]
+fn test_hash_files_with_symlinks() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::create_dir_all(dir.path().join("node_modules/.pnpm/pkg"))?;
+    fs::write(dir.path().join("node_modules/.pnpm/pkg/index.js"), "v1")?;
+    std::os::unix::fs::symlink(".pnpm/pkg", dir.path().join("node_modules/pkg"))?;
+    std::os::unix::fs::symlink("missing", dir.path().join("node_modules/dangling"))?;
+    let patterns = vec!["node_modules/**".to_string()];
+
+    let hash = cache::hash_files(dir.path(), &patterns)?;
+    assert!(!hash.is_empty());
+    fs::remove_file(dir.path().join("node_modules/dangling"))?;
+    std::os::unix::fs::symlink("elsewhere", dir.path().join("node_modules/dangling"))?;
+    assert_ne!(cache::hash_files(dir.path(), &patterns)?, hash);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cache_evicts_least_recently_used() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let now = chrono::Utc::now();
+    for (project, key, size, age) in [
+        ("a", "old", 40, 3),
+        ("b", "recent", 40, 1),
+        ("a", "used", 40, 0),
+    ] {
+        let dir = root.path().join(project).join(key);
+        fs::create_dir_all(&dir)?;
+        let entry = CacheEntry {
+            key: key.to_string(),
+            size,
+            created_at: now - chrono::Duration::hours(5),
+            last_used: now - chrono::Duration::hours(age),
+        };
+        fs::write(dir.join("entry.json"), serde_json::to_string(&entry)?)?;
+    }
+
+    assert!(cache::evict_from(root.path(), 120)?.is_empty());
+    assert_eq!(cache::evict_from(root.path(), 100)?, vec!["a/old"]);
+    assert_eq!(cache::evict_from(root.path(), 40)?, vec!["b/recent"]);
+    assert!(root.path().join("a/used").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cache_config_rejected() -> anyhow::Result<()> {
+    for (cache, env, expected) in [
+        (
+            Cache {
+                key: "deps-${{ hashFiles(Cargo.lock) }}".into(),
+                paths: vec!["target".into()],
+                ..Default::default()
+            },
+            None,
+            "invalid cache key",
+        ),
+        (
+            Cache {
+                key: "deps".into(),
+                paths: vec!["/usr/local/cargo".into()],
+                ..Default::default()
+            },
+            None,
+            "none of its steps runs in a container",
+        ),
+        (
+            Cache {
+                key: "deps".into(),
+                paths: vec!["../target".into()],
+                ..Default::default()
+            },
+            None,
+            "invalid cache path",
+        ),
+        (
+            Cache {
+                key: "deps".into(),
+                paths: vec!["target".into()],
+                ..Default::default()
+            },
+            Some("${{ hashFiles('Cargo.lock') }}"),
+            "only available in cache keys",
+        ),
+    ] {
+        let jobs = HashMap::from([(
+            "build".to_string(),
+            Job {
+                cache: Some(cache),
+                env: env.map(|v| HashMap::from([("HASH".to_string(), v.to_string())])),
+                steps: vec![script("true")],
+                ..Default::default()
+            },
+        )]);
+        let ctx = build_test_ctx("test_cache_config_rejected", jobs).await?;
+        let err = run_pipeline(ctx.clone(), RunTrigger::Manual)
+            .await
+            .expect_err(expected)
+            .to_string();
+        assert!(err.contains(expected), "{err}");
+        Logger::rm_logs_by_id(&ctx.id)?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_matrix_expands_jobs() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    fs::write(
+        &config_path,
+        r#"
+branches: [main]
+pipeline:
+  jobs:
+    test:
+      matrix:
+        toolchain: [stable, nightly]
+        features: [default, no-tty]
+        exclude:
+          - toolchain: nightly
+            features: no-tty
+        include:
+          - toolchain: stable
+            features: no-tty
+            flags: --release
+          - toolchain: "1.80"
+            features: default
+      steps:
+        - run: echo "testing $MATRIX_TOOLCHAIN ${{ matrix.features }} ${{ matrix.flags }}"
+    report:
+      needs: [test]
+      steps:
+        - run: echo report
+"#,
+    )?;
+    let config = load_config(&config_path)?;
+    let mut names: Vec<&String> = config.pipeline.jobs.keys().collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "report",
+            "test (1.80, default)",
+            "test (nightly, default)",
+            "test (stable, default)",
+            "test (stable, no-tty, --release)",
+        ]
+    );
+    let mut needs: Vec<&String> = config.pipeline.jobs["report"].needs.iter().collect();
+    needs.sort();
+    assert_eq!(needs, names[1..]);
+
+    let ctx = Arc::new(build_ctx("test_matrix_expands_jobs", dir.path(), config).await?);
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    run_pipeline(ctx.clone(), RunTrigger::Manual).await?;
+
+    let log = fs::read_to_string(ctx.log_path())?;
+    assert!(log.contains("testing stable no-tty --release"));
+    assert!(log.contains("testing nightly default"));
+    assert!(!log.contains("testing nightly no-tty"));
+    assert_in_log_order(&log, "testing 1.80 default", "report");
+
+    ExecMetrics::rm_metrics_by_id(&ctx.id)?;
+    Logger::rm_logs_by_id(&ctx.id)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_matrix_rejected() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_path = dir.path().join("fleet.yml");
+    for (matrix, expected) in [
+        ("toolchain: stable", "'toolchain' is not a list of values"),
+        (
+            "toolchain: [stable]\n        exclude: [{ toolchain: stable }]",
+            "matrix without any combination",
+        ),
+        ("toolchain: [stable, stable]", "is defined twice"),
+    ] {
+        fs::write(
+            &config_path,
+            format!(
+                "branches: [main]\npipeline:\n  jobs:\n    test:\n      matrix:\n        {matrix}\n      steps:\n        - run: echo ${{{{ matrix.toolchain }}}}\n"
+            ),
+        )?;
+        let err = load_config(&config_path).expect_err(expected).to_string();
+        assert!(err.contains(expected), "{err}");
+    }
+    fs::write(
+        &config_path,
+        "branches: [main]\npipeline:\n  jobs:\n    test:\n      matrix:\n        toolchain: [stable]\n      steps:\n        - run: echo ${{ matrix.feature }}\n",
+    )?;
+    let err = load_config(&config_path).unwrap_err().to_string();
+    assert!(
+        err.contains("`matrix.feature` is not a value of the matrix"),
+        "{err}"
+    );
+    Ok(())
+}